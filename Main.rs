@@ -1,13 +1,15 @@
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use tch::{nn, Device, Tensor, Kind};
 use redis::{Commands, Client};
 use kafka::producer::{BaseProducer, DeliveryResult, Producer, ProducerConfig};
+use mesh_sec_ai_boot::config::Loader;
+use mesh_sec_ai_boot::schema::BootConfig;
 
 // Configuration Management
 #[derive(Deserialize, Serialize)]
@@ -128,6 +130,24 @@ mod bootstrap {
         config
     }
 
+    /// The node's boot config, layered as the `mesh_sec_ai_boot` CLI does (system and
+    /// user files, `MESH_BOOT__*`) plus the file named by `AI_BOOT_CONFIG`. The filesystem
+    /// sandbox is widened to read `data_path` and write the log directory, which is
+    /// created first as Landlock only covers existing paths.
+    pub fn load_node_config(config: &Config) -> Result<BootConfig, Box<dyn std::error::Error>> {
+        let mut node = Loader::new()
+            .user_file(env::var_os("AI_BOOT_CONFIG").map(PathBuf::from))
+            .resolve()?
+            .config;
+        let log_dir = Path::new(&config.log_path).parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        std::fs::create_dir_all(log_dir)?;
+        node.filesystem.read_write.push(log_dir.display().to_string());
+        node.filesystem.read_only.push(config.data_path.clone());
+        Ok(node)
+    }
+
     pub fn initialize_logger(log_path: &str) -> Result<(), std::io::Error> {
         let log_file = File::create(log_path)?;
         simple_logger::SimpleLogger::new()
@@ -141,23 +161,28 @@ mod bootstrap {
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // 1. Load Configuration
     let config = bootstrap::load_config();
+    let node = bootstrap::load_node_config(&config)?;
+
+    // 2. Measured boot; the web service refuses requests unless its integrity state
+    // satisfies the profile's failure policy
+    mesh_sec_ai_boot::boot::launch_with(node)?;
     bootstrap::initialize_logger(&config.log_path)?;
 
     log::info!("Loading data from {}", config.data_path);
     let data = load_data(&config.data_path)?;
     
-    // 2. Initialize AI Models
+    // 3. Initialize AI Models
     let vs = nn::VarStore::new(Device::Cpu);
     let model = Arc::new(Mutex::new(NeuralNetwork::new(&vs)));
     
-    // 3. Create Conversation System
+    // 4. Create Conversation System
     let mut conversation = Conversation::new(model.clone(), &config.redis_url, &config.kafka_broker);
     
-    // 4. Process Sample Input
+    // 5. Process Sample Input
     conversation.add_message("What are popular places in Arizona?".to_string());
     conversation.process()?;
     
-    // 5. System Validation
+    // 6. System Validation
     validate_system(&model, &config)?;
     
     Ok(())
@@ -190,8 +215,22 @@ mod web {
     use super::*;
     use hyper::{Body, Request, Response, Server, Method, StatusCode};
     use hyper::service::{make_service_fn, service_fn};
+    use mesh_sec_ai_boot::integrity;
+    use mesh_sec_ai_boot::profile::BootProfile;
 
-    async fn handle_request(req: Request<Body>, model: Arc<Mutex<dyn AIModel + Send + Sync>>) -> Result<Response<Body>, hyper::Error> {
+    async fn handle_request(req: Request<Body>, model: Arc<Mutex<dyn AIModel + Send + Sync>>, profile: BootProfile) -> Result<Response<Body>, hyper::Error> {
+        // Serve nothing until the boot has verified this node (or, for developer
+        // profiles, booted degraded), and stop as soon as the integrity watcher finds
+        // something wrong.
+        let monitor = integrity::monitor();
+        let state = monitor.state();
+        if !state.permits_serving(profile.failure_policy()) {
+            let reason = monitor.reason().unwrap_or_else(|| "boot has not verified this node".to_string());
+            return Ok(Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(format!("integrity {}: {}", state, reason).into())
+                .unwrap());
+        }
         match (req.method(), req.uri().path()) {
             (&Method::POST, "/predict") => {
                 let body_bytes = hyper::body::to_bytes(req.into_body()).await?;
@@ -206,13 +245,13 @@ mod web {
         }
     }
 
-    pub async fn start_server(model: Arc<Mutex<dyn AIModel + Send + Sync>>) {
+    pub async fn start_server(model: Arc<Mutex<dyn AIModel + Send + Sync>>, profile: BootProfile) {
         let make_svc = make_service_fn(move |_| {
             let model = model.clone();
             async move {
                 Ok::<_, hyper::Error>(service_fn(move |req| {
                     let model = model.clone();
-                    handle_request(req, model)
+                    handle_request(req, model, profile)
                 }))
             }
         });
@@ -242,6 +281,7 @@ fn audit_system(config: &Config) -> Result<(), String> {
 //    export AI_DATA_PATH="data.csv"
 //    export AI_REDIS_URL="redis://127.0.0.1:6379/"
 //    export AI_KAFKA_BROKER="localhost:9092"
+//    export AI_BOOT_CONFIG="config/boot.toml"
 // 2. Prepare data.csv with text data
 // 3. Run `cargo run`
 
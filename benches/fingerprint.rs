@@ -0,0 +1,60 @@
+//! Fingerprinting a synthetic tree: one thread against all cores, and a warm cache against
+//! reading every file. Run with `cargo bench --bench fingerprint`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use mesh_sec_ai_boot::fingerprint::Fingerprinter;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DIRS: usize = 32;
+const FILES_PER_DIR: usize = 64;
+const FILE_SIZE: usize = 64 * 1024;
+
+/// 2048 files of 64 KiB in 32 directories, with distinct contents.
+fn synthetic_tree(root: &Path) {
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    for d in 0..DIRS {
+        let dir = root.join(format!("module_{:02}", d));
+        fs::create_dir_all(&dir).expect("create synthetic tree");
+        for f in 0..FILES_PER_DIR {
+            let contents: Vec<u8> = (0..FILE_SIZE).map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            }).collect();
+            fs::write(dir.join(format!("file_{:03}.rs", f)), contents).expect("write synthetic file");
+        }
+    }
+}
+
+fn fingerprint(c: &mut Criterion) {
+    let scratch = std::env::temp_dir().join(format!("fingerprint-bench-{}", std::process::id()));
+    let root = scratch.join("tree");
+    let cache: PathBuf = scratch.join("cache.json");
+    synthetic_tree(&root);
+    // Files changed within a couple of seconds of a run are never cached.
+    std::thread::sleep(Duration::from_secs(3));
+
+    let mut group = c.benchmark_group("fingerprint");
+    group.sample_size(10).throughput(Throughput::Bytes((DIRS * FILES_PER_DIR * FILE_SIZE) as u64));
+
+    let single = rayon::ThreadPoolBuilder::new().num_threads(1).build().expect("build thread pool");
+    group.bench_function("serial", |b| b.iter(|| single.install(|| Fingerprinter::new(&root).compute().unwrap())));
+    group.bench_function("parallel", |b| b.iter(|| Fingerprinter::new(&root).compute().unwrap()));
+
+    let warm = Fingerprinter::new(&root).cache(&cache).compute().unwrap();
+    assert_eq!(warm.cache_hits, 0);
+    group.bench_function("parallel_cached", |b| b.iter(|| {
+        let result = Fingerprinter::new(&root).cache(&cache).compute().unwrap();
+        assert_eq!(result.cache_hits, result.files);
+        assert_eq!(result.fingerprint, warm.fingerprint);
+    }));
+    group.finish();
+
+    fs::remove_dir_all(&scratch).ok();
+}
+
+criterion_group!(benches, fingerprint);
+criterion_main!(benches);
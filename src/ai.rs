@@ -1,8 +1,31 @@
+use crate::error::BootError;
+use crate::policy::{PolicyStore, Request};
 use crate::schema::AIModelDescriptor;
-pub fn initialize(models: &[AIModelDescriptor]) -> Result<(), String> {
+/// Policy request for loading `model`: subject `model:<name>`, action `load`, resource
+/// `model/<name>@<version>`, with `version`, `security_level` and `isolated` attributes.
+pub fn load_request(model: &AIModelDescriptor) -> Request {
+    Request::new(format!("model:{}", model.name), "load", format!("model/{}@{}", model.name, model.version))
+        .with("version", model.version.as_str())
+        .with("security_level", model.security_level)
+        .with("isolated", model.isolated)
+}
+pub fn initialize(models: &[AIModelDescriptor], policy: &PolicyStore) -> Result<(), BootError> {
     for model in models {
+        policy.check(&load_request(model)).map_err(BootError::Model)?;
         println!("🧠 AI: {} {} S{} {}", model.name, model.version,
                  model.security_level, if model.isolated {"[Isolated]"} else {"[Shared]"} );
     }
     Ok(())
 }
+pub fn shutdown(models: &[AIModelDescriptor]) -> Result<(), BootError> {
+    for model in models.iter().rev() {
+        println!("🧠 AI: {} {} stopped", model.name, model.version);
+    }
+    Ok(())
+}
+pub fn describe(models: &[AIModelDescriptor]) -> Vec<String> {
+    models.iter()
+        .map(|model| format!("load {} {} at S{} {}", model.name, model.version, model.security_level,
+                             if model.isolated {"isolated"} else {"shared"}))
+        .collect()
+}
@@ -0,0 +1,593 @@
+//! Remote attestation. A node signs a `Quote` of its measured boot: the verifier's
+//! nonce, the PCR values, the event log they replay from and the kernel fingerprint
+//! that log measured.
+//! A verifier checks it against an `AttestationPolicy` of golden measurements. `serve`
+//! answers `GET /quote?nonce=<hex>` over plain HTTP on a std `TcpListener`, and `fetch`
+//! is the matching client; the quote is signed, so the transport need not be trusted.
+//!
+//! Without a TPM this is software-only attestation: the PCRs are replayed from the
+//! node's own event log, which anyone holding the node key can rewrite, so a quote
+//! only proves what that key vouches for. With the `tpm` feature and a configured TPM
+//! the quote also carries a TPM2_Quote, signed by the TPM's attestation key over the
+//! nonce and the live SHA-256 PCRs; a policy listing `tpm_keys` requires it and checks
+//! the event log against it.
+
+use crate::config::{self, Format};
+use crate::error::{BootError, ErrorDetail};
+use crate::integrity;
+use crate::measure::{EventLog, PcrBank};
+use crate::schema::{AttestationConfig, GoldenPcr, HashAlgorithm, KeyAlgorithm, SignaturePolicy, TrustedKey};
+use crate::signature::{self, DetachedSignature};
+use crate::tpm_quote::{self, TpmQuote};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Prefix of every signed quote, so a node key cannot be used to forge firmware or
+/// manifest signatures, or the other way round.
+pub const SIGNING_CONTEXT: &[u8] = b"mesh-sec-ai-boot/attestation/v1\0";
+
+pub const QUOTE_VERSION: u32 = 1;
+
+/// Accepted nonce lengths in bytes.
+pub const NONCE_LEN: std::ops::RangeInclusive<usize> = 16..=64;
+
+/// Quotes dated this far ahead of the verifier's clock are still accepted.
+const CLOCK_SKEW_SECS: i64 = 30;
+
+const IO_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How long `serve` gives a client to send its whole request.
+const REQUEST_DEADLINE: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Connections `serve` handles at once; further ones get a 503.
+const MAX_CONNECTIONS: usize = 32;
+
+/// Longest request head `serve` reads.
+const MAX_REQUEST: usize = 16 * 1024;
+
+/// A node signing key as written by `attest keygen`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeKey {
+    pub id: String,
+    pub algorithm: KeyAlgorithm,
+    /// Hex 32-byte secret, see `signature::sign`.
+    pub secret_key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuotedPcr {
+    pub pcr: u32,
+    pub bank: HashAlgorithm,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quote {
+    pub version: u32,
+    /// Hex, as sent by the verifier.
+    pub nonce: String,
+    pub issued_at: DateTime<Utc>,
+    /// Every PCR the event log extends, in every bank.
+    pub pcrs: Vec<QuotedPcr>,
+    pub event_log: EventLog,
+    /// As measured in `event_log`, see `integrity::kernel_fingerprint`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel_fingerprint: Option<String>,
+    /// TPM2_Quote over the nonce and the SHA-256 PCRs the event log extends; absent on
+    /// software-only nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tpm: Option<TpmQuote>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignedQuote {
+    pub quote: Quote,
+    pub signatures: Vec<DetachedSignature>,
+}
+
+/// What a verifier accepts: which node keys, which measurements and how old a quote may be.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AttestationPolicy {
+    pub trusted_keys: Vec<TrustedKey>,
+    #[serde(default)]
+    pub revoked_keys: Vec<String>,
+    /// Which signature families must verify; `classical` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature_policy: Option<SignaturePolicy>,
+    /// Must not be empty: without golden values any signed quote would pass.
+    pub golden: Vec<GoldenPcr>,
+    /// Accepted SHA-512 kernel fingerprints; empty accepts any, including none.
+    #[serde(default)]
+    pub kernel_fingerprints: Vec<String>,
+    /// Hex `ak_public` of each node's TPM attestation key, as printed by `tpm quote`.
+    /// When set, quotes must carry a TPM quote by one of them that covers every golden
+    /// value; when empty, software-only quotes are accepted.
+    #[serde(default)]
+    pub tpm_keys: Vec<String>,
+    #[serde(default = "default_max_age")]
+    pub max_age_secs: u64,
+}
+
+/// An accepted quote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    pub key_ids: Vec<String>,
+    pub golden_checked: usize,
+    pub kernel_fingerprint: Option<String>,
+    /// Whether a TPM quote bound the event log to the node's live PCRs.
+    pub tpm_bound: bool,
+}
+
+fn default_max_age() -> u64 {
+    300
+}
+
+fn attestation_io(message: impl Into<String>) -> impl FnOnce(std::io::Error) -> BootError {
+    let message = message.into();
+    move |e| BootError::Attestation(ErrorDetail::io(message, e))
+}
+
+/// Fresh random bytes from the kernel, for nonces and new keys.
+pub fn random_bytes<const N: usize>() -> Result<[u8; N], BootError> {
+    let mut bytes = [0u8; N];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(&mut bytes))
+        .map_err(attestation_io("cannot read /dev/urandom"))?;
+    Ok(bytes)
+}
+
+/// The bytes a quote signature covers: `SIGNING_CONTEXT || SHA-256(JSON(quote))`.
+pub fn signing_message(quote: &Quote) -> Vec<u8> {
+    let json = serde_json::to_vec(quote).expect("Quote serializes to JSON");
+    [SIGNING_CONTEXT, &Sha256::digest(json)[..]].concat()
+}
+
+impl NodeKey {
+    pub fn generate(id: &str, algorithm: KeyAlgorithm) -> Result<Self, BootError> {
+        loop {
+            let secret: [u8; 32] = random_bytes()?;
+            // Only P-256 rejects some secrets (zero or above the group order).
+            if signature::public_key(algorithm, &secret).is_ok() {
+                return Ok(NodeKey { id: id.to_string(), algorithm, secret_key: hex::encode(secret) });
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, BootError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(attestation_io(format!("cannot read node key {}", path.display())))?;
+        serde_json::from_str(&text).map_err(|e| BootError::Attestation(
+            ErrorDetail::invalid(format!("malformed node key {}", path.display())).with_source(e)))
+    }
+
+    fn secret(&self) -> Result<Vec<u8>, ErrorDetail> {
+        hex::decode(self.secret_key.trim())
+            .map_err(|_| ErrorDetail::invalid(format!("secret of node key `{}` is not valid hex", self.id)))
+    }
+
+    /// The entry a verifier lists in `AttestationPolicy::trusted_keys`.
+    pub fn trusted_key(&self) -> Result<TrustedKey, BootError> {
+        let public = signature::public_key(self.algorithm, &self.secret().map_err(BootError::Attestation)?)
+            .map_err(BootError::Attestation)?;
+        Ok(TrustedKey {
+            id: self.id.clone(),
+            algorithm: self.algorithm,
+            public_key: hex::encode(public),
+            not_before: None,
+            not_after: None,
+        })
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Result<DetachedSignature, BootError> {
+        let secret = self.secret().map_err(BootError::Attestation)?;
+        let signature = signature::sign(self.algorithm, &secret, message).map_err(BootError::Attestation)?;
+        Ok(DetachedSignature { key_id: self.id.clone(), algorithm: self.algorithm, signature: hex::encode(signature) })
+    }
+}
+
+/// Produces quotes on a node from its event log and keys.
+pub struct Attester {
+    keys: Vec<NodeKey>,
+    event_log: PathBuf,
+    #[cfg(feature = "tpm")]
+    tpm: Option<crate::tpm::Tpm>,
+}
+
+impl Attester {
+    pub fn new(cfg: &AttestationConfig, event_log: &Path) -> Result<Self, BootError> {
+        if cfg.signing_keys.is_empty() {
+            return Err(BootError::Attestation(ErrorDetail::invalid("no attestation signing keys configured")));
+        }
+        let keys = cfg.signing_keys.iter().map(|p| NodeKey::load(Path::new(p))).collect::<Result<_, _>>()?;
+        Ok(Attester {
+            keys,
+            event_log: event_log.to_path_buf(),
+            #[cfg(feature = "tpm")]
+            tpm: None,
+        })
+    }
+
+    /// Add a TPM2_Quote by `tpm` to every quote.
+    #[cfg(feature = "tpm")]
+    pub fn with_tpm(mut self, tpm: crate::tpm::Tpm) -> Self {
+        self.tpm = Some(tpm);
+        self
+    }
+
+    /// TPM2_Quote over `nonce` and the SHA-256 PCRs of `replayed`, when a TPM is attached.
+    #[cfg(feature = "tpm")]
+    fn tpm_quote(&self, nonce: &[u8], replayed: &[PcrBank]) -> Result<Option<TpmQuote>, BootError> {
+        let Some(tpm) = &self.tpm else { return Ok(None) };
+        let pcrs: Vec<u32> = replayed.iter()
+            .find(|b| b.algorithm == HashAlgorithm::Sha256)
+            .map(|b| b.values.keys().copied().collect())
+            .unwrap_or_default();
+        tpm.quote(nonce, HashAlgorithm::Sha256, &pcrs).map(Some).map_err(BootError::Attestation)
+    }
+
+    #[cfg(not(feature = "tpm"))]
+    fn tpm_quote(&self, _nonce: &[u8], _replayed: &[PcrBank]) -> Result<Option<TpmQuote>, BootError> {
+        Ok(None)
+    }
+
+    /// Sign the current event log, its replayed PCRs and the kernel fingerprint it
+    /// measured together with `nonce`. The log is re-read on every call.
+    pub fn quote(&self, nonce: &[u8]) -> Result<SignedQuote, BootError> {
+        let event_log = EventLog::load(&self.event_log)?;
+        let replayed = event_log.replay().map_err(BootError::Attestation)?;
+        let tpm = self.tpm_quote(nonce, &replayed)?;
+        let pcrs = replayed
+            .into_iter()
+            .flat_map(|bank| bank.values.into_iter()
+                .map(move |(pcr, value)| QuotedPcr { pcr, bank: bank.algorithm, value: hex::encode(value) }))
+            .collect();
+        let kernel_fingerprint = integrity::kernel_fingerprint(&event_log).map_err(BootError::Attestation)?;
+        let quote = Quote {
+            version: QUOTE_VERSION,
+            nonce: hex::encode(nonce),
+            issued_at: Utc::now(),
+            pcrs,
+            event_log,
+            kernel_fingerprint,
+            tpm,
+        };
+        let message = signing_message(&quote);
+        let signatures = self.keys.iter().map(|k| k.sign(&message)).collect::<Result<_, _>>()?;
+        Ok(SignedQuote { quote, signatures })
+    }
+}
+
+impl AttestationPolicy {
+    pub fn load(path: &Path) -> Result<Self, BootError> {
+        let invalid = |message: String| BootError::Attestation(ErrorDetail::invalid(format!("{}: {}", path.display(), message)));
+        let format = Format::from_path(path).ok_or_else(|| invalid("policy files must be .toml or .json".to_string()))?;
+        let text = std::fs::read_to_string(path)
+            .map_err(attestation_io(format!("cannot read attestation policy {}", path.display())))?;
+        match format {
+            Format::Toml => toml::from_str(&text).map_err(|e| invalid(config::toml_message(&e, &text))),
+            Format::Json => serde_json::from_str(&text).map_err(|e| invalid(e.to_string())),
+        }
+    }
+
+    pub fn signature_policy(&self) -> SignaturePolicy {
+        self.signature_policy.unwrap_or(SignaturePolicy::Classical)
+    }
+}
+
+/// Accept `signed` only if it is signed as `policy` demands, answers `nonce`, is fresh as
+/// of `now`, its PCRs replay from its event log, a trusted TPM quote vouches for that log
+/// when `policy.tpm_keys` is set, the PCRs match every golden value and the kernel
+/// fingerprint measured in the log is allowed. Every refusal is `ErrorKind::Rejected`.
+pub fn verify(signed: &SignedQuote, policy: &AttestationPolicy, nonce: &[u8], now: DateTime<Utc>)
+    -> Result<Verdict, ErrorDetail>
+{
+    let quote = &signed.quote;
+    if policy.golden.is_empty() {
+        return Err(ErrorDetail::rejected("attestation policy lists no golden values; it would accept any signed quote"));
+    }
+    let key_ids = signature::verify_all(&signing_message(quote), &signed.signatures, &policy.trusted_keys,
+                                        &policy.revoked_keys, policy.signature_policy(), now)?;
+    if quote.version != QUOTE_VERSION {
+        return Err(ErrorDetail::rejected(format!("unsupported quote version {}", quote.version)));
+    }
+    if hex::decode(&quote.nonce).ok().as_deref() != Some(nonce) {
+        return Err(ErrorDetail::rejected("quote does not answer our nonce (replayed?)"));
+    }
+    if quote.issued_at > now + Duration::seconds(CLOCK_SKEW_SECS) {
+        return Err(ErrorDetail::rejected(format!("quote is dated in the future ({})", quote.issued_at.to_rfc3339())));
+    }
+    if now - quote.issued_at > Duration::seconds(policy.max_age_secs as i64) {
+        return Err(ErrorDetail::rejected(format!("quote from {} is older than {}s", quote.issued_at.to_rfc3339(), policy.max_age_secs)));
+    }
+
+    let replayed = quote.event_log.replay()?;
+    for quoted in &quote.pcrs {
+        let replay = replayed.iter()
+            .find(|b| b.algorithm == quoted.bank)
+            .map(|b| hex::encode(b.read(quoted.pcr)));
+        if !replay.is_some_and(|r| r.eq_ignore_ascii_case(&quoted.value)) {
+            return Err(ErrorDetail::rejected(format!("{} PCR[{}] does not replay from the event log", quoted.bank, quoted.pcr)));
+        }
+    }
+    let tpm_bound = !policy.tpm_keys.is_empty();
+    if tpm_bound {
+        let tpm = quote.tpm.as_ref()
+            .ok_or_else(|| ErrorDetail::rejected("policy requires a TPM quote; this node sent a software-only quote"))?;
+        let selection = tpm_quote::verify(tpm, &policy.tpm_keys, nonce, &replayed)?;
+        let covered = |bank: HashAlgorithm, pcr: u32| selection.iter().any(|(b, pcrs)| *b == bank && pcrs.contains(&pcr));
+        if let Some(golden) = policy.golden.iter().find(|g| !covered(g.bank, g.pcr)) {
+            return Err(ErrorDetail::rejected(format!("{} PCR[{}] has a golden value but is not covered by the TPM quote",
+                                                     golden.bank, golden.pcr)));
+        }
+    }
+    let mismatches = integrity::golden_mismatches(&quote.event_log, &replayed, &policy.golden)
+        .map_err(|e| ErrorDetail::rejected(e.message))?;
+    if !mismatches.is_empty() {
+        return Err(ErrorDetail::rejected(format!("measurements do not match golden values:\n  {}", mismatches.join("\n  "))));
+    }
+
+    let kernel_fingerprint = integrity::kernel_fingerprint(&quote.event_log)?;
+    if kernel_fingerprint != quote.kernel_fingerprint {
+        return Err(ErrorDetail::rejected("quoted kernel fingerprint is not the one the event log measured"));
+    }
+    if !policy.kernel_fingerprints.is_empty() {
+        let allowed = kernel_fingerprint.as_deref()
+            .is_some_and(|fp| policy.kernel_fingerprints.iter().any(|g| g.eq_ignore_ascii_case(fp)));
+        if !allowed {
+            return Err(ErrorDetail::rejected(format!("kernel fingerprint {} is not allowed",
+                                                     kernel_fingerprint.as_deref().unwrap_or("(none)"))));
+        }
+    }
+    Ok(Verdict { key_ids, golden_checked: policy.golden.len(), kernel_fingerprint, tpm_bound })
+}
+
+/// Answer quote requests until the listener fails. Each connection gets its own thread
+/// and `REQUEST_DEADLINE` to send its request, so a slow or idle client cannot hold up
+/// others; beyond `MAX_CONNECTIONS` at once, clients are turned away with a 503.
+pub fn serve(listener: TcpListener, attester: &Attester) -> Result<(), BootError> {
+    let active = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let mut stream = stream.map_err(attestation_io("cannot accept connection"))?;
+            let peer = stream.peer_addr().map_or_else(|_| "?".to_string(), |a| a.to_string());
+            if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                active.fetch_sub(1, Ordering::SeqCst);
+                let _ = stream.set_write_timeout(Some(IO_TIMEOUT))
+                    .and_then(|()| respond(&mut stream, "503 Service Unavailable", &error_body("too many connections")));
+                continue;
+            }
+            let active = &active;
+            scope.spawn(move || {
+                if let Err(e) = handle(stream, attester) {
+                    eprintln!("⚠️ attestation: {}: {}", peer, e);
+                }
+                active.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+        Ok(())
+    })
+}
+
+/// Read the request head, up to the blank line, within `REQUEST_DEADLINE`.
+fn read_head(stream: &mut TcpStream) -> std::io::Result<String> {
+    let deadline = Instant::now() + REQUEST_DEADLINE;
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "request not received in time"));
+        }
+        stream.set_read_timeout(Some(left.min(IO_TIMEOUT)))?;
+        match stream.read(&mut buffer)? {
+            0 => break,
+            n => head.extend_from_slice(&buffer[..n]),
+        }
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+fn handle(mut stream: TcpStream, attester: &Attester) -> std::io::Result<()> {
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let head = read_head(&mut stream)?;
+    let request_line = head.lines().next().unwrap_or_default();
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let (status, body) = match (method, target.split_once('?')) {
+        ("GET", Some(("/quote", query))) => {
+            let nonce = query.split('&')
+                .find_map(|pair| pair.strip_prefix("nonce="))
+                .and_then(|n| hex::decode(n).ok())
+                .filter(|n| NONCE_LEN.contains(&n.len()));
+            match nonce {
+                None => ("400 Bad Request", error_body(&format!(
+                    "nonce must be {} to {} hex-encoded bytes", NONCE_LEN.start(), NONCE_LEN.end()))),
+                Some(nonce) => match attester.quote(&nonce) {
+                    Ok(quote) => ("200 OK", serde_json::to_string(&quote).expect("SignedQuote serializes to JSON")),
+                    Err(e) => ("500 Internal Server Error", error_body(&e.to_string())),
+                },
+            }
+        }
+        ("GET", _) => ("404 Not Found", error_body("only /quote?nonce=<hex> is served")),
+        _ => ("405 Method Not Allowed", error_body("only GET is supported")),
+    };
+    respond(&mut stream, status, &body)
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> std::io::Result<()> {
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           status, body.len(), body)?;
+    stream.flush()
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+/// Request a quote for `nonce` from a node serving at `addr` (`host:port`).
+pub fn fetch(addr: &str, nonce: &[u8]) -> Result<SignedQuote, BootError> {
+    let io = |what: &str| attestation_io(format!("{} {}", what, addr));
+    let mut stream = TcpStream::connect(addr).map_err(io("cannot connect to"))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(io("cannot configure connection to"))?;
+    write!(stream, "GET /quote?nonce={} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", hex::encode(nonce), addr)
+        .map_err(io("cannot send request to"))?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(io("cannot read response from"))?;
+    let (head, body) = response.split_once("\r\n\r\n")
+        .ok_or_else(|| BootError::Attestation(ErrorDetail::rejected(format!("malformed HTTP response from {}", addr))))?;
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(BootError::Attestation(ErrorDetail::rejected(format!("{} answered `{}`: {}", addr, status, body.trim()))));
+    }
+    serde_json::from_str(body).map_err(|e| BootError::Attestation(
+        ErrorDetail::rejected(format!("malformed quote from {}", addr)).with_source(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::{EventType, MeasuredBoot, PCR_FIRMWARE, PCR_KERNEL, PCR_POLICY};
+    use crate::tpm_quote::tests::synthetic_quote;
+
+    const NONCE: &[u8] = b"nonce-0123456789";
+    const KERNEL: &str = "5eed";
+
+    /// Write an event log measuring `firmware` and `KERNEL` and a node key into `dir`.
+    fn node(dir: &Path, firmware: &[u8]) -> (Attester, NodeKey, Vec<PcrBank>) {
+        let measured = MeasuredBoot::new(&[HashAlgorithm::Sha256]);
+        measured.measure(PCR_FIRMWARE, EventType::EvPostCode, "firmware", firmware).unwrap();
+        measured.measure(PCR_POLICY, EventType::EvEventTag, "policy", b"policy").unwrap();
+        measured.measure(PCR_KERNEL, EventType::EvIpl, format!("{} {}", integrity::KERNEL_EVENT, KERNEL), KERNEL.as_bytes()).unwrap();
+        let log = dir.join(format!("events-{}.json", hex::encode(&firmware[..4])));
+        std::fs::write(&log, measured.log().to_json()).unwrap();
+        let key = NodeKey::generate("node-1", KeyAlgorithm::Ed25519).unwrap();
+        let key_path = dir.join(format!("node-{}.key", hex::encode(&firmware[..4])));
+        std::fs::write(&key_path, serde_json::to_string(&key).unwrap()).unwrap();
+        let cfg = AttestationConfig {
+            signing_keys: vec![key_path.display().to_string()],
+            listen: "127.0.0.1:0".to_string(),
+        };
+        (Attester::new(&cfg, &log).unwrap(), key, measured.pcrs())
+    }
+
+    fn policy(key: &NodeKey, banks: &[PcrBank]) -> AttestationPolicy {
+        AttestationPolicy {
+            trusted_keys: vec![key.trusted_key().unwrap()],
+            revoked_keys: Vec::new(),
+            signature_policy: None,
+            golden: [PCR_FIRMWARE, PCR_POLICY].into_iter()
+                .map(|pcr| GoldenPcr { pcr, bank: HashAlgorithm::Sha256, value: hex::encode(banks[0].read(pcr)) })
+                .collect(),
+            kernel_fingerprints: Vec::new(),
+            tpm_keys: Vec::new(),
+            max_age_secs: default_max_age(),
+        }
+    }
+
+    fn resign(mut signed: SignedQuote, key: &NodeKey) -> SignedQuote {
+        signed.signatures = vec![key.sign(&signing_message(&signed.quote)).unwrap()];
+        signed
+    }
+
+    fn with_tpm(mut signed: SignedQuote, key: &NodeKey, tpm: TpmQuote) -> SignedQuote {
+        signed.quote.tpm = Some(tpm);
+        resign(signed, key)
+    }
+
+    #[test]
+    fn serves_quotes_that_verify_over_loopback() {
+        let dir = tempfile::tempdir().unwrap();
+        let (attester, key, banks) = node(dir.path(), b"firmware");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let attester: &'static Attester = Box::leak(Box::new(attester));
+        std::thread::spawn(move || serve(listener, attester));
+
+        // A client that never sends its request must not hold up the next one.
+        let _idle = TcpStream::connect(&addr).unwrap();
+        let started = Instant::now();
+        let signed = fetch(&addr, NONCE).unwrap();
+        assert!(started.elapsed() < IO_TIMEOUT);
+
+        let verdict = verify(&signed, &policy(&key, &banks), NONCE, Utc::now()).unwrap();
+        assert_eq!(verdict.key_ids, vec!["node-1".to_string()]);
+        assert_eq!(verdict.golden_checked, 2);
+        assert!(!verdict.tpm_bound);
+        let stale = verify(&signed, &policy(&key, &banks), b"nonce-9876543210", Utc::now()).unwrap_err();
+        assert!(stale.message.contains("nonce"), "{}", stale.message);
+    }
+
+    #[test]
+    fn kernel_fingerprints_come_from_the_measured_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let (attester, key, banks) = node(dir.path(), b"firmware");
+        let mut policy = policy(&key, &banks);
+        policy.kernel_fingerprints = vec![KERNEL.to_string()];
+        let signed = attester.quote(NONCE).unwrap();
+        assert_eq!(signed.quote.kernel_fingerprint.as_deref(), Some(KERNEL));
+        assert_eq!(verify(&signed, &policy, NONCE, Utc::now()).unwrap().kernel_fingerprint.as_deref(), Some(KERNEL));
+
+        // Claiming an allowed fingerprint the log does not measure is refused.
+        policy.kernel_fingerprints = vec!["beef".to_string()];
+        let mut claimed = signed.clone();
+        claimed.quote.kernel_fingerprint = Some("beef".to_string());
+        let err = verify(&resign(claimed, &key), &policy, NONCE, Utc::now()).unwrap_err();
+        assert!(err.message.contains("not the one the event log measured"), "{}", err.message);
+
+        // So is an event naming one fingerprint with the digest of another.
+        let mut renamed = signed.clone();
+        let event = renamed.quote.event_log.events.iter_mut().find(|e| e.pcr_index == PCR_KERNEL).unwrap();
+        event.event = format!("{} beef", integrity::KERNEL_EVENT);
+        renamed.quote.kernel_fingerprint = Some("beef".to_string());
+        let err = verify(&resign(renamed, &key), &policy, NONCE, Utc::now()).unwrap_err();
+        assert!(err.message.contains("is not the hash of the fingerprint"), "{}", err.message);
+    }
+
+    #[test]
+    fn policies_without_golden_values_accept_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let (attester, key, banks) = node(dir.path(), b"firmware");
+        let policy = AttestationPolicy { golden: Vec::new(), ..policy(&key, &banks) };
+        let err = verify(&attester.quote(NONCE).unwrap(), &policy, NONCE, Utc::now()).unwrap_err();
+        assert!(err.message.contains("no golden values"), "{}", err.message);
+    }
+
+    #[test]
+    fn tpm_keys_require_a_tpm_quote_over_the_event_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let (attester, key, banks) = node(dir.path(), b"firmware");
+        let tpm = synthetic_quote(3, NONCE, &banks[0], &[PCR_FIRMWARE, PCR_POLICY]);
+        let mut policy = policy(&key, &banks);
+        policy.tpm_keys = vec![tpm.ak_public.clone()];
+
+        let software = attester.quote(NONCE).unwrap();
+        let err = verify(&software, &policy, NONCE, Utc::now()).unwrap_err();
+        assert!(err.message.contains("software-only"), "{}", err.message);
+
+        let verdict = verify(&with_tpm(software.clone(), &key, tpm.clone()), &policy, NONCE, Utc::now()).unwrap();
+        assert!(verdict.tpm_bound);
+
+        let partial = synthetic_quote(3, NONCE, &banks[0], &[PCR_FIRMWARE]);
+        let err = verify(&with_tpm(software, &key, partial), &policy, NONCE, Utc::now()).unwrap_err();
+        assert!(err.message.contains("not covered by the TPM quote"), "{}", err.message);
+
+        // The node key vouches for a log that matches the golden values, but the TPM's
+        // live PCRs measured different firmware.
+        let (_, _, booted) = node(dir.path(), b"backdoored firmware");
+        let live = synthetic_quote(3, NONCE, &booted[0], &[PCR_FIRMWARE, PCR_POLICY]);
+        let err = verify(&with_tpm(attester.quote(NONCE).unwrap(), &key, live), &policy, NONCE, Utc::now()).unwrap_err();
+        assert!(err.message.contains("differ from the event log"), "{}", err.message);
+    }
+}
@@ -0,0 +1,439 @@
+use crate::error::{BootError, ErrorDetail};
+use serde::Serialize;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expect {
+    Equals(i64),
+    AtLeast(i64),
+    AtMost(i64),
+}
+
+/// One sysctl in the baseline; `recommended` is what `apply` writes.
+#[derive(Debug, Clone, Copy)]
+pub struct SysctlCheck {
+    pub key: &'static str,
+    pub expect: Expect,
+    pub recommended: i64,
+    pub weight: u32,
+    pub why: &'static str,
+    /// The kernel refuses to reset this until the next reboot, so `apply` only writes
+    /// it after `allow_irreversible`.
+    pub irreversible: bool,
+}
+
+/// Mount options a mount point must carry, if it is mounted on its own.
+#[derive(Debug, Clone, Copy)]
+pub struct MountCheck {
+    pub mount_point: &'static str,
+    pub options: &'static [&'static str],
+    pub weight: u32,
+}
+
+/// The hardening baseline `harden_kernel` audits against, loosely following the KSPP
+/// recommendations.
+pub const SYSCTL_BASELINE: &[SysctlCheck] = &[
+    SysctlCheck { key: "kernel.kptr_restrict", expect: Expect::AtLeast(1), recommended: 2, weight: 3,
+                  why: "hides kernel pointers that defeat KASLR", irreversible: false },
+    SysctlCheck { key: "kernel.dmesg_restrict", expect: Expect::Equals(1), recommended: 1, weight: 2,
+                  why: "keeps the kernel log away from unprivileged users", irreversible: false },
+    SysctlCheck { key: "kernel.unprivileged_bpf_disabled", expect: Expect::AtLeast(1), recommended: 1, weight: 3,
+                  why: "unprivileged eBPF is a frequent kernel exploit vector", irreversible: false },
+    SysctlCheck { key: "kernel.yama.ptrace_scope", expect: Expect::AtLeast(1), recommended: 2, weight: 3,
+                  why: "stops processes from tracing their siblings", irreversible: false },
+    SysctlCheck { key: "kernel.kexec_load_disabled", expect: Expect::Equals(1), recommended: 1, weight: 2,
+                  why: "prevents replacing the running kernel", irreversible: true },
+    SysctlCheck { key: "kernel.perf_event_paranoid", expect: Expect::AtLeast(2), recommended: 3, weight: 2,
+                  why: "limits perf events to privileged users", irreversible: false },
+    SysctlCheck { key: "kernel.randomize_va_space", expect: Expect::Equals(2), recommended: 2, weight: 3,
+                  why: "full address space layout randomisation", irreversible: false },
+    SysctlCheck { key: "kernel.sysrq", expect: Expect::Equals(0), recommended: 0, weight: 1,
+                  why: "magic SysRq keys bypass access control on the console", irreversible: false },
+    SysctlCheck { key: "kernel.modules_disabled", expect: Expect::Equals(1), recommended: 1, weight: 2,
+                  why: "no kernel modules can be loaded after boot", irreversible: true },
+    SysctlCheck { key: "net.core.bpf_jit_harden", expect: Expect::Equals(2), recommended: 2, weight: 2,
+                  why: "blinds constants in JIT-compiled BPF", irreversible: false },
+    SysctlCheck { key: "dev.tty.ldisc_autoload", expect: Expect::Equals(0), recommended: 0, weight: 1,
+                  why: "stops unprivileged users loading TTY line disciplines", irreversible: false },
+    SysctlCheck { key: "fs.protected_symlinks", expect: Expect::Equals(1), recommended: 1, weight: 2,
+                  why: "blocks symlink attacks in world-writable directories", irreversible: false },
+    SysctlCheck { key: "fs.protected_hardlinks", expect: Expect::Equals(1), recommended: 1, weight: 2,
+                  why: "blocks hardlinks to files the user cannot access", irreversible: false },
+    SysctlCheck { key: "fs.protected_fifos", expect: Expect::AtLeast(1), recommended: 2, weight: 1,
+                  why: "restricts opening FIFOs in sticky directories", irreversible: false },
+    SysctlCheck { key: "fs.protected_regular", expect: Expect::AtLeast(1), recommended: 2, weight: 1,
+                  why: "restricts opening regular files in sticky directories", irreversible: false },
+    SysctlCheck { key: "fs.suid_dumpable", expect: Expect::Equals(0), recommended: 0, weight: 2,
+                  why: "setuid processes must not leave core dumps", irreversible: false },
+];
+
+pub const MOUNT_BASELINE: &[MountCheck] = &[
+    MountCheck { mount_point: "/tmp", options: &["nosuid", "nodev", "noexec"], weight: 2 },
+    MountCheck { mount_point: "/var/tmp", options: &["nosuid", "nodev", "noexec"], weight: 1 },
+    MountCheck { mount_point: "/dev/shm", options: &["nosuid", "nodev", "noexec"], weight: 2 },
+    MountCheck { mount_point: "/proc", options: &["nosuid", "nodev", "noexec"], weight: 1 },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Sysctl,
+    Mount,
+    Modules,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Pass,
+    Fail,
+    /// Not present on this kernel or system; left out of the score.
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    /// Sysctl key, mount point or module setting, e.g. `kernel.kptr_restrict`.
+    pub id: String,
+    pub category: Category,
+    pub status: Status,
+    pub actual: Option<String>,
+    pub expected: String,
+    pub weight: u32,
+    /// How to fix a failing finding.
+    pub remediation: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditReport {
+    pub root: PathBuf,
+    /// Weighted share of applicable checks that pass, 0 to 100. Unavailable checks are
+    /// left out; when none was available there is no score rather than a score of 0.
+    pub score: Option<u8>,
+    pub findings: Vec<Finding>,
+}
+
+/// Reads kernel state below `root` (normally `/`), so it can be pointed at a fake
+/// procfs tree containing `proc/sys/...`, `proc/mounts` and `sys/module/...`.
+#[derive(Debug, Clone)]
+pub struct Auditor {
+    root: PathBuf,
+    irreversible: bool,
+}
+
+impl Default for Auditor {
+    fn default() -> Self {
+        Auditor::new("/")
+    }
+}
+
+impl Auditor {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Auditor { root: root.into(), irreversible: false }
+    }
+
+    /// Let `apply` also write sysctls that lock until reboot, such as
+    /// `kernel.modules_disabled`.
+    pub fn allow_irreversible(mut self, allow: bool) -> Self {
+        self.irreversible = allow;
+        self
+    }
+
+    fn sysctl_path(&self, key: &str) -> PathBuf {
+        self.root.join("proc/sys").join(key.replace('.', "/"))
+    }
+
+    fn read(&self, relative: impl AsRef<Path>) -> Option<String> {
+        std::fs::read_to_string(self.root.join(relative)).ok().map(|s| s.trim().to_string())
+    }
+
+    pub fn run(&self) -> AuditReport {
+        let mut findings: Vec<Finding> = SYSCTL_BASELINE.iter().map(|check| self.check_sysctl(check)).collect();
+        let mounts = self.read("proc/mounts").unwrap_or_default();
+        findings.extend(MOUNT_BASELINE.iter().map(|check| check_mount(check, &mounts)));
+        findings.push(self.check_module_signatures());
+
+        let (passed, total) = findings.iter()
+            .filter(|f| f.status != Status::Unavailable)
+            .fold((0, 0), |(passed, total), f| (passed + if f.status == Status::Pass { f.weight } else { 0 }, total + f.weight));
+        let score = (passed * 100).checked_div(total).map(|score| score as u8);
+        AuditReport { root: self.root.clone(), score, findings }
+    }
+
+    fn check_sysctl(&self, check: &SysctlCheck) -> Finding {
+        let actual = std::fs::read_to_string(self.sysctl_path(check.key)).ok().map(|s| s.trim().to_string());
+        let status = match actual.as_deref().map(str::parse::<i64>) {
+            None => Status::Unavailable,
+            Some(Ok(value)) if check.expect.holds(value) => Status::Pass,
+            Some(_) => Status::Fail,
+        };
+        Finding {
+            id: check.key.to_string(),
+            category: if check.key == "kernel.modules_disabled" { Category::Modules } else { Category::Sysctl },
+            status,
+            actual,
+            expected: check.expect.to_string(),
+            weight: check.weight,
+            remediation: (status == Status::Fail).then(|| format!(
+                "sysctl -w {}={} ({}{}); persist it in /etc/sysctl.d/", check.key, check.recommended, check.why,
+                if check.irreversible { ", cannot be undone until reboot" } else { "" })),
+        }
+    }
+
+    fn check_module_signatures(&self) -> Finding {
+        let actual = self.read("sys/module/module/parameters/sig_enforce");
+        let status = match actual.as_deref() {
+            None => Status::Unavailable,
+            Some("Y") | Some("1") => Status::Pass,
+            Some(_) => Status::Fail,
+        };
+        Finding {
+            id: "module.sig_enforce".to_string(),
+            category: Category::Modules,
+            status,
+            actual,
+            expected: "Y".to_string(),
+            weight: 2,
+            remediation: (status == Status::Fail)
+                .then(|| "boot with `module.sig_enforce=1` so only signed modules load".to_string()),
+        }
+    }
+
+    /// Write the recommended value of every failing sysctl, leaving irreversible ones
+    /// alone unless allowed. Every target is opened for writing first, so nothing is
+    /// written when any of them cannot be. Only root may change the real `/proc/sys`;
+    /// a fake root is written as-is. Returns one line per change.
+    pub fn apply(&self, report: &AuditReport) -> Result<Vec<String>, BootError> {
+        if self.root == Path::new("/") && !is_root() {
+            return Err(BootError::Enforcement(ErrorDetail::rejected("applying sysctls requires root")));
+        }
+        let checks: Vec<&SysctlCheck> = report.failures()
+            .filter_map(|finding| SYSCTL_BASELINE.iter().find(|c| c.key == finding.id))
+            .filter(|check| self.irreversible || !check.irreversible)
+            .collect();
+        let mut targets = Vec::new();
+        let mut unwritable = Vec::new();
+        for check in checks {
+            let path = self.sysctl_path(check.key);
+            match std::fs::OpenOptions::new().write(true).open(&path) {
+                Ok(file) => targets.push((check, path, file)),
+                Err(e) => unwritable.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+        if !unwritable.is_empty() {
+            return Err(BootError::Enforcement(ErrorDetail::rejected(
+                format!("no sysctls written; cannot write {}", unwritable.join(", ")))));
+        }
+        let mut applied = Vec::new();
+        for (check, path, mut file) in targets {
+            file.write_all(format!("{}\n", check.recommended).as_bytes()).map_err(|e| BootError::Enforcement(
+                ErrorDetail::io(format!("cannot write {}", path.display()), e)))?;
+            applied.push(format!("{} = {}", check.key, check.recommended));
+        }
+        Ok(applied)
+    }
+}
+
+fn check_mount(check: &MountCheck, mounts: &str) -> Finding {
+    // `/proc/mounts`: device, mount point, type, options, ...; the last entry for a
+    // mount point is the one in effect.
+    let options = mounts.lines()
+        .rev()
+        .find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            (fields.len() >= 4 && fields[1] == check.mount_point).then(|| fields[3].to_string())
+        });
+    let missing: Vec<&str> = match &options {
+        Some(options) => check.options.iter().copied()
+            .filter(|wanted| !options.split(',').any(|o| o == *wanted))
+            .collect(),
+        None => Vec::new(),
+    };
+    let status = match (&options, missing.is_empty()) {
+        (None, _) => Status::Unavailable,
+        (Some(_), true) => Status::Pass,
+        (Some(_), false) => Status::Fail,
+    };
+    Finding {
+        id: check.mount_point.to_string(),
+        category: Category::Mount,
+        status,
+        actual: options,
+        expected: check.options.join(","),
+        weight: check.weight,
+        remediation: (status == Status::Fail).then(|| format!(
+            "remount {} with {} and add the options to /etc/fstab", check.mount_point, missing.join(","))),
+    }
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+impl Expect {
+    pub fn holds(self, value: i64) -> bool {
+        match self {
+            Expect::Equals(v) => value == v,
+            Expect::AtLeast(v) => value >= v,
+            Expect::AtMost(v) => value <= v,
+        }
+    }
+}
+
+impl AuditReport {
+    pub fn failures(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|f| f.status == Status::Fail)
+    }
+
+    /// Require a score of at least `min`. Without a score nothing could be checked, which
+    /// is reported as unsupported rather than as a failing host.
+    pub fn require(&self, min: u8) -> Result<(), ErrorDetail> {
+        match self.score {
+            Some(score) if score < min => Err(ErrorDetail::rejected(
+                format!("kernel audit scored {}/100, below {}", score, min))),
+            Some(_) => Ok(()),
+            None => Err(ErrorDetail::unsupported(
+                format!("no kernel audit check is available under {}, so a score of {} cannot be met", self.root.display(), min))),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("AuditReport serializes to JSON")
+    }
+}
+
+impl fmt::Display for Expect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expect::Equals(v) => write!(f, "= {}", v),
+            Expect::AtLeast(v) => write!(f, ">= {}", v),
+            Expect::AtMost(v) => write!(f, "<= {}", v),
+        }
+    }
+}
+
+/// Findings table followed by remediation hints for every failure.
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.findings.iter().map(|s| s.id.len()).max().unwrap_or(5).max(5);
+        writeln!(f, "{:<width$}  {:<11}  {:<22}  EXPECTED", "CHECK", "STATUS", "ACTUAL")?;
+        for finding in &self.findings {
+            let status = match finding.status {
+                Status::Pass => "ok",
+                Status::Fail => "FAIL",
+                Status::Unavailable => "unavailable",
+            };
+            writeln!(f, "{:<width$}  {:<11}  {:<22}  {}", finding.id, status,
+                     finding.actual.as_deref().unwrap_or("-"), finding.expected)?;
+        }
+        match self.score {
+            Some(score) => write!(f, "score {}/100 for {}", score, self.root.display())?,
+            None => write!(f, "no checks available for {}", self.root.display())?,
+        }
+        for finding in self.failures() {
+            if let Some(remediation) = &finding.remediation {
+                write!(f, "\n🔧 {}: {}", finding.id, remediation)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    /// A fake root where every baseline sysctl holds `value(check)`, `/tmp` is mounted
+    /// without `noexec` and module signatures are enforced.
+    fn fake_root(value: impl Fn(&SysctlCheck) -> i64) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let auditor = Auditor::new(root.path());
+        for check in SYSCTL_BASELINE {
+            let path = auditor.sysctl_path(check.key);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, format!("{}\n", value(check))).unwrap();
+        }
+        std::fs::write(root.path().join("proc/mounts"), "tmpfs /tmp tmpfs rw,nosuid,nodev 0 0\n").unwrap();
+        let params = root.path().join("sys/module/module/parameters");
+        std::fs::create_dir_all(&params).unwrap();
+        std::fs::write(params.join("sig_enforce"), "Y\n").unwrap();
+        root
+    }
+
+    fn sysctl(root: &Path, key: &str) -> String {
+        Auditor::new(root).read(Path::new("proc/sys").join(key.replace('.', "/"))).unwrap()
+    }
+
+    fn failing(report: &AuditReport) -> Vec<&str> {
+        report.failures().map(|f| f.id.as_str()).collect()
+    }
+
+    #[test]
+    fn scores_a_fake_procfs() {
+        let root = fake_root(|check| check.recommended);
+        let report = Auditor::new(root.path()).run();
+        assert_eq!(failing(&report), vec!["/tmp"]);
+        let tmp = report.findings.iter().find(|f| f.id == "/tmp").unwrap();
+        assert!(tmp.remediation.as_deref().unwrap().contains("noexec"));
+        let unmounted = report.findings.iter().find(|f| f.id == "/dev/shm").unwrap();
+        assert_eq!(unmounted.status, Status::Unavailable);
+        assert!(report.score.is_some_and(|s| s > 90 && s < 100), "score {:?}", report.score);
+        assert!(report.require(90).is_ok());
+        assert_eq!(report.require(100).unwrap_err().kind, ErrorKind::Rejected);
+    }
+
+    #[test]
+    fn an_empty_root_has_no_score_rather_than_zero() {
+        let root = tempfile::tempdir().unwrap();
+        let report = Auditor::new(root.path()).run();
+        assert!(report.findings.iter().all(|f| f.status == Status::Unavailable));
+        assert_eq!(report.score, None);
+        assert!(report.to_string().ends_with(&format!("no checks available for {}", root.path().display())));
+        assert_eq!(report.require(0).unwrap_err().kind, ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn apply_leaves_irreversible_sysctls_alone_by_default() {
+        let root = fake_root(|check| if check.expect.holds(0) { 1 } else { 0 });
+        let auditor = Auditor::new(root.path());
+        let before = auditor.run();
+        assert!(failing(&before).contains(&"kernel.modules_disabled"));
+
+        let applied = auditor.apply(&before).unwrap();
+        assert!(applied.contains(&"kernel.kptr_restrict = 2".to_string()));
+        assert_eq!(sysctl(root.path(), "kernel.kptr_restrict"), "2");
+        assert_eq!(sysctl(root.path(), "kernel.modules_disabled"), "0");
+        assert_eq!(sysctl(root.path(), "kernel.kexec_load_disabled"), "0");
+        let after = auditor.run();
+        assert_eq!(failing(&after), vec!["kernel.kexec_load_disabled", "kernel.modules_disabled", "/tmp"]);
+
+        let auditor = auditor.allow_irreversible(true);
+        assert_eq!(auditor.apply(&after).unwrap(), vec!["kernel.kexec_load_disabled = 1", "kernel.modules_disabled = 1"]);
+        assert_eq!(failing(&auditor.run()), vec!["/tmp"]);
+    }
+
+    #[test]
+    fn apply_writes_nothing_when_any_sysctl_is_unwritable() {
+        let root = fake_root(|check| if check.expect.holds(0) { 1 } else { 0 });
+        let auditor = Auditor::new(root.path());
+        let report = auditor.run();
+        // A directory cannot be opened for writing, not even by root.
+        let blocked = auditor.sysctl_path("fs.suid_dumpable");
+        std::fs::remove_file(&blocked).unwrap();
+        std::fs::create_dir(&blocked).unwrap();
+
+        let err = auditor.apply(&report).unwrap_err();
+        assert!(err.to_string().contains("fs/suid_dumpable"), "{}", err);
+        assert_eq!(sysctl(root.path(), "kernel.kptr_restrict"), "0");
+        assert_eq!(sysctl(root.path(), "kernel.dmesg_restrict"), "0");
+    }
+}
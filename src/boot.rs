@@ -1,40 +1,132 @@
-use crate::schema::{BootConfig, EnforcementLayer, AIModelDescriptor, FileSystemConfig, ComplianceConfig, CryptoProfile};
-use crate::{ai, compliance, fs, integrity, security};
-
-pub fn launch() -> Result<(), String> {
-    let config = BootConfig {
-        enforcement: EnforcementLayer {
-            restrict_shell: true,
-            lock_resources: true,
-            harden_kernel: true,
-        },
-        ai_models: vec![
-            AIModelDescriptor {
-                name: "APU-3.0",
-                version: "v1.68.2",
-                security_level: 5,
-                isolated: true,
-            }
-        ],
-        filesystem: FileSystemConfig {
-            mount_at: "/secure_data",
-            encryption: CryptoProfile {
-                algorithm: "PQ-AES-256-GCM",
-                quantum_resistant: true,
-            },
-        },
-        compliance: ComplianceConfig {
-            gdpr: true,
-            ccpa: true,
-            audit_log: true,
-        },
+use crate::error::{BootError, ErrorDetail};
+use crate::integrity::WatchList;
+use crate::measure::MeasuredBoot;
+use crate::policy::PolicyStore;
+use crate::report::{BootReport, DryRun};
+use crate::schema::{BootConfig, HashAlgorithm};
+use crate::stage::{BootContext, BootFailure, StageRegistry};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Boot with the built-in default `BootConfig`.
+pub fn launch() -> Result<BootReport, BootFailure> {
+    launch_with(BootConfig::default())
+}
+
+/// Boot with a caller-supplied (typically per-node, file-loaded) config, running the
+/// stages its `profile` selects.
+pub fn launch_with(config: BootConfig) -> Result<BootReport, BootFailure> {
+    let stages = config.profile.stages();
+    launch_with_stages(config, &stages)
+}
+
+/// Boot with a custom stage set, e.g. the defaults plus site-specific stages.
+pub fn launch_with_stages(config: BootConfig, stages: &StageRegistry) -> Result<BootReport, BootFailure> {
+    let config_failure = |error, config_warnings| BootFailure {
+        stage: "config",
+        error,
+        report: Box::new(BootReport { config_warnings, ..BootReport::begin() }),
+    };
+    let Prepared { config, policy, warnings: config_warnings } = match prepare(config) {
+        Ok(prepared) => prepared,
+        Err((error, config_warnings)) => return Err(config_failure(error, config_warnings)),
+    };
+    let measurements = match measurements(&config) {
+        Ok(measurements) => measurements,
+        Err(error) => return Err(config_failure(error, config_warnings)),
+    };
+    println!("🧭 Boot profile: {}", config.profile);
+    let ctx = BootContext::new(config).with_policy(policy).with_measurements(measurements);
+    let mut result = stages.run(&ctx);
+    match &mut result {
+        Ok(report) => report.config_warnings = config_warnings,
+        Err(failure) => failure.report.config_warnings = config_warnings,
+    }
+    let report = result?;
+    watch_policy(&ctx);
+    watch_integrity(&ctx);
+    println!("\n📱 ADB-BOOT READY → Integrity: {}", report.integrity);
+    Ok(report)
+}
+
+/// Start polling the policy file when `policy.reload_interval_secs` is set. Only called
+/// after a successful boot, so the reload thread inherits the enforced sandbox,
+/// rlimits and dropped capabilities rather than running unconfined.
+fn watch_policy(ctx: &BootContext) {
+    let Some(cfg) = ctx.config.policy.as_ref() else { return };
+    let Some(secs) = cfg.reload_interval_secs else { return };
+    println!("📜 Policy: reloading {} every {}s", cfg.path, secs);
+    ctx.policy.watch(ctx.measurements.clone(), Duration::from_secs(secs));
+}
+
+/// Start the runtime integrity watcher when `measured_boot.watch_interval_secs` is set.
+/// A hot-reloaded policy file is expected to change and is left out; every version the
+/// store accepts is measured into PCR_POLICY instead.
+fn watch_integrity(ctx: &BootContext) {
+    let measured = ctx.config.measured_boot.as_ref();
+    let Some(secs) = measured.and_then(|m| m.watch_interval_secs) else { return };
+    let mut watch = WatchList::new(&ctx.measurements);
+    if let Some(policy) = ctx.config.policy.as_ref().filter(|p| p.reload_interval_secs.is_some()) {
+        watch = watch.ignore(Path::new(&policy.path));
+    }
+    println!("👁️ Integrity: re-checking measured files every {}s", secs);
+    ctx.integrity.watch(watch, Duration::from_secs(secs));
+}
+
+/// Resolve the stage plan for `config` and report what each stage would do, without
+/// running anything.
+pub fn dry_run(config: BootConfig) -> Result<DryRun, BootError> {
+    let stages = config.profile.stages();
+    dry_run_with_stages(config, &stages)
+}
+
+pub fn dry_run_with_stages(config: BootConfig, stages: &StageRegistry) -> Result<DryRun, BootError> {
+    let Prepared { config, policy, warnings: config_warnings } = prepare(config).map_err(|(error, _)| error)?;
+    let mut plan = stages.dry_run(&BootContext::new(config).with_policy(policy))
+        .map_err(|e| BootError::Plan(ErrorDetail::invalid("cannot order boot stages").with_source(e)))?;
+    plan.config_warnings = config_warnings;
+    Ok(plan)
+}
+
+/// Apply `config`'s profile, validate the result and load its policy, collecting profile
+/// adjustments and validation warnings for the report. Validating after the profile means
+/// the checks see the controls that will actually be enforced.
+fn prepare(mut config: BootConfig) -> Result<Prepared, (BootError, Vec<String>)> {
+    let mut warnings = config.profile.apply(&mut config);
+    let validation = config.validate();
+    warnings.extend(validation.warnings().map(ToString::to_string));
+    if validation.has_errors() {
+        let error = BootError::Config(ErrorDetail::invalid(
+            format!("refusing to boot with an invalid config:\n{}", validation)));
+        return Err((error, warnings));
+    }
+    let policy = match &config.policy {
+        Some(cfg) => {
+            let store = Arc::new(PolicyStore::load(Path::new(&cfg.path)).map_err(|e| (e, warnings.clone()))?);
+            println!("📜 Policy: {} version {}", cfg.path, store.current().version);
+            store
+        }
+        None => Arc::new(PolicyStore::permissive()),
     };
-    security::validate_firmware()?;
-    security::enforce(&config.enforcement)?;
-    ai::initialize(&config.ai_models)?;
-    fs::mount(&config.filesystem)?;
-    compliance::apply(&config.compliance)?;
-    integrity::verify()?;
-    println!("\n📱 ADB-BOOT READY → Status: {}", integrity::status());
-    Ok(())
+    Ok(Prepared { config, policy, warnings })
+}
+
+/// PCR banks for `config`, mirrored into the TPM when one is configured and the `tpm`
+/// feature is built in. Dry runs leave the TPM alone.
+fn measurements(config: &BootConfig) -> Result<MeasuredBoot, BootError> {
+    let measured = config.measured_boot.as_ref();
+    let measurements = MeasuredBoot::new(&measured.map_or(vec![HashAlgorithm::Sha256], |m| m.banks.clone()));
+    #[cfg(feature = "tpm")]
+    if let Some(tpm) = measured.and_then(|m| m.tpm.as_ref()) {
+        let tpm = crate::tpm::Tpm::open(tpm).map_err(BootError::Integrity)?;
+        return measurements.with_tpm(tpm);
+    }
+    Ok(measurements)
+}
+
+struct Prepared {
+    config: BootConfig,
+    policy: Arc<PolicyStore>,
+    warnings: Vec<String>,
 }
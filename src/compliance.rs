@@ -1,8 +1,16 @@
+use crate::error::BootError;
 use crate::schema::ComplianceConfig;
-pub fn apply(cfg: &ComplianceConfig) -> Result<(), String> {
+pub fn apply(cfg: &ComplianceConfig) -> Result<(), BootError> {
     println!("⚖️ Compliance:");
     if cfg.gdpr { println!("   ✅ GDPR"); }
     if cfg.ccpa { println!("   ✅ CCPA"); }
     if cfg.audit_log { println!("   📜 Auditing"); }
     Ok(())
 }
+pub fn describe(cfg: &ComplianceConfig) -> Vec<String> {
+    let mut actions = Vec::new();
+    if cfg.gdpr { actions.push("enable GDPR controls".to_string()); }
+    if cfg.ccpa { actions.push("enable CCPA controls".to_string()); }
+    if cfg.audit_log { actions.push("start audit log".to_string()); }
+    actions
+}
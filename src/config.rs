@@ -0,0 +1,711 @@
+use crate::error::{BootError, ErrorDetail, ErrorKind};
+use crate::migrate::{self, Migration};
+use crate::schema::BootConfig;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Machine-wide config layer, applied on top of the built-in defaults.
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/mesh_sec_ai_boot/boot.toml";
+/// Prefix of environment overrides, e.g. `MESH_BOOT__FILESYSTEM__MOUNT_AT=/data`.
+pub const ENV_PREFIX: &str = "MESH_BOOT__";
+
+/// On-disk encodings accepted for a `BootConfig`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Json,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "toml" => Some(Format::Toml),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read.
+    Io { path: PathBuf, source: std::io::Error },
+    /// The extension is neither `.toml` nor `.json`.
+    UnsupportedFormat { path: PathBuf },
+    /// The document is malformed or does not match the schema at `field` (dotted path, `.` for the root).
+    Invalid { path: PathBuf, field: String, message: String },
+    /// An environment variable or CLI flag does not fit the schema.
+    Override { origin: Source, field: String, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(f, "{}: cannot read config: {}", path.display(), source)
+            }
+            ConfigError::UnsupportedFormat { path } => {
+                write!(f, "{}: unsupported config format (expected .toml or .json)", path.display())
+            }
+            ConfigError::Invalid { path, field, message } => {
+                write!(f, "{}: at `{}`: {}", path.display(), field, message)
+            }
+            ConfigError::Override { origin, field, message } => {
+                write!(f, "{}: at `{}`: {}", origin, field, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for BootError {
+    fn from(error: ConfigError) -> Self {
+        let detail = match &error {
+            ConfigError::Io { .. } => ErrorDetail::new(ErrorKind::Io, "cannot read boot config"),
+            _ => ErrorDetail::invalid("cannot load boot config"),
+        };
+        BootError::Config(detail.with_source(error))
+    }
+}
+
+/// Read and deserialize a `BootConfig`, choosing the parser from the file extension.
+pub fn load(path: &Path) -> Result<BootConfig, ConfigError> {
+    let (text, format) = read(path)?;
+    parse(&text, format, path)
+}
+
+/// Deserialize `text` as `format`, migrating older schema versions first; `origin` is only
+/// used to annotate errors.
+pub fn parse(text: &str, format: Format, origin: &Path) -> Result<BootConfig, ConfigError> {
+    let invalid = |field: String, message: String| ConfigError::Invalid {
+        path: origin.to_owned(),
+        field,
+        message,
+    };
+    let mut doc = parse_tree(text, format, origin)?;
+    let applied = migrate::migrate(&mut doc).map_err(|e| invalid(e.field, e.message))?;
+    if !applied.is_empty() {
+        return serde_path_to_error::deserialize(&doc)
+            .map_err(|e| invalid(e.path().to_string(), e.inner().to_string()));
+    }
+    // Current documents go through the typed deserializer so errors keep their line numbers.
+    match format {
+        Format::Toml => {
+            let de = toml::Deserializer::new(text);
+            serde_path_to_error::deserialize(de).map_err(|e| {
+                let inner = e.inner();
+                invalid(e.path().to_string(), toml_message(inner, text))
+            })
+        }
+        Format::Json => {
+            let mut de = serde_json::Deserializer::from_str(text);
+            serde_path_to_error::deserialize(&mut de)
+                .map_err(|e| invalid(e.path().to_string(), e.inner().to_string()))
+        }
+    }
+}
+
+/// Result of upgrading one config file with `migrate_file`.
+#[derive(Debug)]
+pub struct MigrationOutcome {
+    pub from_version: u32,
+    pub applied: Vec<&'static Migration>,
+    /// The upgraded document in the file's own format. TOML files keep their comments and
+    /// layout; only the keys a migration touched change.
+    pub rendered: String,
+    /// Copy of the original file, when `write` replaced it.
+    pub backup: Option<PathBuf>,
+}
+
+/// Upgrade a config file to `CURRENT_SCHEMA_VERSION`. With `write`, the file is rewritten in
+/// place after copying the original to `<file>.bak` (or `<file>.bak.1`, `.bak.2`, … when
+/// that exists); up-to-date files are never touched.
+pub fn migrate_file(path: &Path, write: bool) -> Result<MigrationOutcome, ConfigError> {
+    let (text, format) = read(path)?;
+    let original = parse_tree(&text, format, path)?;
+    let mut doc = original.clone();
+    let invalid = |field: String, message: String| ConfigError::Invalid { path: path.to_owned(), field, message };
+    let from_version = migrate::version_of(&doc).map_err(|e| invalid(e.field, e.message))?;
+    let applied = migrate::migrate(&mut doc).map_err(|e| invalid(e.field, e.message))?;
+    serde_path_to_error::deserialize::<_, BootConfig>(&doc)
+        .map_err(|e| invalid(e.path().to_string(), e.inner().to_string()))?;
+    let rendered = match format {
+        Format::Toml => {
+            let mut edited: toml_edit::DocumentMut = text.parse()
+                .map_err(|e: toml_edit::TomlError| invalid(".".to_string(), e.message().to_string()))?;
+            edit_toml(edited.as_table_mut(), &original, &doc);
+            edited.to_string()
+        }
+        Format::Json => serde_json::to_string_pretty(&doc).expect("config tree serializes to JSON") + "\n",
+    };
+
+    let mut backup = None;
+    if write && !applied.is_empty() {
+        let io = |source| ConfigError::Io { path: path.to_owned(), source };
+        let backup_path = create_backup(path).map_err(io)?;
+        std::fs::write(path, &rendered).map_err(io)?;
+        backup = Some(backup_path);
+    }
+    Ok(MigrationOutcome { from_version, applied, rendered, backup })
+}
+
+/// Copy `path` to the first of `<path>.bak`, `<path>.bak.1`, … that does not exist yet.
+fn create_backup(path: &Path) -> std::io::Result<PathBuf> {
+    for n in 0.. {
+        let mut name = path.as_os_str().to_owned();
+        name.push(if n == 0 { ".bak".to_string() } else { format!(".bak.{}", n) });
+        let backup = PathBuf::from(name);
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&backup) {
+            Ok(mut file) => {
+                std::io::copy(&mut std::fs::File::open(path)?, &mut file)?;
+                return Ok(backup);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("ran out of backup names for {}", path.display())
+}
+
+/// Carry what the migrations changed between `before` and `after` over to `table`, leaving
+/// every untouched key, comment and blank line as it was. Keys that replace removed ones
+/// (e.g. `cipher` and `kem` for `algorithm`) take the first removed key's place and comment.
+fn edit_toml(table: &mut toml_edit::Table, before: &Value, after: &Value) {
+    let (Some(before), Some(after)) = (before.as_object(), after.as_object()) else { return };
+    for (key, new) in after {
+        let Some(old) = before.get(key).filter(|old| *old != new) else { continue };
+        match table.get_mut(key) {
+            Some(toml_edit::Item::Table(child)) if old.is_object() && new.is_object() => edit_toml(child, old, new),
+            Some(item) => {
+                let decor = item.as_value().map(|v| v.decor().clone());
+                *item = toml_item(new);
+                if let (Some(decor), Some(value)) = (decor, item.as_value_mut()) {
+                    *value.decor_mut() = decor;
+                }
+            }
+            None => {}
+        }
+    }
+    let removed: Vec<&String> = before.keys().filter(|k| !after.contains_key(*k)).collect();
+    let added: Vec<(&String, &Value)> = after.iter().filter(|(k, _)| !before.contains_key(*k)).collect();
+    if removed.is_empty() {
+        for (key, value) in added {
+            table.insert(key, toml_item(value));
+        }
+        return;
+    }
+    let mut rebuilt = toml_edit::Table::new();
+    *rebuilt.decor_mut() = table.decor().clone();
+    rebuilt.set_position(table.position().unwrap_or_default());
+    rebuilt.set_implicit(table.is_implicit());
+    let mut added = Some(added);
+    let names: Vec<String> = table.iter().map(|(name, _)| name.to_string()).collect();
+    for name in names {
+        let Some((key, item)) = table.get_key_value(&name).map(|(k, i)| (k.clone(), i.clone())) else { continue };
+        if !removed.iter().any(|r| r.as_str() == key.get()) {
+            rebuilt.insert_formatted(&key, item);
+            continue;
+        }
+        // The first removed key hands its position and leading comment to the added keys.
+        for (i, (name, value)) in added.take().into_iter().flatten().enumerate() {
+            let mut new_key = toml_edit::Key::new(name.as_str());
+            if i == 0 {
+                *new_key.leaf_decor_mut() = key.leaf_decor().clone();
+            }
+            rebuilt.insert_formatted(&new_key, toml_item(value));
+        }
+    }
+    *table = rebuilt;
+}
+
+/// `value` as a TOML item: a table for objects, an inline value otherwise.
+fn toml_item(value: &Value) -> toml_edit::Item {
+    if value.is_object() {
+        let text = toml::to_string(value).expect("JSON object serializes to TOML");
+        let doc: toml_edit::DocumentMut = text.parse().expect("serialized TOML parses");
+        return toml_edit::Item::Table(doc.as_table().clone());
+    }
+    let text = toml::Value::try_from(value).map(|v| v.to_string()).expect("JSON value serializes to TOML");
+    toml_edit::Item::Value(text.parse().expect("serialized TOML value parses"))
+}
+
+fn read(path: &Path) -> Result<(String, Format), ConfigError> {
+    let format = Format::from_path(path)
+        .ok_or_else(|| ConfigError::UnsupportedFormat { path: path.to_owned() })?;
+    let text = std::fs::read_to_string(path)
+        .map_err(|source| ConfigError::Io { path: path.to_owned(), source })?;
+    Ok((text, format))
+}
+
+/// Parse `text` into an untyped tree without applying the schema.
+fn parse_tree(text: &str, format: Format, origin: &Path) -> Result<Value, ConfigError> {
+    let invalid = |message: String| ConfigError::Invalid { path: origin.to_owned(), field: ".".to_string(), message };
+    match format {
+        Format::Toml => toml::from_str(text).map_err(|e| invalid(toml_message(&e, text))),
+        Format::Json => serde_json::from_str(text).map_err(|e| invalid(e.to_string())),
+    }
+}
+
+pub(crate) fn toml_message(error: &toml::de::Error, text: &str) -> String {
+    match error.span() {
+        Some(span) => {
+            let (line, col) = line_col(text, span.start);
+            format!("{} (line {}, column {})", error.message(), line, col)
+        }
+        None => error.message().to_string(),
+    }
+}
+
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rfind('\n').map_or(before.len(), |nl| before.len() - nl - 1) + 1;
+    (line, col)
+}
+
+/// Where a resolved configuration value came from, lowest precedence first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    SystemFile(PathBuf),
+    UserFile(PathBuf),
+    /// Name of the environment variable.
+    Env(String),
+    /// The `--set KEY=VALUE` argument as given.
+    Cli(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => f.write_str("built-in default"),
+            Source::SystemFile(path) => write!(f, "system file {}", path.display()),
+            Source::UserFile(path) => write!(f, "user file {}", path.display()),
+            Source::Env(var) => write!(f, "env {}", var),
+            Source::Cli(arg) => write!(f, "cli --set {}", arg),
+        }
+    }
+}
+
+/// A `BootConfig` together with the layer that supplied each leaf value.
+#[derive(Debug, Clone)]
+pub struct ResolvedConfig {
+    pub config: BootConfig,
+    /// Keyed by dotted field path, e.g. `ai_models[0].name`.
+    pub provenance: BTreeMap<String, Source>,
+}
+
+impl ResolvedConfig {
+    /// One `key = value  (source)` line per resolved leaf, for `config explain`.
+    pub fn explain(&self) -> String {
+        let tree = serde_json::to_value(&self.config).unwrap_or(Value::Null);
+        let mut leaves = Vec::new();
+        collect_leaves(&tree, "", &mut leaves);
+        leaves.iter()
+            .map(|(path, value)| {
+                let source = self.provenance.get(path).map_or_else(|| "unknown".to_string(), Source::to_string);
+                format!("{} = {}  ({})", path, value, source)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Resolves a `BootConfig` from built-in defaults → system file → user file → env → CLI flags.
+pub struct Loader {
+    system_file: Option<PathBuf>,
+    user_file: Option<PathBuf>,
+    /// Set once a file layer is chosen explicitly; only the implicit defaults may be missing.
+    system_required: bool,
+    user_required: bool,
+    /// Only the `MESH_BOOT__*` variables, as given; checked for UTF-8 in `resolve`.
+    env: Vec<(OsString, OsString)>,
+    cli: Vec<String>,
+}
+
+impl Default for Loader {
+    fn default() -> Self {
+        Loader {
+            system_file: Some(PathBuf::from(SYSTEM_CONFIG_PATH)),
+            user_file: default_user_file(),
+            system_required: false,
+            user_required: false,
+            env: overrides(std::env::vars_os()),
+            cli: Vec::new(),
+        }
+    }
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override (or with `None`, skip) the system layer. Unlike the default
+    /// `SYSTEM_CONFIG_PATH`, a file given here must exist.
+    pub fn system_file(mut self, path: Option<PathBuf>) -> Self {
+        self.system_required = path.is_some();
+        self.system_file = path;
+        self
+    }
+
+    /// Override (or with `None`, skip) the user layer. Unlike the default
+    /// `~/.config/mesh_sec_ai_boot/boot.toml`, a file given here must exist.
+    pub fn user_file(mut self, path: Option<PathBuf>) -> Self {
+        self.user_required = path.is_some();
+        self.user_file = path;
+        self
+    }
+
+    /// Replace the process environment; only `MESH_BOOT__*` variables are consulted.
+    pub fn env<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<OsString>,
+        V: Into<OsString>,
+    {
+        self.env = overrides(vars.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Add a `KEY=VALUE` override, e.g. `filesystem.mount_at=/data` or `ai_models[0].isolated=false`.
+    pub fn set(mut self, assignment: impl Into<String>) -> Self {
+        self.cli.push(assignment.into());
+        self
+    }
+
+    pub fn resolve(self) -> Result<ResolvedConfig, ConfigError> {
+        let mut tree = serde_json::to_value(BootConfig::default()).expect("BootConfig serializes");
+        let mut provenance = BTreeMap::new();
+        record(&mut provenance, "", &tree, &Source::Default);
+
+        let system = self.system_file.map(|p| (p, self.system_required, Source::SystemFile as fn(PathBuf) -> Source));
+        let user = self.user_file.map(|p| (p, self.user_required, Source::UserFile as fn(PathBuf) -> Source));
+        for (path, required, source) in system.into_iter().chain(user) {
+            if !required && !path.exists() {
+                continue;
+            }
+            let layer = read_tree(&path)?;
+            merge(&mut tree, layer, "", &mut provenance, &source(path));
+        }
+
+        let mut env = self.env;
+        env.sort();
+        for (var, raw) in env {
+            let name = var.to_string_lossy().into_owned();
+            let field = name[ENV_PREFIX.len()..].split("__")
+                .map(|seg| seg.to_ascii_lowercase())
+                .collect::<Vec<_>>()
+                .join(".");
+            let not_utf8 = |what: &str| ConfigError::Override {
+                origin: Source::Env(name.clone()),
+                field: field.clone(),
+                message: format!("{} is not valid UTF-8", what),
+            };
+            if var.to_str().is_none() {
+                return Err(not_utf8("variable name"));
+            }
+            let raw = raw.into_string().map_err(|_| not_utf8("value"))?;
+            apply_override(&mut tree, &mut provenance, &field, &raw, Source::Env(name.clone()))?;
+        }
+        for arg in self.cli {
+            let (field, raw) = arg.split_once('=').ok_or_else(|| ConfigError::Override {
+                origin: Source::Cli(arg.clone()),
+                field: arg.clone(),
+                message: "expected KEY=VALUE".to_string(),
+            })?;
+            let (field, raw) = (field.trim().to_string(), raw.to_string());
+            apply_override(&mut tree, &mut provenance, &field, &raw, Source::Cli(arg))?;
+        }
+
+        let config = serde_path_to_error::deserialize(&tree).map_err(|e| {
+            let field = e.path().to_string();
+            let message = e.inner().to_string();
+            match blame(&provenance, &field) {
+                Some(Source::SystemFile(path)) | Some(Source::UserFile(path)) => {
+                    ConfigError::Invalid { path, field, message }
+                }
+                origin => ConfigError::Override { origin: origin.unwrap_or(Source::Default), field, message },
+            }
+        })?;
+        Ok(ResolvedConfig { config, provenance })
+    }
+}
+
+/// The `MESH_BOOT__*` entries of `vars`. Other variables may hold anything, so they are
+/// never decoded.
+fn overrides(vars: impl Iterator<Item = (OsString, OsString)>) -> Vec<(OsString, OsString)> {
+    vars.filter(|(var, _)| var.as_encoded_bytes().starts_with(ENV_PREFIX.as_bytes())).collect()
+}
+
+fn default_user_file() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("mesh_sec_ai_boot").join("boot.toml"))
+}
+
+/// Read one layer file as an untyped tree, upgraded to the current schema version.
+fn read_tree(path: &Path) -> Result<Value, ConfigError> {
+    let (text, format) = read(path)?;
+    let mut doc = parse_tree(&text, format, path)?;
+    migrate::migrate(&mut doc).map_err(|e| ConfigError::Invalid {
+        path: path.to_owned(),
+        field: e.field,
+        message: e.message,
+    })?;
+    Ok(doc)
+}
+
+/// Deep-merge `layer` into `base`; tables merge key by key, everything else (arrays included) replaces.
+fn merge(base: &mut Value, layer: Value, path: &str, provenance: &mut BTreeMap<String, Source>, source: &Source) {
+    match (base, layer) {
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                let child = join_key(path, &key);
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value, &child, provenance, source),
+                    None => {
+                        record(provenance, &child, &value, source);
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => {
+            record(provenance, path, &layer, source);
+            *base = layer;
+        }
+    }
+}
+
+fn apply_override(tree: &mut Value, provenance: &mut BTreeMap<String, Source>, field: &str, raw: &str, source: Source)
+    -> Result<(), ConfigError>
+{
+    let fail = |message: String| ConfigError::Override { origin: source.clone(), field: field.to_string(), message };
+    let mut slot = tree;
+    let mut path = String::new();
+    for seg in field.split('.').flat_map(|s| s.split('[')).filter(|s| !s.is_empty()) {
+        if let Some(index) = seg.strip_suffix(']') {
+            let index: usize = index.parse().map_err(|_| fail(format!("bad index `{}`", index)))?;
+            let len = slot.as_array().map(Vec::len);
+            slot = slot.get_mut(index).ok_or_else(|| match len {
+                Some(len) => fail(format!("index {} out of range (length {})", index, len)),
+                None => fail(format!("`{}` is not a list", path)),
+            })?;
+            path = format!("{}[{}]", path, index);
+        } else if let Ok(index) = seg.parse::<usize>() {
+            // Environment variables spell indices as plain segments: AI_MODELS__0__NAME.
+            slot = slot.get_mut(index).ok_or_else(|| fail(format!("index {} out of range", index)))?;
+            path = format!("{}[{}]", path, index);
+        } else {
+            let map = slot.as_object_mut().ok_or_else(|| fail(format!("`{}` is not a table", path)))?;
+            slot = map.entry(seg.to_string()).or_insert_with(|| Value::Object(Map::new()));
+            path = join_key(&path, seg);
+        }
+    }
+    let value = parse_scalar(raw);
+    record(provenance, &path, &value, &source);
+    *slot = value;
+    Ok(())
+}
+
+/// Interpret an override as a TOML value (`true`, `5`, `["a"]`), falling back to a bare string.
+fn parse_scalar(raw: &str) -> Value {
+    toml::from_str::<BTreeMap<String, Value>>(&format!("v = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("v"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+/// Attribute every leaf under `path` to `source`, forgetting whatever supplied it before.
+fn record(provenance: &mut BTreeMap<String, Source>, path: &str, value: &Value, source: &Source) {
+    provenance.retain(|key, _| !is_under(key, path));
+    let mut leaves = Vec::new();
+    collect_leaves(value, path, &mut leaves);
+    for (leaf, _) in leaves {
+        provenance.insert(leaf, source.clone());
+    }
+}
+
+/// The source of `field` or, for keys that were never recorded, of its nearest recorded relative.
+fn blame(provenance: &BTreeMap<String, Source>, field: &str) -> Option<Source> {
+    let field = if field == "." { "" } else { field };
+    let mut prefix = field;
+    loop {
+        if let Some(source) = provenance.get(prefix) {
+            return Some(source.clone());
+        }
+        if let Some((_, source)) = provenance.iter().find(|(key, _)| is_under(key, prefix)) {
+            return Some(source.clone());
+        }
+        match prefix.rfind(['.', '[']) {
+            Some(cut) => prefix = &prefix[..cut],
+            None if !prefix.is_empty() => prefix = "",
+            None => return None,
+        }
+    }
+}
+
+fn is_under(key: &str, path: &str) -> bool {
+    path.is_empty()
+        || key == path
+        || key.strip_prefix(path).is_some_and(|rest| rest.starts_with('.') || rest.starts_with('['))
+}
+
+fn join_key(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+fn collect_leaves(value: &Value, path: &str, out: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, child) in map {
+                collect_leaves(child, &join_key(path, key), out);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (i, child) in items.iter().enumerate() {
+                collect_leaves(child, &format!("{}[{}]", path, i), out);
+            }
+        }
+        leaf => out.push((path.to_string(), leaf.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/config").join(name)
+    }
+
+    #[test]
+    fn explicit_config_files_must_exist() {
+        let missing = PathBuf::from("/nonexistent/typo.toml");
+        let loader = || Loader::new().system_file(None).env(Vec::<(String, String)>::new());
+        let err = loader().user_file(Some(missing.clone())).resolve().unwrap_err();
+        assert!(matches!(err, ConfigError::Io { ref path, .. } if *path == missing), "{}", err);
+        assert!(loader().system_file(Some(missing)).resolve().is_err());
+        assert!(loader().user_file(Some(fixture("v2.toml"))).resolve().is_ok());
+    }
+
+    #[test]
+    fn implicit_config_files_may_be_missing() {
+        let loader = Loader { system_file: Some(PathBuf::from("/nonexistent/boot.toml")), ..Loader::new() };
+        assert_eq!(loader.env(Vec::<(String, String)>::new()).resolve().unwrap().config.schema_version, migrate::CURRENT_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn cli_overrides_env_overrides_files_override_defaults() {
+        let file = fixture("v2.toml");
+        let resolved = Loader::new()
+            .system_file(None)
+            .user_file(Some(file.clone()))
+            .env([
+                ("MESH_BOOT__ENFORCEMENT__HARDEN_KERNEL", "true"),
+                ("MESH_BOOT__COMPLIANCE__CCPA", "true"),
+                ("MESH_BOOT__AI_MODELS__0__VERSION", "v2.0.0"),
+                ("HOME", "/ignored"),
+            ])
+            .set("compliance.ccpa=false")
+            .resolve()
+            .unwrap();
+        let config = &resolved.config;
+        assert!(config.enforcement.harden_kernel);
+        assert!(!config.compliance.ccpa);
+        assert_eq!(config.ai_models[0].version, "v2.0.0");
+        assert!(config.enforcement.restrict_shell);
+
+        let source = |path: &str| resolved.provenance.get(path).cloned();
+        assert_eq!(source("enforcement.harden_kernel"), Some(Source::Env("MESH_BOOT__ENFORCEMENT__HARDEN_KERNEL".into())));
+        assert_eq!(source("ai_models[0].version"), Some(Source::Env("MESH_BOOT__AI_MODELS__0__VERSION".into())));
+        assert_eq!(source("compliance.ccpa"), Some(Source::Cli("compliance.ccpa=false".into())));
+        assert_eq!(source("enforcement.restrict_shell"), Some(Source::UserFile(file.clone())));
+        assert_eq!(source("enforcement.limits.max_open_files"), Some(Source::Default));
+
+        let explain = resolved.explain();
+        for line in [
+            "enforcement.harden_kernel = true  (env MESH_BOOT__ENFORCEMENT__HARDEN_KERNEL)".to_string(),
+            "compliance.ccpa = false  (cli --set compliance.ccpa=false)".to_string(),
+            format!("enforcement.restrict_shell = true  (user file {})", file.display()),
+        ] {
+            assert!(explain.lines().any(|l| l == line), "{} not in\n{}", line, explain);
+        }
+        assert!(!explain.contains("(unknown)"), "{}", explain);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_overrides_are_errors() {
+        use std::os::unix::ffi::OsStringExt;
+        let bad = || OsString::from_vec(b"\xff".to_vec());
+        let loader = || Loader::new().system_file(None);
+        let err = loader().env([(OsString::from("MESH_BOOT__COMPLIANCE__CCPA"), bad())]).resolve().unwrap_err();
+        assert!(matches!(err, ConfigError::Override { ref field, .. } if field == "compliance.ccpa"), "{}", err);
+        let name = OsString::from_vec(b"MESH_BOOT__\xff".to_vec());
+        assert!(loader().env([(name, OsString::from("1"))]).resolve().is_err());
+        // Anything outside MESH_BOOT__ is never looked at.
+        assert!(loader().env([(bad(), bad())]).resolve().is_ok());
+    }
+
+    #[test]
+    fn migrating_v1_toml_matches_the_v2_fixture() {
+        let outcome = migrate_file(&fixture("v1.toml"), false).unwrap();
+        assert_eq!(outcome.from_version, 1);
+        assert_eq!(outcome.applied.len(), 1);
+        assert!(outcome.backup.is_none());
+        let migrated = parse(&outcome.rendered, Format::Toml, Path::new("migrated.toml")).unwrap();
+        assert_eq!(migrated, load(&fixture("v2.toml")).unwrap());
+        assert_eq!(migrated, load(&fixture("v1.toml")).unwrap());
+    }
+
+    #[test]
+    fn migrating_toml_keeps_comments_and_writes_no_defaults() {
+        let rendered = migrate_file(&fixture("v1.toml"), false).unwrap().rendered;
+        let original = std::fs::read_to_string(fixture("v1.toml")).unwrap();
+        let expected = original
+            .replace("profile = \"staging\"", "profile = \"staging\"\nschema_version = 2")
+            .replace("algorithm = \"PQ-AES-256-GCM\"", "cipher = \"AES-256-GCM\"\nkem = \"ML-KEM-768\"");
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn migrating_v1_json_round_trips() {
+        let outcome = migrate_file(&fixture("v1.json"), false).unwrap();
+        let migrated = parse(&outcome.rendered, Format::Json, Path::new("migrated.json")).unwrap();
+        assert_eq!(migrated, load(&fixture("v1.json")).unwrap());
+        assert_eq!((migrated.filesystem.encryption.cipher.as_str(), migrated.filesystem.encryption.kem.as_str()),
+                   ("ChaCha20-Poly1305", "X25519"));
+        assert!(!outcome.rendered.contains("limits"));
+    }
+
+    #[test]
+    fn write_keeps_an_existing_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("boot.toml");
+        std::fs::copy(fixture("v1.toml"), &path).unwrap();
+        std::fs::write(dir.path().join("boot.toml.bak"), "earlier backup").unwrap();
+
+        let outcome = migrate_file(&path, true).unwrap();
+        assert_eq!(outcome.backup.as_deref(), Some(dir.path().join("boot.toml.bak.1").as_path()));
+        assert_eq!(std::fs::read_to_string(dir.path().join("boot.toml.bak")).unwrap(), "earlier backup");
+        assert_eq!(std::fs::read(dir.path().join("boot.toml.bak.1")).unwrap(), std::fs::read(fixture("v1.toml")).unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), outcome.rendered);
+
+        let again = migrate_file(&path, true).unwrap();
+        assert!(again.applied.is_empty() && again.backup.is_none());
+        assert!(!dir.path().join("boot.toml.bak.2").exists());
+    }
+}
@@ -0,0 +1,253 @@
+use std::error::Error;
+use std::fmt;
+
+type Source = Box<dyn Error + Send + Sync + 'static>;
+
+/// What went wrong, independent of which subsystem it went wrong in. Supervisors use
+/// this to decide whether a reboot is worth retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// An I/O or device error that may clear up on its own; safe to retry.
+    Io,
+    /// A check ran and said no (bad signature, hash mismatch); retrying will not help.
+    Rejected,
+    /// The configuration or input is wrong; fix it before retrying.
+    Invalid,
+    /// The platform lacks a required capability (kernel feature, TPM, ...).
+    Unsupported,
+    /// A bug or panic inside the boot crate.
+    Internal,
+}
+
+impl ErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Io => "io",
+            ErrorKind::Rejected => "rejected",
+            ErrorKind::Invalid => "invalid",
+            ErrorKind::Unsupported => "unsupported",
+            ErrorKind::Internal => "internal",
+        }
+    }
+}
+
+/// Message, kind and optional cause shared by every `BootError` variant.
+#[derive(Debug)]
+pub struct ErrorDetail {
+    pub kind: ErrorKind,
+    pub message: String,
+    source: Option<Source>,
+}
+
+impl ErrorDetail {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        ErrorDetail { kind, message: message.into(), source: None }
+    }
+
+    pub fn io(message: impl Into<String>, source: std::io::Error) -> Self {
+        Self::new(ErrorKind::Io, message).with_source(source)
+    }
+
+    pub fn rejected(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Rejected, message)
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Invalid, message)
+    }
+
+    pub fn unsupported(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Unsupported, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+
+    pub fn with_source<E: Into<Source>>(mut self, source: E) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+}
+
+/// Every way a boot can fail, by subsystem.
+#[derive(Debug)]
+pub enum BootError {
+    Config(ErrorDetail),
+    /// The stage graph could not be ordered.
+    Plan(ErrorDetail),
+    Firmware(ErrorDetail),
+    Enforcement(ErrorDetail),
+    Model(ErrorDetail),
+    Filesystem(ErrorDetail),
+    Compliance(ErrorDetail),
+    Integrity(ErrorDetail),
+    /// The security policy could not be loaded or replaced.
+    Policy(ErrorDetail),
+    /// A quote could not be produced, fetched or verified.
+    Attestation(ErrorDetail),
+    /// The recovery shell is missing, not permitted by policy or could not be started.
+    Shell(ErrorDetail),
+    /// Raised by a site-specific stage registered outside this crate.
+    Stage(&'static str, ErrorDetail),
+}
+
+impl BootError {
+    pub fn detail(&self) -> &ErrorDetail {
+        match self {
+            BootError::Config(d)
+            | BootError::Plan(d)
+            | BootError::Firmware(d)
+            | BootError::Enforcement(d)
+            | BootError::Model(d)
+            | BootError::Filesystem(d)
+            | BootError::Compliance(d)
+            | BootError::Integrity(d)
+            | BootError::Policy(d)
+            | BootError::Attestation(d)
+            | BootError::Shell(d)
+            | BootError::Stage(_, d) => d,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.detail().kind
+    }
+
+    pub fn subsystem(&self) -> &'static str {
+        match self {
+            BootError::Config(_) => "config",
+            BootError::Plan(_) => "plan",
+            BootError::Firmware(_) => "firmware",
+            BootError::Enforcement(_) => "enforcement",
+            BootError::Model(_) => "model",
+            BootError::Filesystem(_) => "filesystem",
+            BootError::Compliance(_) => "compliance",
+            BootError::Integrity(_) => "integrity",
+            BootError::Policy(_) => "policy",
+            BootError::Attestation(_) => "attestation",
+            BootError::Shell(_) => "shell",
+            BootError::Stage(..) => "stage",
+        }
+    }
+
+    /// Stable, machine-matchable identifier such as `integrity.rejected` or `filesystem.io`.
+    pub fn code(&self) -> String {
+        format!("{}.{}", self.subsystem(), self.kind().as_str())
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.kind() == ErrorKind::Io
+    }
+
+    /// Process exit status for supervisors, following `sysexits.h` where it has a match:
+    /// 75 (EX_TEMPFAIL) means retry, 65 (EX_DATAERR) a rejected integrity or signature check.
+    /// The recovery shell uses 77 (EX_NOPERM) when policy refuses it and 71 (EX_OSERR)
+    /// when it cannot be started.
+    pub fn exit_code(&self) -> u8 {
+        match (self, self.kind()) {
+            (BootError::Shell(_), ErrorKind::Rejected) => 77,
+            (BootError::Shell(_), ErrorKind::Io) => 71,
+            (_, ErrorKind::Io) => 75,
+            (_, ErrorKind::Rejected) => 65,
+            (_, ErrorKind::Invalid) => 78,
+            (_, ErrorKind::Unsupported) => 69,
+            (_, ErrorKind::Internal) => 70,
+        }
+    }
+
+    /// This error's message followed by the messages of its sources, outermost first.
+    pub fn chain(&self) -> Vec<String> {
+        let mut chain = vec![self.to_string()];
+        let mut source = self.source();
+        while let Some(cause) = source {
+            chain.push(cause.to_string());
+            source = cause.source();
+        }
+        chain
+    }
+}
+
+impl fmt::Display for BootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BootError::Stage(stage, d) => write!(f, "[{}] {}: {}", self.code(), stage, d.message),
+            _ => write!(f, "[{}] {}", self.code(), self.detail().message),
+        }
+    }
+}
+
+impl Error for BootError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.detail().source.as_deref().map(|s| s as &(dyn Error + 'static))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [ErrorKind; 5] = [ErrorKind::Io, ErrorKind::Rejected, ErrorKind::Invalid, ErrorKind::Unsupported, ErrorKind::Internal];
+
+    fn every_subsystem(kind: ErrorKind) -> Vec<BootError> {
+        let d = || ErrorDetail::new(kind, "boom");
+        vec![
+            BootError::Config(d()),
+            BootError::Plan(d()),
+            BootError::Firmware(d()),
+            BootError::Enforcement(d()),
+            BootError::Model(d()),
+            BootError::Filesystem(d()),
+            BootError::Compliance(d()),
+            BootError::Integrity(d()),
+            BootError::Policy(d()),
+            BootError::Attestation(d()),
+            BootError::Shell(d()),
+            BootError::Stage("site", d()),
+        ]
+    }
+
+    #[test]
+    fn exit_codes_follow_sysexits() {
+        for (kind, code) in KINDS.into_iter().zip([75, 65, 78, 69, 70]) {
+            for error in every_subsystem(kind) {
+                let expected = match (&error, kind) {
+                    (BootError::Shell(_), ErrorKind::Rejected) => 77,
+                    (BootError::Shell(_), ErrorKind::Io) => 71,
+                    _ => code,
+                };
+                assert_eq!(error.exit_code(), expected, "{}", error);
+                assert_eq!(error.is_retryable(), kind == ErrorKind::Io, "{}", error);
+            }
+        }
+    }
+
+    #[test]
+    fn codes_name_the_subsystem_and_kind() {
+        let subsystems = ["config", "plan", "firmware", "enforcement", "model", "filesystem", "compliance",
+            "integrity", "policy", "attestation", "shell", "stage"];
+        for kind in KINDS {
+            let codes: Vec<String> = every_subsystem(kind).iter().map(BootError::code).collect();
+            let expected: Vec<String> = subsystems.iter().map(|s| format!("{}.{}", s, kind.as_str())).collect();
+            assert_eq!(codes, expected);
+        }
+        assert_eq!(KINDS.map(ErrorKind::as_str), ["io", "rejected", "invalid", "unsupported", "internal"]);
+        assert_eq!(BootError::Integrity(ErrorDetail::rejected("PCR mismatch")).to_string(), "[integrity.rejected] PCR mismatch");
+        assert_eq!(BootError::Stage("mount", ErrorDetail::internal("oops")).to_string(), "[stage.internal] mount: oops");
+    }
+
+    #[test]
+    fn chains_list_every_source_outermost_first() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+        let inner = BootError::Firmware(ErrorDetail::io("cannot read image", io));
+        let outer = BootError::Config(ErrorDetail::invalid("bad firmware section").with_source(inner));
+        assert_eq!(outer.chain(), [
+            "[config.invalid] bad firmware section",
+            "[firmware.io] cannot read image",
+            "no such file",
+        ]);
+        assert_eq!(outer.kind(), ErrorKind::Invalid);
+        assert!(BootError::Plan(ErrorDetail::invalid("cycle")).source().is_none());
+    }
+}
@@ -0,0 +1,1185 @@
+//! Directory fingerprints: one digest over everything under a root, formerly computed
+//! only by the standalone `kernel_fingerprint` binary. The fingerprint is the root of a
+//! Merkle tree (see `merkle`) with one leaf per entry in path order, so `Manifest::prove`
+//! can show that one entry belongs to a fingerprinted tree without revealing the rest.
+//! Sinks publish the result (a state file, a per-file `Manifest`, an audit log line, a
+//! GPG-encrypted copy, IPFS) in the order they were added; `Manifest::diff` names the
+//! entries behind a changed fingerprint.
+//!
+//! # Format
+//!
+//! Every entry under the root, the root itself included as `.`, is one leaf. Symlinks
+//! are recorded, never followed. A leaf is the concatenation of, integers big-endian:
+//!
+//! | field    | encoding                                                         |
+//! |----------|------------------------------------------------------------------|
+//! | version  | `u8`, `FORMAT_VERSION`                                           |
+//! | kind     | `u8`, `EntryKind::code`                                          |
+//! | path     | `u32` length, then the path relative to the root, `/`-separated  |
+//! | mode     | `u32`, permission bits including setuid, setgid and sticky       |
+//! | owner    | `u8` 0, or `u8` 1 then `u32` uid and `u32` gid                   |
+//! | body     | `u32` length, then the body                                      |
+//!
+//! The body is the raw content digest of a regular file, the target of a symlink as
+//! stored, the `u64` device number of a character or block device, and empty for
+//! directories, FIFOs and sockets. Special files are never opened: reading a FIFO
+//! blocks and reading a device need not end. Ownership is recorded only when asked
+//! for (`Fingerprinter::ownership`), as uid and gid differ between otherwise identical
+//! machines. Timestamps are not part of the fingerprint. Paths and symlink targets are
+//! hashed byte for byte; where they are not UTF-8 the manifest shows them after lossy
+//! conversion and keeps the bytes in hex (`raw_path`, `raw_target`).
+//!
+//! An entry that cannot be examined (not stat-able, a directory that cannot be listed,
+//! a file that cannot be read) does not stop the run: it becomes an `Unreadable` leaf
+//! with mode 0, no owner and an empty body, so the fingerprint still matches only a tree
+//! in the same state, and the reason is kept in the manifest. Only an unreadable root is
+//! an error.
+
+use crate::error::{BootError, ErrorDetail, ErrorKind};
+use crate::schema::HashAlgorithm;
+use crate::{measure, merkle};
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// Where the standalone tool has always written the kernel fingerprint.
+pub const STATE_HASH_PATH: &str = "/secure/state_hashes/vsc_master_kernel_fingerprint.sha512";
+pub const AUDIT_LOG_PATH: &str = "/secure/logs/kernel_resource_enforcement.log";
+/// First byte of every leaf; bump when the leaf encoding changes.
+pub const FORMAT_VERSION: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FingerprintResult {
+    pub root: PathBuf,
+    pub algorithm: HashAlgorithm,
+    /// Hex.
+    pub fingerprint: String,
+    /// Regular files, and the bytes in them.
+    pub files: usize,
+    pub bytes: u64,
+    /// Entries recorded as `EntryKind::Unreadable`.
+    pub unreadable: usize,
+    /// Files whose digest came from the `HashCache` instead of being read.
+    pub cache_hits: usize,
+    pub computed_at: DateTime<Utc>,
+    /// What each sink did, in order.
+    pub published: Vec<Published>,
+    /// Every entry, in fingerprint order; see `manifest`.
+    #[serde(skip)]
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl FingerprintResult {
+    pub fn manifest(&self) -> Manifest {
+        Manifest {
+            version: Manifest::VERSION,
+            root: self.root.clone(),
+            algorithm: self.algorithm,
+            fingerprint: self.fingerprint.clone(),
+            computed_at: self.computed_at,
+            entries: self.entries.clone(),
+        }
+    }
+
+    /// Entries that could not be examined, with the reason.
+    pub fn unreadable(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.entries.iter().filter(|e| e.kind == EntryKind::Unreadable)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Fifo,
+    Socket,
+    CharDevice,
+    BlockDevice,
+    /// Could not be examined; see `ManifestEntry::error`.
+    Unreadable,
+}
+
+impl EntryKind {
+    /// The kind byte of a leaf.
+    pub fn code(self) -> u8 {
+        match self {
+            EntryKind::File => 1,
+            EntryKind::Directory => 2,
+            EntryKind::Symlink => 3,
+            EntryKind::Fifo => 4,
+            EntryKind::Socket => 5,
+            EntryKind::CharDevice => 6,
+            EntryKind::BlockDevice => 7,
+            EntryKind::Unreadable => 0xff,
+        }
+    }
+
+    #[cfg(unix)]
+    fn of(file_type: fs::FileType) -> Option<Self> {
+        use std::os::unix::fs::FileTypeExt;
+        Some(match file_type {
+            t if t.is_file() => EntryKind::File,
+            t if t.is_dir() => EntryKind::Directory,
+            t if t.is_symlink() => EntryKind::Symlink,
+            t if t.is_fifo() => EntryKind::Fifo,
+            t if t.is_socket() => EntryKind::Socket,
+            t if t.is_char_device() => EntryKind::CharDevice,
+            t if t.is_block_device() => EntryKind::BlockDevice,
+            _ => return None,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn of(file_type: fs::FileType) -> Option<Self> {
+        Some(match file_type {
+            t if t.is_file() => EntryKind::File,
+            t if t.is_dir() => EntryKind::Directory,
+            t if t.is_symlink() => EntryKind::Symlink,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EntryKind::File => "file",
+            EntryKind::Directory => "directory",
+            EntryKind::Symlink => "symlink",
+            EntryKind::Fifo => "fifo",
+            EntryKind::Socket => "socket",
+            EntryKind::CharDevice => "character device",
+            EntryKind::BlockDevice => "block device",
+            EntryKind::Unreadable => "unreadable",
+        })
+    }
+}
+
+impl ManifestEntry {
+    /// `path` (at `file` on disk) as described by its `symlink_metadata`, without
+    /// reading a regular file's contents; see `hashed`.
+    pub fn of(path: String, file: &Path, metadata: &fs::Metadata, ownership: bool) -> Self {
+        let Some(kind) = EntryKind::of(metadata.file_type()) else {
+            return ManifestEntry::unreadable(path, "unsupported file type".to_string());
+        };
+        let target = match kind {
+            EntryKind::Symlink => match fs::read_link(file) {
+                Ok(target) => Some(display_and_raw(target.as_os_str().as_encoded_bytes())),
+                Err(e) => return ManifestEntry::unreadable(path, e.to_string()),
+            },
+            _ => None,
+        };
+        let (target, raw_target) = target.unzip();
+        let (uid, gid) = match ownership {
+            true => owner_of(metadata).unzip(),
+            false => (None, None),
+        };
+        ManifestEntry {
+            path,
+            raw_path: None,
+            kind,
+            size: metadata.len(),
+            mode: mode_of(metadata),
+            uid,
+            gid,
+            mtime: metadata.modified().map(DateTime::<Utc>::from).unwrap_or_default(),
+            digest: String::new(),
+            target,
+            raw_target: raw_target.flatten(),
+            rdev: matches!(kind, EntryKind::CharDevice | EntryKind::BlockDevice).then(|| rdev_of(metadata)),
+            error: None,
+        }
+    }
+
+    /// `of` for the entry at `file`, with its contents hashed if it is a regular file.
+    pub fn read(path: String, file: &Path, algorithm: HashAlgorithm, ownership: bool) -> Self {
+        match fs::symlink_metadata(file) {
+            Ok(metadata) => ManifestEntry::of(path, file, &metadata, ownership).hashed(file, algorithm),
+            Err(e) => ManifestEntry::unreadable(path, e.to_string()),
+        }
+    }
+
+    pub fn unreadable(path: String, error: String) -> Self {
+        ManifestEntry {
+            path,
+            raw_path: None,
+            kind: EntryKind::Unreadable,
+            size: 0,
+            mode: 0,
+            uid: None,
+            gid: None,
+            mtime: DateTime::<Utc>::default(),
+            digest: String::new(),
+            target: None,
+            raw_target: None,
+            rdev: None,
+            error: Some(error),
+        }
+    }
+
+    /// Fill in the digest of a regular file from `file`; a read error makes the entry
+    /// unreadable. Other kinds are returned unchanged.
+    fn hashed(self, file: &Path, algorithm: HashAlgorithm) -> Self {
+        if self.kind != EntryKind::File {
+            return self;
+        }
+        match measure::hash_file(&[algorithm], file) {
+            Ok(digest) => ManifestEntry { digest: hex::encode(&digest[0]), ..self },
+            Err(e) => ManifestEntry::unreadable(self.path, e.to_string()),
+        }
+    }
+
+    fn owner(&self) -> Option<String> {
+        Some(format!("{}:{}", self.uid?, self.gid?))
+    }
+
+    /// The path as hashed; `None` when `raw_path` is not hex of non-UTF-8 bytes that
+    /// read as `path`.
+    pub fn path_bytes(&self) -> Option<Cow<'_, [u8]>> {
+        stored_bytes(&self.path, self.raw_path.as_deref())
+    }
+}
+
+/// `bytes` as lossy text, and in hex when that text is not the bytes themselves.
+fn display_and_raw(bytes: &[u8]) -> (String, Option<String>) {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_string(), None),
+        Err(_) => (String::from_utf8_lossy(bytes).into_owned(), Some(hex::encode(bytes))),
+    }
+}
+
+/// Undo `display_and_raw`. A `raw` that is UTF-8 or reads as anything but `text` is
+/// refused, so a manifest never shows one name while hashing another.
+fn stored_bytes<'a>(text: &'a str, raw: Option<&str>) -> Option<Cow<'a, [u8]>> {
+    match raw {
+        None => Some(Cow::Borrowed(text.as_bytes())),
+        Some(raw) => hex::decode(raw).ok()
+            .filter(|bytes| std::str::from_utf8(bytes).is_err() && String::from_utf8_lossy(bytes) == text)
+            .map(Cow::Owned),
+    }
+}
+
+/// One leaf of the fingerprint; see the module docs for which fields are hashed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntry {
+    /// Relative to the root, `/`-separated; `.` for the root. Lossy where the name is
+    /// not UTF-8, for display; see `raw_path`.
+    pub path: String,
+    /// Hex of the path as stored, only where it is not UTF-8. This is what is hashed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_path: Option<String>,
+    pub kind: EntryKind,
+    pub size: u64,
+    /// Permission bits, e.g. `0o644`; 0 where the platform has none.
+    pub mode: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    pub mtime: DateTime<Utc>,
+    /// Hex, in the manifest's algorithm; regular files only.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub digest: String,
+    /// Lossy where the target is not UTF-8; see `raw_target`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Hex of the target as stored, only where it is not UTF-8.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_target: Option<String>,
+    /// Device number of a character or block device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rdev: Option<u64>,
+    /// Why an unreadable entry could not be examined; not hashed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The per-file record behind a fingerprint, written by `Fingerprinter::manifest`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub version: u32,
+    pub root: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub fingerprint: String,
+    pub computed_at: DateTime<Utc>,
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// How a tree changed between two manifests. Only what the fingerprint covers counts;
+/// a file touched without changing is not reported.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ManifestDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Kind, content, symlink target or device number differs.
+    pub modified: Vec<String>,
+    /// Path, old mode, new mode.
+    pub permissions: Vec<(String, u32, u32)>,
+    /// Path, old and new `uid:gid`; only where both manifests record ownership.
+    pub owners: Vec<(String, String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Published {
+    pub sink: &'static str,
+    /// A path, CID or similar the sink wrote the fingerprint to.
+    pub location: String,
+}
+
+/// Somewhere a computed fingerprint is published. `earlier` holds what the sinks
+/// before this one published, e.g. the file a later sink encrypts.
+pub trait FingerprintSink: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn publish(&self, result: &FingerprintResult, earlier: &[Published]) -> Result<String, ErrorDetail>;
+}
+
+/// Computes the fingerprint of `root` and hands it to each configured sink.
+pub struct Fingerprinter {
+    root: PathBuf,
+    algorithm: HashAlgorithm,
+    cache: Option<PathBuf>,
+    ownership: bool,
+    sinks: Vec<Box<dyn FingerprintSink>>,
+}
+
+impl Fingerprinter {
+    /// SHA-512 over `root`, published nowhere.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Fingerprinter { root: root.into(), algorithm: HashAlgorithm::Sha512, cache: None, ownership: false, sinks: Vec::new() }
+    }
+
+    pub fn algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Write the hex fingerprint to `path`, mode 0600.
+    pub fn output(self, path: impl Into<PathBuf>) -> Self {
+        self.sink(FileSink { path: path.into() })
+    }
+
+    /// Append a timestamped line naming the fingerprint and where it was published.
+    pub fn audit_log(self, path: impl Into<PathBuf>) -> Self {
+        self.sink(AuditLogSink { path: path.into() })
+    }
+
+    pub fn sink(mut self, sink: impl FingerprintSink + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Include each entry's uid and gid in the fingerprint.
+    pub fn ownership(mut self, ownership: bool) -> Self {
+        self.ownership = ownership;
+        self
+    }
+
+    /// Reuse digests from the `HashCache` at `path` for files whose inode, size, mtime and
+    /// ctime are unchanged, and rewrite it afterwards. Without a cache every file is read.
+    /// Anyone who can write the cache can vouch for a modified file, so integrity checks
+    /// never use one.
+    pub fn cache(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cache = Some(path.into());
+        self
+    }
+
+    /// Write the per-file `Manifest` as JSON to `path`.
+    pub fn manifest(self, path: impl Into<PathBuf>) -> Self {
+        self.sink(ManifestSink { path: path.into() })
+    }
+
+    /// Hash the tree without publishing anything. Files are hashed in parallel.
+    pub fn compute(&self) -> Result<FingerprintResult, ErrorDetail> {
+        let started = Utc::now();
+        fs::metadata(&self.root).map_err(|e| ErrorDetail::io(format!("cannot fingerprint {}", self.root.display()), e))?;
+        // A directory that cannot be listed is walked as a directory, then reported as an
+        // error under the same path; it is recorded once, as unreadable.
+        let mut found = BTreeMap::new();
+        for entry in walkdir::WalkDir::new(&self.root) {
+            match entry {
+                Ok(entry) => found.insert(relative_path(&self.root, entry.path()), Ok(entry)),
+                Err(e) => found.insert(relative_path(&self.root, e.path().unwrap_or(&self.root)),
+                                       Err(e.io_error().map_or_else(|| e.to_string(), ToString::to_string))),
+            };
+        }
+        let found: Vec<_> = found.into_iter().collect();
+        let cache = self.cache.as_deref().map(|p| HashCache::load(p, self.algorithm)).unwrap_or_default();
+
+        let described: Vec<_> = found.into_par_iter()
+            .map(|(raw, entry)| {
+                let (path, raw_path) = display_and_raw(&raw);
+                let (entry, stamp, hit) = match entry {
+                    Ok(entry) => self.describe(path, &entry, &cache),
+                    Err(error) => (ManifestEntry::unreadable(path, error), None, false),
+                };
+                (ManifestEntry { raw_path, ..entry }, stamp, hit)
+            })
+            .collect();
+
+        let cache_hits = described.iter().filter(|(_, _, hit)| *hit).count();
+        if let Some(path) = &self.cache {
+            let stamps = described.iter().filter_map(|(e, stamp, _)| stamp.map(|s| (s, e.digest.clone())));
+            HashCache::new(self.algorithm, stamps, started).save(path)?;
+        }
+        let entries: Vec<ManifestEntry> = described.into_iter().map(|(e, _, _)| e).collect();
+        let files = entries.iter().filter(|e| e.kind == EntryKind::File);
+        let leaves = leaf_hashes(self.algorithm, &entries)?;
+        Ok(FingerprintResult {
+            root: self.root.clone(),
+            algorithm: self.algorithm,
+            fingerprint: hex::encode(merkle::root(self.algorithm, &leaves)),
+            files: files.clone().count(),
+            bytes: files.map(|e| e.size).sum(),
+            unreadable: entries.iter().filter(|e| e.kind == EntryKind::Unreadable).count(),
+            cache_hits,
+            computed_at: Utc::now(),
+            published: Vec::new(),
+            entries,
+        })
+    }
+
+    /// The entry for one walked path, its cache stamp if it is a regular file, and
+    /// whether its digest came from the cache.
+    fn describe(&self, path: String, entry: &walkdir::DirEntry, cache: &HashCache) -> (ManifestEntry, Option<FileStamp>, bool) {
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => return (ManifestEntry::unreadable(path, e.to_string()), None, false),
+        };
+        let described = ManifestEntry::of(path, entry.path(), &metadata, self.ownership);
+        if described.kind != EntryKind::File {
+            return (described, None, false);
+        }
+        let stamp = FileStamp::of(&metadata);
+        match stamp.and_then(|s| cache.get(&s)) {
+            Some(digest) => (ManifestEntry { digest: digest.clone(), ..described }, stamp, true),
+            None => (described.hashed(entry.path(), self.algorithm), stamp, false),
+        }
+    }
+
+    /// Hash the tree and publish the result through every sink, stopping at the first
+    /// that fails.
+    pub fn run(&self) -> Result<FingerprintResult, BootError> {
+        let mut result = self.compute().map_err(BootError::Integrity)?;
+        for sink in &self.sinks {
+            let location = sink.publish(&result, &result.published).map_err(|mut e| {
+                e.message = format!("fingerprint sink `{}`: {}", sink.name(), e.message);
+                BootError::Integrity(e)
+            })?;
+            result.published.push(Published { sink: sink.name(), location });
+        }
+        Ok(result)
+    }
+}
+
+/// Evidence that one entry is part of a fingerprinted tree: the entry as hashed and the
+/// audit path from its leaf to the fingerprint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InclusionProof {
+    pub algorithm: HashAlgorithm,
+    pub entry: ManifestEntry,
+    pub leaf_index: u64,
+    /// Checked only against the audit path; the fingerprint does not commit to it.
+    pub tree_size: u64,
+    /// Hex sibling hashes, bottom first.
+    pub audit_path: Vec<String>,
+    /// The fingerprint the proof was made against.
+    pub fingerprint: String,
+}
+
+/// The leaf for one entry, in the encoding the module docs describe.
+pub fn leaf_data(algorithm: HashAlgorithm, entry: &ManifestEntry) -> Result<Vec<u8>, ErrorDetail> {
+    let malformed = |what: &str| ErrorDetail::invalid(format!("malformed {} for {}", what, entry.path));
+    let body = match entry.kind {
+        EntryKind::File => hex::decode(&entry.digest).ok().filter(|d| d.len() == algorithm.digest_len())
+            .ok_or_else(|| malformed(&format!("{} digest", algorithm)))?,
+        EntryKind::Symlink => entry.target.as_deref().and_then(|t| stored_bytes(t, entry.raw_target.as_deref()))
+            .ok_or_else(|| malformed("symlink target"))?.into_owned(),
+        EntryKind::CharDevice | EntryKind::BlockDevice => entry.rdev.ok_or_else(|| malformed("device number"))?.to_be_bytes().to_vec(),
+        EntryKind::Directory | EntryKind::Fifo | EntryKind::Socket | EntryKind::Unreadable => Vec::new(),
+    };
+    let mut data = vec![FORMAT_VERSION, entry.kind.code()];
+    push_prefixed(&mut data, &entry.path_bytes().ok_or_else(|| malformed("raw path"))?);
+    data.extend(entry.mode.to_be_bytes());
+    match (entry.uid, entry.gid) {
+        (None, None) => data.push(0),
+        (Some(uid), Some(gid)) => {
+            data.push(1);
+            data.extend(uid.to_be_bytes());
+            data.extend(gid.to_be_bytes());
+        }
+        _ => return Err(malformed("ownership")),
+    }
+    push_prefixed(&mut data, &body);
+    Ok(data)
+}
+
+/// `u32` big-endian length, then `bytes`; keeps the variable-length fields unambiguous.
+fn push_prefixed(data: &mut Vec<u8>, bytes: &[u8]) {
+    data.extend(u32::try_from(bytes.len()).expect("field shorter than 4 GiB").to_be_bytes());
+    data.extend(bytes);
+}
+
+fn leaf_hashes(algorithm: HashAlgorithm, entries: &[ManifestEntry]) -> Result<Vec<Vec<u8>>, ErrorDetail> {
+    entries.iter()
+        .map(|e| Ok(merkle::leaf_hash(algorithm, &leaf_data(algorithm, e)?)))
+        .collect()
+}
+
+/// `path` relative to `root` as stored, `/`-separated; `.` for the root itself.
+fn relative_path(root: &Path, path: &Path) -> Vec<u8> {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    if relative.as_os_str().is_empty() {
+        return b".".to_vec();
+    }
+    relative.components().map(|c| c.as_os_str().as_encoded_bytes()).collect::<Vec<_>>().join(&b'/')
+}
+
+impl Manifest {
+    pub const VERSION: u32 = 4;
+
+    pub fn load(path: &Path) -> Result<Self, ErrorDetail> {
+        let bytes = fs::read(path)
+            .map_err(|e| ErrorDetail::io(format!("cannot read manifest {}", path.display()), e))?;
+        Self::parse(&bytes, path)
+    }
+
+    /// Parse and check a manifest already read from `path`, e.g. after verifying its signature.
+    pub fn parse(bytes: &[u8], path: &Path) -> Result<Self, ErrorDetail> {
+        let manifest: Manifest = serde_json::from_slice(bytes)
+            .map_err(|e| ErrorDetail::invalid(format!("malformed manifest {}", path.display())).with_source(e))?;
+        if manifest.version != Self::VERSION {
+            return Err(ErrorDetail::invalid(format!(
+                "{}: unsupported manifest version {}; re-record it with `kernel_fingerprint --manifest`",
+                path.display(), manifest.version)));
+        }
+        if !manifest.merkle_root()?.eq_ignore_ascii_case(&manifest.fingerprint) {
+            return Err(ErrorDetail::rejected(format!("{}: entries do not hash to the recorded fingerprint", path.display())));
+        }
+        Ok(manifest)
+    }
+
+    /// Hex Merkle root over the entries as listed.
+    pub fn merkle_root(&self) -> Result<String, ErrorDetail> {
+        Ok(hex::encode(merkle::root(self.algorithm, &leaf_hashes(self.algorithm, &self.entries)?)))
+    }
+
+    /// Inclusion proof for the entry at `path` (relative, `/`-separated; `.` for the root).
+    pub fn prove(&self, path: &str) -> Result<InclusionProof, ErrorDetail> {
+        let index = self.entries.iter().position(|e| e.path == path)
+            .ok_or_else(|| ErrorDetail::invalid(format!("{} is not in the manifest of {}", path, self.root.display())))?;
+        if self.entries[index + 1..].iter().any(|e| e.path == path) {
+            return Err(ErrorDetail::invalid(format!("{} names more than one entry of {} once invalid UTF-8 is replaced",
+                                                    path, self.root.display())));
+        }
+        let leaves = leaf_hashes(self.algorithm, &self.entries)?;
+        Ok(InclusionProof {
+            algorithm: self.algorithm,
+            entry: self.entries[index].clone(),
+            leaf_index: index as u64,
+            tree_size: leaves.len() as u64,
+            audit_path: merkle::audit_path(self.algorithm, &leaves, index).iter().map(hex::encode).collect(),
+            fingerprint: self.fingerprint.clone(),
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Manifest serializes to JSON")
+    }
+
+    /// What changed from `self` (the baseline) to `current`. Digests in different
+    /// algorithms cannot be compared and are an error.
+    pub fn diff(&self, current: &Manifest) -> Result<ManifestDiff, ErrorDetail> {
+        if self.algorithm != current.algorithm {
+            return Err(ErrorDetail::invalid(format!(
+                "cannot compare a {} manifest with a {} manifest", self.algorithm, current.algorithm)));
+        }
+        // Keyed by the stored name, so entries whose names only differ in invalid UTF-8 stay apart.
+        fn by_name(entries: &[ManifestEntry]) -> BTreeMap<(&str, Option<&str>), &ManifestEntry> {
+            entries.iter().map(|e| ((e.path.as_str(), e.raw_path.as_deref()), e)).collect()
+        }
+        let (old, new) = (by_name(&self.entries), by_name(&current.entries));
+        let mut diff = ManifestDiff::default();
+        for (name @ (path, _), before) in &old {
+            match new.get(name) {
+                None => diff.removed.push(path.to_string()),
+                Some(after) => {
+                    if before.kind != after.kind || before.size != after.size || !before.digest.eq_ignore_ascii_case(&after.digest)
+                        || before.target != after.target || before.raw_target != after.raw_target || before.rdev != after.rdev {
+                        diff.modified.push(path.to_string());
+                    }
+                    if before.mode != after.mode {
+                        diff.permissions.push((path.to_string(), before.mode, after.mode));
+                    }
+                    if let (Some(old), Some(new)) = (before.owner(), after.owner()) {
+                        if old != new {
+                            diff.owners.push((path.to_string(), old, new));
+                        }
+                    }
+                }
+            }
+        }
+        diff.added = new.keys().filter(|name| !old.contains_key(*name)).map(|(path, _)| path.to_string()).collect();
+        Ok(diff)
+    }
+}
+
+impl InclusionProof {
+    pub fn load(path: &Path) -> Result<Self, ErrorDetail> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| ErrorDetail::io(format!("cannot read proof {}", path.display()), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| ErrorDetail::invalid(format!("malformed proof {}", path.display())).with_source(e))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("InclusionProof serializes to JSON")
+    }
+
+    /// Check that the proof places its entry in the tree with the trusted hex
+    /// `fingerprint`; the proof's own `fingerprint` field is not trusted.
+    pub fn verify(&self, fingerprint: &str) -> Result<(), ErrorDetail> {
+        let malformed = || ErrorDetail::rejected(format!("malformed inclusion proof for {}", self.entry.path));
+        let path = self.audit_path.iter()
+            .map(|h| hex::decode(h).ok().filter(|d| d.len() == self.algorithm.digest_len()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(malformed)?;
+        let leaf = merkle::leaf_hash(self.algorithm, &leaf_data(self.algorithm, &self.entry).map_err(|_| malformed())?);
+        let root = merkle::root_from_path(self.algorithm, &leaf, self.leaf_index, self.tree_size, &path)?;
+        if !hex::encode(root).eq_ignore_ascii_case(fingerprint.trim()) {
+            return Err(ErrorDetail::rejected(format!("{} is not part of the tree with fingerprint {}", self.entry.path, fingerprint.trim())));
+        }
+        Ok(())
+    }
+
+    /// `verify`, and check that the local `file` hashes to the leaf the proof is about:
+    /// same kind, permissions, ownership if proven, and content or target.
+    pub fn verify_file(&self, fingerprint: &str, file: &Path) -> Result<(), ErrorDetail> {
+        let local = ManifestEntry {
+            raw_path: self.entry.raw_path.clone(),
+            ..ManifestEntry::read(self.entry.path.clone(), file, self.algorithm, self.entry.uid.is_some())
+        };
+        if local.kind == EntryKind::Unreadable && self.entry.kind != EntryKind::Unreadable {
+            let error = std::io::Error::other(local.error.unwrap_or_default());
+            return Err(ErrorDetail::io(format!("cannot read {}", file.display()), error));
+        }
+        if leaf_data(self.algorithm, &local)? != leaf_data(self.algorithm, &self.entry)? {
+            return Err(ErrorDetail::rejected(format!("{} does not match the entry proven for {}", file.display(), self.entry.path)));
+        }
+        self.verify(fingerprint)
+    }
+}
+
+impl ManifestDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty() && self.permissions.is_empty()
+            && self.owners.is_empty()
+    }
+
+    /// One line per change, e.g. `modified lib/a.so`, for error messages.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        lines.extend(self.added.iter().map(|p| format!("added {}", p)));
+        lines.extend(self.removed.iter().map(|p| format!("removed {}", p)));
+        lines.extend(self.modified.iter().map(|p| format!("modified {}", p)));
+        lines.extend(self.permissions.iter().map(|(p, old, new)| format!("mode {:04o} → {:04o} {}", old, new, p)));
+        lines.extend(self.owners.iter().map(|(p, old, new)| format!("owner {} → {} {}", old, new, p)));
+        lines
+    }
+}
+
+/// `diff`-style listing: `+` added, `-` removed, `~` modified, `m` permissions changed,
+/// `o` owner changed.
+impl fmt::Display for ManifestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("no changes");
+        }
+        let mut lines = Vec::new();
+        lines.extend(self.added.iter().map(|p| format!("+ {}", p)));
+        lines.extend(self.removed.iter().map(|p| format!("- {}", p)));
+        lines.extend(self.modified.iter().map(|p| format!("~ {}", p)));
+        lines.extend(self.permissions.iter().map(|(p, old, new)| format!("m {} ({:04o} → {:04o})", p, old, new)));
+        lines.extend(self.owners.iter().map(|(p, old, new)| format!("o {} ({} → {})", p, old, new)));
+        write!(f, "{}\n{} added, {} removed, {} modified, {} permission change(s), {} owner change(s)", lines.join("\n"),
+               self.added.len(), self.removed.len(), self.modified.len(), self.permissions.len(), self.owners.len())
+    }
+}
+
+/// What identifies an unchanged file: device, inode, size, and modification and change
+/// times in nanoseconds. ctime cannot be set from user space, so restoring an old mtime
+/// after editing a file still misses the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileStamp {
+    pub dev: u64,
+    pub inode: u64,
+    pub size: u64,
+    pub mtime_ns: i64,
+    pub ctime_ns: i64,
+}
+
+impl FileStamp {
+    #[cfg(unix)]
+    pub fn of(metadata: &fs::Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        Some(FileStamp {
+            dev: metadata.dev(),
+            inode: metadata.ino(),
+            size: metadata.size(),
+            mtime_ns: metadata.mtime().saturating_mul(1_000_000_000).saturating_add(metadata.mtime_nsec()),
+            ctime_ns: metadata.ctime().saturating_mul(1_000_000_000).saturating_add(metadata.ctime_nsec()),
+        })
+    }
+
+    /// No stable identity outside Unix; every file is hashed.
+    #[cfg(not(unix))]
+    pub fn of(_: &fs::Metadata) -> Option<Self> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CachedDigest {
+    #[serde(flatten)]
+    stamp: FileStamp,
+    digest: String,
+}
+
+/// Digests from an earlier run, by `FileStamp`. A cache that is missing, unreadable or
+/// for another algorithm is treated as empty and replaced.
+#[derive(Debug, Default)]
+pub struct HashCache {
+    algorithm: Option<HashAlgorithm>,
+    digests: HashMap<FileStamp, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CacheFile {
+    version: u32,
+    algorithm: HashAlgorithm,
+    entries: Vec<CachedDigest>,
+}
+
+impl HashCache {
+    const VERSION: u32 = 1;
+    /// Files changed this close to the start of a run are not cached: a write in the
+    /// same filesystem timestamp tick as the hash could otherwise go unnoticed.
+    const RACY_SECS: i64 = 2;
+
+    pub fn load(path: &Path, algorithm: HashAlgorithm) -> Self {
+        let file: Option<CacheFile> = fs::read_to_string(path).ok().and_then(|text| serde_json::from_str(&text).ok());
+        match file {
+            Some(file) if file.version == Self::VERSION && file.algorithm == algorithm => HashCache {
+                algorithm: Some(algorithm),
+                digests: file.entries.into_iter().map(|e| (e.stamp, e.digest)).collect(),
+            },
+            _ => HashCache::default(),
+        }
+    }
+
+    fn new(algorithm: HashAlgorithm, digests: impl Iterator<Item = (FileStamp, String)>, started: DateTime<Utc>) -> Self {
+        let racy_after = started.timestamp().saturating_sub(Self::RACY_SECS).saturating_mul(1_000_000_000);
+        HashCache {
+            algorithm: Some(algorithm),
+            digests: digests.filter(|(s, _)| s.mtime_ns < racy_after && s.ctime_ns < racy_after).collect(),
+        }
+    }
+
+    pub fn get(&self, stamp: &FileStamp) -> Option<&String> {
+        self.digests.get(stamp)
+    }
+
+    pub fn len(&self) -> usize {
+        self.digests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.digests.is_empty()
+    }
+
+    fn save(&self, path: &Path) -> Result<(), ErrorDetail> {
+        let Some(algorithm) = self.algorithm else { return Ok(()) };
+        let mut entries: Vec<CachedDigest> = self.digests.iter()
+            .map(|(stamp, digest)| CachedDigest { stamp: *stamp, digest: digest.clone() })
+            .collect();
+        entries.sort_by_key(|e| (e.stamp.dev, e.stamp.inode));
+        let json = serde_json::to_string(&CacheFile { version: Self::VERSION, algorithm, entries })
+            .expect("hash cache serializes to JSON");
+        let io = |e| ErrorDetail::io(format!("cannot write fingerprint cache {}", path.display()), e);
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(io)?;
+        }
+        write_private(path, json.as_bytes()).map_err(io)
+    }
+}
+
+pub struct FileSink {
+    pub path: PathBuf,
+}
+
+impl FingerprintSink for FileSink {
+    fn name(&self) -> &'static str { "file" }
+    fn publish(&self, result: &FingerprintResult, _: &[Published]) -> Result<String, ErrorDetail> {
+        let io = |e| ErrorDetail::io(format!("cannot write {}", self.path.display()), e);
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(io)?;
+        }
+        write_private(&self.path, result.fingerprint.as_bytes()).map_err(io)?;
+        Ok(self.path.display().to_string())
+    }
+}
+
+pub struct ManifestSink {
+    pub path: PathBuf,
+}
+
+impl FingerprintSink for ManifestSink {
+    fn name(&self) -> &'static str { "manifest" }
+    fn publish(&self, result: &FingerprintResult, _: &[Published]) -> Result<String, ErrorDetail> {
+        let io = |e| ErrorDetail::io(format!("cannot write {}", self.path.display()), e);
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(io)?;
+        }
+        write_private(&self.path, result.manifest().to_json().as_bytes()).map_err(io)?;
+        Ok(self.path.display().to_string())
+    }
+}
+
+pub struct AuditLogSink {
+    pub path: PathBuf,
+}
+
+impl FingerprintSink for AuditLogSink {
+    fn name(&self) -> &'static str { "audit-log" }
+    fn publish(&self, result: &FingerprintResult, earlier: &[Published]) -> Result<String, ErrorDetail> {
+        let io = |e| ErrorDetail::io(format!("cannot append to {}", self.path.display()), e);
+        let mut line = format!("[{}] Kernel Fingerprint: ROOT={} {}={}", result.computed_at.to_rfc3339(),
+                               result.root.display(), result.algorithm.to_string().to_uppercase(), result.fingerprint);
+        for published in earlier {
+            line.push_str(&format!(" | {}={}", published.sink.to_uppercase(), published.location));
+        }
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(io)?;
+        }
+        fs::OpenOptions::new().append(true).create(true).open(&self.path)
+            .and_then(|mut f| writeln!(f, "{}", line))
+            .map_err(io)?;
+        Ok(self.path.display().to_string())
+    }
+}
+
+/// Symmetrically encrypts the file an earlier `FileSink` wrote with `gpg --symmetric`
+/// (AES-256), producing `<file>.gpg`.
+pub struct GpgSink;
+
+impl FingerprintSink for GpgSink {
+    fn name(&self) -> &'static str { "gpg" }
+    fn publish(&self, _: &FingerprintResult, earlier: &[Published]) -> Result<String, ErrorDetail> {
+        let file = earlier.iter().rev().find(|p| p.sink == "file")
+            .ok_or_else(|| ErrorDetail::invalid("gpg encrypts the fingerprint file; add an output first"))?;
+        run_tool(Command::new("gpg").args(["--batch", "--yes", "--symmetric", "--cipher-algo", "AES256"]).arg(&file.location))?;
+        Ok(format!("{}.gpg", file.location))
+    }
+}
+
+/// Adds the most recent file or encrypted copy to IPFS and records the CID in `cid_file`.
+pub struct IpfsSink {
+    pub cid_file: PathBuf,
+    /// Start `ipfs daemon` first and give it this long to come up.
+    pub start_daemon: Option<Duration>,
+}
+
+impl FingerprintSink for IpfsSink {
+    fn name(&self) -> &'static str { "ipfs" }
+    fn publish(&self, _: &FingerprintResult, earlier: &[Published]) -> Result<String, ErrorDetail> {
+        let file = earlier.iter().rev().find(|p| p.sink == "gpg" || p.sink == "file")
+            .ok_or_else(|| ErrorDetail::invalid("ipfs publishes the fingerprint file; add an output first"))?;
+        if Command::new("ipfs").arg("version").output().is_err() {
+            return Err(ErrorDetail::unsupported("ipfs is not installed"));
+        }
+        if !Path::new("/root/.ipfs").exists() {
+            run_tool(Command::new("ipfs").arg("init"))?;
+        }
+        if let Some(wait) = self.start_daemon {
+            Command::new("ipfs").arg("daemon").spawn()
+                .map_err(|e| ErrorDetail::io("cannot start ipfs daemon", e))?;
+            std::thread::sleep(wait);
+        }
+        let cid = run_tool(Command::new("ipfs").args(["add", "-Q"]).arg(&file.location))?;
+        fs::write(&self.cid_file, &cid)
+            .map_err(|e| ErrorDetail::io(format!("cannot write {}", self.cid_file.display()), e))?;
+        Ok(cid)
+    }
+}
+
+/// Run an external tool, returning its trimmed stdout.
+fn run_tool(command: &mut Command) -> Result<String, ErrorDetail> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command.output().map_err(|e| ErrorDetail::io(format!("cannot run {}", program), e))?;
+    if !output.status.success() {
+        return Err(ErrorDetail::new(ErrorKind::Io, format!("{} failed ({}): {}", program, output.status,
+                                                           String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode_of(_: &fs::Metadata) -> u32 {
+    0
+}
+
+#[cfg(unix)]
+fn owner_of(metadata: &fs::Metadata) -> Option<(u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.uid(), metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn owner_of(_: &fs::Metadata) -> Option<(u32, u32)> {
+    None
+}
+
+#[cfg(unix)]
+fn rdev_of(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.rdev()
+}
+
+#[cfg(not(unix))]
+fn rdev_of(_: &fs::Metadata) -> u64 {
+    0
+}
+
+/// Replace `path` with `data` atomically: write a sibling `<name>.tmp` created with
+/// mode 0600, sync it and rename it over `path`, so readers never see a partial or
+/// briefly world-readable file.
+pub(crate) fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let tmp = path.with_file_name(name);
+    // A leftover from an interrupted run may have other permissions; never reuse it.
+    let _ = fs::remove_file(&tmp);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options.open(&tmp)
+        .and_then(|mut file| file.write_all(data).and_then(|()| file.sync_all()))
+        .and_then(|()| fs::rename(&tmp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn outputs_are_replaced_atomically_and_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        fs::create_dir(&tree).unwrap();
+        fs::write(tree.join("vmlinuz"), b"kernel image").unwrap();
+        let output = dir.path().join("kernel.sha512");
+        fs::write(&output, b"stale").unwrap();
+        fs::set_permissions(&output, fs::Permissions::from_mode(0o644)).unwrap();
+        let cache = dir.path().join("kernel.cache.json");
+
+        let result = Fingerprinter::new(&tree).output(&output).cache(&cache).run().unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), result.fingerprint);
+        for path in [&output, &cache] {
+            assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600, "{}", path.display());
+        }
+        let mut names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        names.sort();
+        assert_eq!(names, ["kernel.cache.json", "kernel.sha512", "tree"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn names_that_decode_alike_are_both_hashed_as_stored() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(std::ffi::OsStr::from_bytes(b"model\xff")), b"one").unwrap();
+        fs::write(dir.path().join(std::ffi::OsStr::from_bytes(b"model\xfe")), b"two").unwrap();
+        let result = Fingerprinter::new(dir.path()).compute().unwrap();
+        assert_eq!((result.files, result.unreadable), (2, 0));
+        let named: Vec<_> = result.entries.iter().map(|e| (e.path.as_str(), e.raw_path.as_deref())).collect();
+        assert_eq!(named, [(".", None), ("model\u{fffd}", Some("6d6f64656cfe")), ("model\u{fffd}", Some("6d6f64656cff"))]);
+
+        let mut manifest = Manifest::parse(result.manifest().to_json().as_bytes(), Path::new("manifest.json")).unwrap();
+        assert!(manifest.prove("model\u{fffd}").unwrap_err().message.contains("more than one entry"));
+        // The displayed name must be what the stored bytes read as.
+        manifest.entries[1].raw_path = Some(hex::encode(b"other\xfe"));
+        assert!(manifest.merkle_root().is_err());
+        manifest.entries[1].raw_path = Some(hex::encode("model\u{fffd}"));
+        assert!(manifest.merkle_root().is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn renames_setuid_and_symlink_retargets_change_the_fingerprint() {
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::{symlink, PermissionsExt};
+        let dir = tempfile::tempdir().unwrap();
+        let name = |bytes: &[u8]| dir.path().join(std::ffi::OsStr::from_bytes(bytes));
+        let retarget = |target: &[u8]| {
+            fs::remove_file(dir.path().join("link")).unwrap();
+            symlink(std::ffi::OsStr::from_bytes(target), dir.path().join("link")).unwrap();
+        };
+        fs::write(name(b"model\xff"), b"weights").unwrap();
+        symlink("a", dir.path().join("link")).unwrap();
+        let fingerprint = || {
+            let result = Fingerprinter::new(dir.path()).compute().unwrap();
+            Manifest::parse(result.manifest().to_json().as_bytes(), Path::new("manifest.json")).unwrap();
+            result.fingerprint
+        };
+
+        let mut seen = vec![fingerprint()];
+        fs::rename(name(b"model\xff"), name(b"model\xfe")).unwrap();
+        seen.push(fingerprint());
+        fs::set_permissions(name(b"model\xfe"), fs::Permissions::from_mode(0o4644)).unwrap();
+        seen.push(fingerprint());
+        retarget(b"b");
+        seen.push(fingerprint());
+        retarget(b"\xff");
+        seen.push(fingerprint());
+        retarget(b"\xfe");
+        seen.push(fingerprint());
+        assert_eq!(seen.iter().collect::<std::collections::HashSet<_>>().len(), seen.len(), "{:#?}", seen);
+        assert_eq!(fingerprint(), seen[5]);
+    }
+
+    fn tree() -> (tempfile::TempDir, Manifest) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("lib")).unwrap();
+        for (name, body) in [("vmlinuz", "kernel"), ("lib/a.so", "a"), ("lib/b.so", "b"), ("initrd", "initrd")] {
+            fs::write(dir.path().join(name), body).unwrap();
+        }
+        let manifest = Fingerprinter::new(dir.path()).compute().unwrap().manifest();
+        (dir, manifest)
+    }
+
+    #[test]
+    fn every_entry_proves_into_its_fingerprint() {
+        let (dir, manifest) = tree();
+        assert_eq!(manifest.entries.len(), 6);
+        for (i, entry) in manifest.entries.iter().enumerate() {
+            let proof = serde_json::from_str::<InclusionProof>(&manifest.prove(&entry.path).unwrap().to_json()).unwrap();
+            assert_eq!((proof.leaf_index, proof.tree_size), (i as u64, 6));
+            proof.verify(&manifest.fingerprint).unwrap();
+            proof.verify(&manifest.fingerprint.to_uppercase()).unwrap();
+            proof.verify_file(&manifest.fingerprint, &dir.path().join(&entry.path)).unwrap();
+        }
+        assert!(manifest.prove("lib/c.so").is_err());
+    }
+
+    #[test]
+    fn tampered_proofs_are_rejected() {
+        let (dir, manifest) = tree();
+        let proof = manifest.prove("lib/a.so").unwrap();
+        let rejected = |tamper: &dyn Fn(&mut InclusionProof)| {
+            let mut proof = proof.clone();
+            tamper(&mut proof);
+            proof.verify(&manifest.fingerprint).is_err()
+        };
+        assert!(!rejected(&|_| {}));
+        assert!(rejected(&|p| p.leaf_index += 1));
+        assert!(rejected(&|p| p.leaf_index = p.tree_size));
+        assert!(rejected(&|p| p.tree_size = 2 * p.tree_size + 1));
+        assert!(rejected(&|p| { p.audit_path.pop(); }));
+        assert!(rejected(&|p| p.audit_path.push(p.fingerprint.clone())));
+        assert!(rejected(&|p| p.audit_path[0] = "00".to_string()));
+        assert!(rejected(&|p| p.entry.digest = manifest.entries[4].digest.clone()));
+        assert!(rejected(&|p| p.entry.mode |= 0o4000));
+        assert!(rejected(&|p| p.entry.path = "lib/b.so".to_string()));
+        // The entry's leaf and its sibling presented as one leaf of a smaller tree.
+        assert!(rejected(&|p| {
+            p.audit_path.remove(0);
+            (p.leaf_index, p.tree_size) = (p.leaf_index / 2, p.tree_size - 1);
+        }));
+        // Only the trusted fingerprint counts, never the proof's own.
+        assert!(!rejected(&|p| p.fingerprint = p.audit_path[0].clone()));
+        assert!(proof.verify(&proof.audit_path[0]).is_err());
+
+        fs::write(dir.path().join("lib/a.so"), "A").unwrap();
+        let error = proof.verify_file(&manifest.fingerprint, &dir.path().join("lib/a.so")).unwrap_err();
+        assert!(error.message.contains("does not match"), "{:?}", error);
+        assert!(proof.verify_file(&manifest.fingerprint, &dir.path().join("lib/missing.so")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn diffs_name_every_kind_of_change() {
+        use std::os::unix::fs::PermissionsExt;
+        let (dir, baseline) = tree();
+        assert!(baseline.diff(&baseline).unwrap().is_empty());
+        assert_eq!(baseline.diff(&baseline).unwrap().to_string(), "no changes");
+
+        fs::write(dir.path().join("lib/a.so"), "patched").unwrap();
+        fs::remove_file(dir.path().join("initrd")).unwrap();
+        fs::write(dir.path().join("lib/c.so"), "c").unwrap();
+        fs::set_permissions(dir.path().join("vmlinuz"), fs::Permissions::from_mode(0o4755)).unwrap();
+        // Touching a file without changing it is not a change.
+        fs::File::options().write(true).open(dir.path().join("lib/b.so")).unwrap()
+            .set_modified(std::time::UNIX_EPOCH).unwrap();
+        let mut current = Fingerprinter::new(dir.path()).compute().unwrap().manifest();
+        // Ownership only counts where both sides record it.
+        for entry in &mut current.entries {
+            (entry.uid, entry.gid) = (Some(if entry.path == "lib" { 1000 } else { 0 }), Some(0));
+        }
+        let mut owned = baseline.clone();
+        for entry in &mut owned.entries {
+            (entry.uid, entry.gid) = (Some(0), Some(0));
+        }
+
+        let diff = baseline.diff(&current).unwrap();
+        assert_eq!(diff.added, ["lib/c.so"]);
+        assert_eq!(diff.removed, ["initrd"]);
+        assert_eq!(diff.modified, ["lib/a.so"]);
+        assert!(diff.owners.is_empty());
+        let mode = baseline.entries.iter().find(|e| e.path == "vmlinuz").unwrap().mode;
+        assert_eq!(diff.permissions, [("vmlinuz".to_string(), mode, 0o4755)]);
+        let diff = owned.diff(&current).unwrap();
+        assert_eq!(diff.owners, [("lib".to_string(), "0:0".to_string(), "1000:0".to_string())]);
+        assert!(diff.lines().contains(&"owner 0:0 → 1000:0 lib".to_string()), "{:?}", diff.lines());
+        assert!(diff.to_string().ends_with("1 added, 1 removed, 1 modified, 1 permission change(s), 1 owner change(s)"),
+                "{}", diff);
+    }
+
+    #[test]
+    fn manifests_in_different_algorithms_do_not_compare() {
+        let (dir, sha512) = tree();
+        let sha256 = Fingerprinter::new(dir.path()).algorithm(HashAlgorithm::Sha256).compute().unwrap().manifest();
+        let error = sha512.diff(&sha256).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Invalid);
+        assert!(error.message.contains("cannot compare a sha512 manifest with a sha256 manifest"), "{:?}", error);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::error::{BootError, ErrorDetail};
+use crate::schema::{FirmwareConfig, SignaturePolicy};
+use crate::signature;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// Prefix of every signed message, so a firmware signature can never be replayed as
+/// a signature over anything else.
+pub const SIGNING_CONTEXT: &[u8] = b"mesh-sec-ai-boot/firmware/v1\0";
+
+/// A firmware image whose signatures checked out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedImage {
+    pub sha256: [u8; 32],
+    /// Keys whose signatures were verified, in signature file order.
+    pub key_ids: Vec<String>,
+    /// The image exactly as read and verified. Measure these bytes rather than reading
+    /// the file again, which could have been swapped in between.
+    pub bytes: Vec<u8>,
+}
+
+/// The bytes a firmware signature covers: `SIGNING_CONTEXT || SHA-256(image)`.
+pub fn signing_message(image_sha256: &[u8; 32]) -> Vec<u8> {
+    [SIGNING_CONTEXT, image_sha256.as_slice()].concat()
+}
+
+/// Check the configured image against its detached signatures, the trusted key set and
+/// the revocation lists, as of `now`. The image is read once, into the result.
+pub fn verify(cfg: &FirmwareConfig, policy: SignaturePolicy, now: DateTime<Utc>) -> Result<VerifiedImage, BootError> {
+    let bytes = std::fs::read(&cfg.image).map_err(|e| BootError::Firmware(
+        ErrorDetail::io(format!("cannot read firmware image {}", cfg.image), e)))?;
+    let sha256: [u8; 32] = Sha256::digest(&bytes).into();
+    let digest_hex = hex::encode(sha256);
+    if cfg.revoked_images.iter().any(|r| r.eq_ignore_ascii_case(&digest_hex)) {
+        return Err(BootError::Firmware(ErrorDetail::rejected(
+            format!("image {} (sha256 {}) is revoked", cfg.image, digest_hex))));
+    }
+
+    let signatures = signature::read_signatures(Path::new(&cfg.signature)).map_err(BootError::Firmware)?;
+    let key_ids = signature::verify_all(&signing_message(&sha256), &signatures, &cfg.trusted_keys,
+                                        &cfg.revoked_keys, policy, now)
+        .map_err(BootError::Firmware)?;
+    Ok(VerifiedImage { sha256, key_ids, bytes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::{self, EventType, MeasuredBoot};
+    use crate::schema::{HashAlgorithm, KeyAlgorithm, TrustedKey};
+    use crate::signature::DetachedSignature;
+
+    fn signed_image(dir: &Path, image: &[u8]) -> FirmwareConfig {
+        let secret = [5u8; 32];
+        let image_path = dir.join("firmware.bin");
+        std::fs::write(&image_path, image).unwrap();
+        let message = signing_message(&Sha256::digest(image).into());
+        let signature = DetachedSignature {
+            key_id: "vendor".to_string(),
+            algorithm: KeyAlgorithm::Ed25519,
+            signature: hex::encode(signature::sign(KeyAlgorithm::Ed25519, &secret, &message).unwrap()),
+        };
+        let signature_path = dir.join("firmware.sig");
+        std::fs::write(&signature_path, serde_json::to_string(&signature).unwrap()).unwrap();
+        FirmwareConfig {
+            image: image_path.to_string_lossy().into_owned(),
+            signature: signature_path.to_string_lossy().into_owned(),
+            trusted_keys: vec![TrustedKey {
+                id: "vendor".to_string(),
+                algorithm: KeyAlgorithm::Ed25519,
+                public_key: hex::encode(signature::public_key(KeyAlgorithm::Ed25519, &secret).unwrap()),
+                not_before: None,
+                not_after: None,
+            }],
+            revoked_keys: Vec::new(),
+            revoked_images: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn measures_the_bytes_that_were_verified() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = signed_image(dir.path(), b"signed firmware");
+        let verified = verify(&cfg, SignaturePolicy::Classical, Utc::now()).unwrap();
+        assert_eq!(verified.key_ids, vec!["vendor"]);
+        // Swapping the file after verification must not change what gets measured.
+        std::fs::write(&cfg.image, b"swapped firmware").unwrap();
+        let measurements = MeasuredBoot::new(&[HashAlgorithm::Sha256]);
+        measurements.measure_contents(measure::PCR_FIRMWARE, EventType::EvPostCode, "firmware",
+                                      Path::new(&cfg.image), &verified.bytes).unwrap();
+        let log = measurements.log();
+        assert_eq!(log.events[0].digests[0].digest, hex::encode(verified.sha256));
+    }
+
+    #[test]
+    fn rejects_a_modified_or_revoked_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = signed_image(dir.path(), b"signed firmware");
+        cfg.revoked_images.push(hex::encode(Sha256::digest(b"signed firmware")));
+        let err = verify(&cfg, SignaturePolicy::Classical, Utc::now()).unwrap_err();
+        assert!(err.to_string().contains("is revoked"), "{}", err);
+
+        cfg.revoked_images.clear();
+        std::fs::write(&cfg.image, b"patched firmware").unwrap();
+        let err = verify(&cfg, SignaturePolicy::Classical, Utc::now()).unwrap_err();
+        assert_eq!(err.code(), "firmware.rejected");
+    }
+}
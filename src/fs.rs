@@ -1,8 +1,47 @@
-use crate::schema::FileSystemConfig;
-pub fn mount(fs: &FileSystemConfig) -> Result<(), String> {
-    println!("💽 Mounting {} with {}", fs.mount_at, fs.encryption.algorithm);
+use crate::error::{BootError, ErrorDetail};
+use crate::measure::MeasuredBoot;
+use crate::schema::{FileSystemConfig, TpmConfig};
+/// `key` is the data key unsealed by the TPM, if one is configured.
+pub fn mount(fs: &FileSystemConfig, key: Option<&[u8]>) -> Result<(), BootError> {
+    println!("💽 Mounting {} with {} (KEM {})", fs.mount_at, fs.encryption.cipher, fs.encryption.kem);
     if fs.encryption.quantum_resistant {
         println!("   🔐 PQ Crypto enabled");
     }
+    if let Some(key) = key {
+        println!("   🔑 Data key: {} bytes, TPM-sealed", key.len());
+    }
+    Ok(())
+}
+/// Unseal the data key named by `tpm.sealed_key`, if any. Fails with `Rejected` when the
+/// PCRs it is sealed to no longer hold the values they had at `tpm seal`.
+#[cfg(feature = "tpm")]
+pub fn unseal_key(tpm: Option<&TpmConfig>, measurements: &MeasuredBoot) -> Result<Option<Vec<u8>>, BootError> {
+    use crate::tpm::SealedBlob;
+    let Some(path) = tpm.and_then(|t| t.sealed_key.as_deref()) else { return Ok(None) };
+    let tpm = measurements.tpm().ok_or_else(|| BootError::Filesystem(ErrorDetail::internal("TPM was not opened")))?;
+    let blob = SealedBlob::load(std::path::Path::new(path)).map_err(BootError::Filesystem)?;
+    let key = tpm.unseal(&blob).map_err(BootError::Filesystem)?;
+    println!("🔑 Data key unsealed from {} (PCRs {:?})", path, blob.pcrs);
+    Ok(Some(key))
+}
+#[cfg(not(feature = "tpm"))]
+pub fn unseal_key(tpm: Option<&TpmConfig>, _measurements: &MeasuredBoot) -> Result<Option<Vec<u8>>, BootError> {
+    match tpm.and_then(|t| t.sealed_key.as_deref()) {
+        Some(path) => Err(BootError::Filesystem(ErrorDetail::unsupported(
+            format!("sealed key {} needs a build with the `tpm` feature", path)))),
+        None => Ok(None),
+    }
+}
+pub fn unmount(fs: &FileSystemConfig) -> Result<(), BootError> {
+    println!("💽 Unmounting {}", fs.mount_at);
     Ok(())
 }
+pub fn describe_mount(fs: &FileSystemConfig, tpm: Option<&TpmConfig>) -> Vec<String> {
+    let mut actions = Vec::new();
+    if let Some(path) = tpm.and_then(|t| t.sealed_key.as_deref()) {
+        actions.push(format!("unseal data key from {} with the TPM", path));
+    }
+    actions.push(format!("mount {} with {}, data key wrapped by {}{}", fs.mount_at, fs.encryption.cipher,
+                 fs.encryption.kem, if fs.encryption.quantum_resistant {" (PQ)"} else {""}));
+    actions
+}
@@ -1,11 +1,572 @@
-pub fn verify() -> Result<(), String> {
-    println!("🔎 Integrity: Checking TPM...");
-    if !hash_pass() {
-        return Err("❌ Hash Fail".to_string());
+use crate::error::{BootError, ErrorDetail};
+use crate::fingerprint::{Fingerprinter, Manifest};
+use crate::measure::{self, EventLog, EventType, MeasuredBoot, MeasuredFile, PcrBank};
+use crate::profile::FailurePolicy;
+use crate::schema::{GoldenPcr, HashAlgorithm, KernelFingerprintConfig, MeasuredBootConfig, SignaturePolicy};
+use crate::signature;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// Prefix of every golden file and kernel manifest signature, so reference values can
+/// never be passed off as firmware or a quote, or the other way round.
+pub const REFERENCE_SIGNING_CONTEXT: &[u8] = b"mesh-sec-ai-boot/reference/v1\0";
+
+/// Event recording the kernel fingerprint in `measure::PCR_KERNEL`, followed by the
+/// hex fingerprint; the measured data is the hex string.
+pub const KERNEL_EVENT: &str = "kernel fingerprint";
+
+/// How far this process's integrity has been established.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityState {
+    /// Nothing has been checked yet.
+    Unverified,
+    /// The integrity stage is replaying the event log.
+    Verifying,
+    /// The event log replayed and matched every golden value.
+    Verified,
+    /// Booted, but something could not be checked or a stage failure was tolerated.
+    Degraded,
+    /// A check said no. Only a reboot leaves this state.
+    Compromised,
+}
+
+impl IntegrityState {
+    /// Whether `to` may follow this state. Degraded and compromised states never return
+    /// to verified within one process.
+    pub fn can_become(self, to: IntegrityState) -> bool {
+        use IntegrityState::*;
+        matches!((self, to),
+            (Unverified, Verifying | Degraded | Compromised)
+            | (Verifying, Verified | Degraded | Compromised)
+            | (Verified, Degraded | Compromised)
+            | (Degraded, Compromised))
+    }
+
+    /// Whether a service may answer requests in this state under a profile's `policy`:
+    /// fail-closed profiles need `Verified`, `Warn` also serves while degraded.
+    pub fn permits_serving(self, policy: FailurePolicy) -> bool {
+        match policy {
+            FailurePolicy::FailClosed => self == IntegrityState::Verified,
+            FailurePolicy::Warn => matches!(self, IntegrityState::Verified | IntegrityState::Degraded),
+        }
     }
-    println!("✅ Chain OK");
-    Ok(())
 }
 
-fn hash_pass() -> bool { true }
-pub fn status() -> &'static str { "SECURE | READY | CHAINED" }
+impl fmt::Display for IntegrityState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IntegrityState::Unverified => "unverified",
+            IntegrityState::Verifying => "verifying",
+            IntegrityState::Verified => "verified",
+            IntegrityState::Degraded => "degraded",
+            IntegrityState::Compromised => "compromised",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Transition {
+    pub from: IntegrityState,
+    pub to: IntegrityState,
+    pub reason: String,
+    pub at: DateTime<Utc>,
+}
+
+/// The integrity state of this process and how it got there, updated by the boot
+/// stages and the runtime watcher and read by anything that must refuse to serve an
+/// unverified node.
+#[derive(Debug)]
+pub struct IntegrityMonitor {
+    state: Mutex<(IntegrityState, Vec<Transition>)>,
+}
+
+impl Default for IntegrityMonitor {
+    fn default() -> Self {
+        IntegrityMonitor { state: Mutex::new((IntegrityState::Unverified, Vec::new())) }
+    }
+}
+
+impl IntegrityMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> IntegrityState {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).0
+    }
+
+    /// Why the monitor is in its current state; `None` while still unverified.
+    pub fn reason(&self) -> Option<String> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).1.last().map(|t| t.reason.clone())
+    }
+
+    pub fn transitions(&self) -> Vec<Transition> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).1.clone()
+    }
+
+    /// Move to `to` if `IntegrityState::can_become` allows it. Returns whether it did.
+    pub fn transition(&self, to: IntegrityState, reason: impl Into<String>) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (from, transitions) = &mut *state;
+        if !from.can_become(to) {
+            return false;
+        }
+        let reason = reason.into();
+        println!("🔐 Integrity: {} → {} ({})", from, to, reason);
+        transitions.push(Transition { from: *from, to, reason, at: Utc::now() });
+        *from = to;
+        true
+    }
+
+    /// Re-run `watch` every `interval` on a background thread, moving to the state its
+    /// first finding calls for. Stops once compromised or when the last other reference
+    /// to the monitor is dropped.
+    pub fn watch(self: &Arc<Self>, watch: WatchList, interval: Duration) -> thread::JoinHandle<()> {
+        let monitor = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            let Some(monitor) = monitor.upgrade() else { return };
+            if monitor.state() == IntegrityState::Compromised {
+                return;
+            }
+            if let Err((state, reason)) = watch.check() {
+                monitor.transition(state, reason);
+            }
+        })
+    }
+}
+
+/// The process-wide monitor `BootContext` reports into by default.
+pub fn monitor() -> Arc<IntegrityMonitor> {
+    static MONITOR: OnceLock<Arc<IntegrityMonitor>> = OnceLock::new();
+    MONITOR.get_or_init(|| Arc::new(IntegrityMonitor::new())).clone()
+}
+
+/// What the runtime watcher re-checks after boot: every file measured during boot and
+/// the event log written by `verify`, which later measurements keep rewriting.
+#[derive(Clone)]
+pub struct WatchList {
+    banks: Vec<HashAlgorithm>,
+    files: Vec<MeasuredFile>,
+    measured: Arc<MeasuredBoot>,
+}
+
+impl WatchList {
+    pub fn new(measured: &Arc<MeasuredBoot>) -> Self {
+        WatchList { banks: measured.banks(), files: measured.files(), measured: measured.clone() }
+    }
+
+    /// Stop watching `path`, e.g. a policy file that is expected to be hot-reloaded.
+    pub fn ignore(mut self, path: &Path) -> Self {
+        self.files.retain(|f| f.path != path);
+        self
+    }
+
+    /// The state a finding calls for and why: `Compromised` for a file that changed
+    /// since it was measured, `Degraded` for one that can no longer be read.
+    pub fn check(&self) -> Result<(), (IntegrityState, String)> {
+        for file in &self.files {
+            match measure::hash_file(&self.banks, &file.path) {
+                Ok(digests) if digests == file.digests => {}
+                Ok(_) => return Err((IntegrityState::Compromised,
+                                     format!("{} (PCR[{}]) changed since it was measured", file.path.display(), file.pcr))),
+                Err(e) => return Err((IntegrityState::Degraded, format!("cannot re-measure {}: {}", file.path.display(), e))),
+            }
+        }
+        match self.measured.log_unchanged() {
+            None | Some((_, Ok(true))) => Ok(()),
+            Some((path, Ok(false))) => Err((IntegrityState::Compromised, format!("event log {} was rewritten", path.display()))),
+            Some((path, Err(e))) => Err((IntegrityState::Degraded, format!("cannot read event log {}: {}", path.display(), e))),
+        }
+    }
+}
+
+/// Fingerprint the kernel tree into `measure::PCR_KERNEL` when configured, replay the
+/// boot's event log, check that it reproduces the live PCRs and compare the result with
+/// the configured golden values. The log is written out first when configured, so a
+/// failed boot still leaves it behind. Golden files and kernel manifests must be signed
+/// as `policy` demands. Returns warnings.
+pub fn verify(measured: &MeasuredBoot, cfg: Option<&MeasuredBootConfig>, policy: SignaturePolicy)
+    -> Result<Vec<String>, BootError>
+{
+    let kernel = cfg.and_then(|c| c.kernel_fingerprint.as_ref().map(|k| verify_kernel_fingerprint(k, c, policy, measured)));
+    let log = measured.log();
+    println!("🔎 Integrity: replaying {} measured boot event(s)", log.events.len());
+    if let Some(path) = cfg.and_then(|c| c.event_log.as_deref()) {
+        measured.write_log(Path::new(path))?;
+    }
+
+    let replayed = log.replay().map_err(BootError::Integrity)?;
+    for (bank, live) in replayed.iter().zip(measured.pcrs()) {
+        if let Some(pcr) = live.values.keys().find(|pcr| bank.read(**pcr) != live.read(**pcr)) {
+            return Err(BootError::Integrity(ErrorDetail::rejected(
+                format!("event log does not reproduce {} PCR[{}]", bank.algorithm, pcr))));
+        }
+    }
+
+    #[cfg(feature = "tpm")]
+    if let Some(tpm) = measured.tpm() {
+        for bank in &replayed {
+            let pcrs: Vec<u32> = bank.values.keys().copied().collect();
+            let actual = tpm.read(bank.algorithm, &pcrs).map_err(BootError::Integrity)?;
+            if let Some(pcr) = pcrs.iter().find(|pcr| actual.get(pcr) != Some(&bank.read(**pcr))) {
+                return Err(BootError::Integrity(ErrorDetail::rejected(format!(
+                    "TPM {} PCR[{}] differs from the replayed event log; it was extended outside this boot",
+                    bank.algorithm, pcr))));
+            }
+        }
+        println!("🔏 TPM PCRs match the event log");
+    }
+
+    let mut warnings: Vec<String> = kernel.transpose()?.into_iter().flatten().collect();
+    let golden = cfg.map(|c| golden_values(c, policy)).transpose()?.unwrap_or_default();
+    if golden.is_empty() {
+        warnings.push("no golden PCR values configured; measurements were recorded but not compared".to_string());
+    } else {
+        let mismatches = golden_mismatches(&log, &replayed, &golden).map_err(BootError::Integrity)?;
+        if !mismatches.is_empty() {
+            return Err(BootError::Integrity(ErrorDetail::rejected(
+                format!("measured boot does not match golden values:\n  {}", mismatches.join("\n  ")))));
+        }
+        println!("✅ Chain OK: {} golden PCR value(s) match", golden.len());
+    }
+    Ok(warnings)
+}
+
+/// The bytes a golden file or manifest signature covers:
+/// `REFERENCE_SIGNING_CONTEXT || SHA-256(contents)`.
+pub fn reference_message(contents: &[u8]) -> Vec<u8> {
+    let digest: [u8; 32] = Sha256::digest(contents).into();
+    [REFERENCE_SIGNING_CONTEXT, digest.as_slice()].concat()
+}
+
+/// Where the detached signature of a golden file or manifest lives: `<path>.sig`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+/// Read a golden file or manifest and check its signature against `trusted_keys`,
+/// returning the contents that were verified.
+pub fn read_signed(path: &Path, cfg: &MeasuredBootConfig, policy: SignaturePolicy) -> Result<Vec<u8>, BootError> {
+    let contents = std::fs::read(path).map_err(|e| BootError::Integrity(
+        ErrorDetail::io(format!("cannot read {}", path.display()), e)))?;
+    let signatures = signature::read_signatures(&signature_path(path)).map_err(BootError::Integrity)?;
+    let key_ids = signature::verify_all(&reference_message(&contents), &signatures, &cfg.trusted_keys,
+                                        &cfg.revoked_keys, policy, Utc::now())
+        .map_err(|e| BootError::Integrity(ErrorDetail::new(e.kind, format!("{}: {}", path.display(), e.message))))?;
+    println!("🔏 {} signed by {}", path.display(), key_ids.join(" + "));
+    Ok(contents)
+}
+
+/// `measure replay` output: `[[measured_boot.golden]]` tables.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GoldenFile {
+    measured_boot: GoldenTables,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GoldenTables {
+    golden: Vec<GoldenPcr>,
+}
+
+/// The inline golden values followed by those of the signed `golden_file`.
+fn golden_values(cfg: &MeasuredBootConfig, policy: SignaturePolicy) -> Result<Vec<GoldenPcr>, BootError> {
+    let mut golden = cfg.golden.clone();
+    if let Some(path) = &cfg.golden_file {
+        let contents = read_signed(Path::new(path), cfg, policy)?;
+        let file: GoldenFile = std::str::from_utf8(&contents).ok()
+            .and_then(|text| toml::from_str(text).ok())
+            .ok_or_else(|| BootError::Integrity(ErrorDetail::invalid(
+                format!("{} is not `measure replay` output", path))))?;
+        golden.extend(file.measured_boot.golden);
+    }
+    Ok(golden)
+}
+
+/// Fingerprint the kernel tree, measure it as a `KERNEL_EVENT`, publish it to `output`
+/// and compare it with `expected` or the signed baseline manifest, naming the changed
+/// files when the manifest is available. Every file is read on every boot: whoever can
+/// write a digest cache could make it vouch for a modified file.
+fn verify_kernel_fingerprint(cfg: &KernelFingerprintConfig, measured: &MeasuredBootConfig, policy: SignaturePolicy,
+                             measurements: &MeasuredBoot) -> Result<Option<String>, BootError>
+{
+    let baseline = cfg.manifest.as_deref()
+        .map(|p| read_signed(Path::new(p), measured, policy)
+            .and_then(|bytes| Manifest::parse(&bytes, Path::new(p)).map_err(BootError::Integrity)))
+        .transpose()?;
+    let mut fingerprinter = Fingerprinter::new(&cfg.root).algorithm(cfg.algorithm).ownership(cfg.ownership);
+    if let Some(output) = &cfg.output {
+        fingerprinter = fingerprinter.output(output);
+    }
+    let result = fingerprinter.run()?;
+    measurements.measure(measure::PCR_KERNEL, EventType::EvIpl, format!("{} {}", KERNEL_EVENT, result.fingerprint),
+                         result.fingerprint.as_bytes())?;
+    println!("🧬 Kernel fingerprint of {} ({} files): {}", cfg.root, result.files, result.fingerprint);
+    let unreadable: Vec<&str> = result.unreadable().map(|e| e.path.as_str()).collect();
+    let Some(expected) = cfg.expected.as_deref().or(baseline.as_ref().map(|m| m.fingerprint.as_str())) else {
+        return Ok(Some(format!("kernel fingerprint of {} computed but no expected value configured", cfg.root)));
+    };
+    if expected.trim().eq_ignore_ascii_case(&result.fingerprint) {
+        return Ok((!unreadable.is_empty()).then(|| format!(
+            "kernel fingerprint of {} matches, but {} entries could not be read: {}", cfg.root, unreadable.len(), unreadable.join(", "))));
+    }
+    let mut message = format!("kernel fingerprint of {} is {}, expected {}", cfg.root, result.fingerprint, expected.trim());
+    if let Some(baseline) = &baseline {
+        let changes = baseline.diff(&result.manifest()).map_err(BootError::Integrity)?.lines();
+        if !changes.is_empty() {
+            message.push_str(&format!(":\n  {}", changes.join("\n  ")));
+        }
+    }
+    Err(BootError::Integrity(ErrorDetail::rejected(message)))
+}
+
+/// The kernel fingerprint `log` measured, checking that each digest of its
+/// `KERNEL_EVENT` is the hash of the fingerprint the event names.
+pub fn kernel_fingerprint(log: &EventLog) -> Result<Option<String>, ErrorDetail> {
+    let mut events = log.events.iter()
+        .filter(|e| e.pcr_index == measure::PCR_KERNEL)
+        .filter_map(|e| e.event.strip_prefix(KERNEL_EVENT).and_then(|rest| rest.strip_prefix(' ')).map(|fp| (e, fp)));
+    let Some((event, fingerprint)) = events.next() else { return Ok(None) };
+    if events.next().is_some() {
+        return Err(ErrorDetail::rejected("event log measures more than one kernel fingerprint"));
+    }
+    for digest in &event.digests {
+        if !hex::encode(measure::hash(digest.algorithm, fingerprint.as_bytes())).eq_ignore_ascii_case(&digest.digest) {
+            return Err(ErrorDetail::rejected(format!(
+                "{} digest of `{}` is not the hash of the fingerprint it names", digest.algorithm, event.event)));
+        }
+    }
+    Ok(Some(fingerprint.to_ascii_lowercase()))
+}
+
+/// One line per golden value the replayed banks do not hold, naming the events that
+/// went into the PCR.
+pub fn golden_mismatches(log: &EventLog, replayed: &[PcrBank], golden: &[GoldenPcr]) -> Result<Vec<String>, ErrorDetail> {
+    let mut mismatches = Vec::new();
+    for expected in golden {
+        let Some(bank) = replayed.iter().find(|b| b.algorithm == expected.bank) else {
+            return Err(ErrorDetail::invalid(
+                format!("golden value for {} PCR[{}] but that bank is not measured", expected.bank, expected.pcr)));
+        };
+        let actual = hex::encode(bank.read(expected.pcr));
+        if !actual.eq_ignore_ascii_case(expected.value.trim()) {
+            let events: Vec<&str> = log.events_for(expected.pcr).collect();
+            mismatches.push(format!("{} PCR[{}] is {}, expected {} (measured: {})", expected.bank, expected.pcr,
+                                    actual, expected.value, if events.is_empty() { "nothing".to_string() } else { events.join(", ") }));
+        }
+    }
+    Ok(mismatches)
+}
+
+pub fn describe(cfg: Option<&MeasuredBootConfig>) -> Vec<String> {
+    let mut actions = Vec::new();
+    if let Some(manifest) = cfg.and_then(|c| c.kernel_fingerprint.as_ref()).and_then(|k| k.manifest.as_deref()) {
+        actions.push(format!("check the signature of kernel manifest {}", manifest));
+    }
+    if let Some(kernel) = cfg.and_then(|c| c.kernel_fingerprint.as_ref()) {
+        actions.push(format!("fingerprint {} with {} into PCR[{}]{}", kernel.root, kernel.algorithm, measure::PCR_KERNEL,
+                             if kernel.expected.is_some() { " and compare with the expected value" } else { "" }));
+    }
+    if let Some(path) = cfg.and_then(|c| c.event_log.as_deref()) {
+        actions.push(format!("write event log to {}", path));
+    }
+    actions.push("replay event log and check it reproduces the PCRs".to_string());
+    match (cfg.map_or(0, |c| c.golden.len()), cfg.and_then(|c| c.golden_file.as_deref())) {
+        (0, None) => actions.push("no golden PCR values to compare".to_string()),
+        (n, golden_file) => {
+            if n > 0 {
+                actions.push(format!("compare {} golden PCR value(s)", n));
+            }
+            if let Some(path) = golden_file {
+                actions.push(format!("check the signature of {} and compare its golden PCR values", path));
+            }
+        }
+    }
+    actions
+}
+
+/// Golden values for every PCR a log extends, as `[[measured_boot.golden]]` tables.
+pub fn golden_toml(log: &EventLog) -> Result<String, BootError> {
+    let mut out = String::new();
+    for bank in log.replay().map_err(BootError::Integrity)? {
+        for (pcr, value) in &bank.values {
+            out.push_str(&format!("[[measured_boot.golden]]\npcr = {}\nbank = \"{}\"\nvalue = \"{}\"\n\n",
+                                  pcr, bank.algorithm, hex::encode(value)));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attest::NodeKey;
+    use crate::measure::EventType;
+    use crate::schema::KeyAlgorithm;
+    use crate::signature::SignatureBundle;
+
+    fn release_key() -> NodeKey {
+        NodeKey { id: "release".to_string(), algorithm: KeyAlgorithm::Ed25519, secret_key: hex::encode([9u8; 32]) }
+    }
+
+    fn config(key: &NodeKey) -> MeasuredBootConfig {
+        MeasuredBootConfig {
+            banks: vec![HashAlgorithm::Sha256],
+            event_log: None,
+            golden: Vec::new(),
+            golden_file: None,
+            trusted_keys: vec![key.trusted_key().unwrap()],
+            revoked_keys: Vec::new(),
+            watch_interval_secs: None,
+            tpm: None,
+            kernel_fingerprint: None,
+        }
+    }
+
+    fn sign(key: &NodeKey, path: &Path) {
+        let signature = key.sign(&reference_message(&std::fs::read(path).unwrap())).unwrap();
+        let bundle = serde_json::to_string(&SignatureBundle { signatures: vec![signature] }).unwrap();
+        std::fs::write(signature_path(path), bundle).unwrap();
+    }
+
+    fn measured(data: &[u8]) -> MeasuredBoot {
+        let measured = MeasuredBoot::new(&[HashAlgorithm::Sha256]);
+        measured.measure(measure::PCR_FIRMWARE, EventType::EvPostCode, "firmware", data).unwrap();
+        measured
+    }
+
+    fn with_golden_file(dir: &Path, key: &NodeKey) -> MeasuredBootConfig {
+        let path = dir.join("golden.toml");
+        std::fs::write(&path, golden_toml(&measured(b"known good").log()).unwrap()).unwrap();
+        sign(key, &path);
+        MeasuredBootConfig { golden_file: Some(path.to_string_lossy().into_owned()), ..config(key) }
+    }
+
+    #[test]
+    fn only_the_warn_policy_serves_while_degraded() {
+        use IntegrityState::*;
+        let serving = |policy| [Unverified, Verifying, Verified, Degraded, Compromised]
+            .into_iter().filter(|s| s.permits_serving(policy)).collect::<Vec<_>>();
+        assert_eq!(serving(FailurePolicy::FailClosed), vec![Verified]);
+        assert_eq!(serving(FailurePolicy::Warn), vec![Verified, Degraded]);
+    }
+
+    #[test]
+    fn compares_against_a_signed_golden_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = with_golden_file(dir.path(), &release_key());
+        assert_eq!(verify(&measured(b"known good"), Some(&cfg), SignaturePolicy::Classical).unwrap(), Vec::<String>::new());
+        let err = verify(&measured(b"tampered"), Some(&cfg), SignaturePolicy::Classical).unwrap_err();
+        assert!(err.to_string().contains("does not match golden values"), "{}", err);
+    }
+
+    #[test]
+    fn refuses_unsigned_or_modified_golden_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = release_key();
+        let cfg = with_golden_file(dir.path(), &key);
+        let golden = PathBuf::from(cfg.golden_file.as_deref().unwrap());
+
+        let text = std::fs::read_to_string(&golden).unwrap();
+        std::fs::write(&golden, golden_toml(&measured(b"tampered").log()).unwrap()).unwrap();
+        let err = verify(&measured(b"tampered"), Some(&cfg), SignaturePolicy::Classical).unwrap_err();
+        assert_eq!(err.code(), "integrity.rejected");
+        assert!(err.to_string().contains("does not match key `release`"), "{}", err);
+
+        std::fs::write(&golden, text).unwrap();
+        std::fs::remove_file(signature_path(&golden)).unwrap();
+        let err = verify(&measured(b"known good"), Some(&cfg), SignaturePolicy::Classical).unwrap_err();
+        assert!(err.to_string().contains("unsigned"), "{}", err);
+
+        sign(&key, &golden);
+        let revoked = MeasuredBootConfig { revoked_keys: vec!["release".to_string()], ..cfg };
+        let err = verify(&measured(b"known good"), Some(&revoked), SignaturePolicy::Classical).unwrap_err();
+        assert!(err.to_string().contains("revoked"), "{}", err);
+    }
+
+    #[test]
+    fn kernel_manifests_must_be_signed() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("kernel");
+        std::fs::create_dir(&tree).unwrap();
+        std::fs::write(tree.join("vmlinuz"), b"kernel image").unwrap();
+        let manifest = dir.path().join("kernel.manifest.json");
+        Fingerprinter::new(&tree).manifest(&manifest).run().unwrap();
+
+        let key = release_key();
+        let kernel = KernelFingerprintConfig {
+            root: tree.to_string_lossy().into_owned(),
+            algorithm: HashAlgorithm::Sha512,
+            expected: None,
+            manifest: Some(manifest.to_string_lossy().into_owned()),
+            output: None,
+            ownership: false,
+        };
+        let cfg = MeasuredBootConfig { kernel_fingerprint: Some(kernel), ..config(&key) };
+        let err = verify(&measured(b"boot"), Some(&cfg), SignaturePolicy::Classical).unwrap_err();
+        assert!(err.to_string().contains("unsigned"), "{}", err);
+
+        sign(&key, &manifest);
+        let boot = measured(b"boot");
+        let warnings = verify(&boot, Some(&cfg), SignaturePolicy::Classical).unwrap();
+        assert_eq!(warnings, vec!["no golden PCR values configured; measurements were recorded but not compared"]);
+        let manifest = Manifest::load(&manifest).unwrap();
+        assert_eq!(kernel_fingerprint(&boot.log()).unwrap(), Some(manifest.fingerprint));
+
+        std::fs::write(tree.join("vmlinuz"), b"patched kernel").unwrap();
+        let err = verify(&measured(b"boot"), Some(&cfg), SignaturePolicy::Classical).unwrap_err();
+        assert!(err.to_string().contains("vmlinuz"), "{}", err);
+    }
+
+    #[test]
+    fn kernel_mismatches_name_the_changed_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("kernel");
+        std::fs::create_dir_all(tree.join("modules")).unwrap();
+        for name in ["vmlinuz", "modules/a.ko", "modules/b.ko"] {
+            std::fs::write(tree.join(name), name).unwrap();
+        }
+        let manifest = dir.path().join("kernel.manifest.json");
+        let recorded = Fingerprinter::new(&tree).manifest(&manifest).run().unwrap();
+        let key = release_key();
+        sign(&key, &manifest);
+        let kernel = KernelFingerprintConfig {
+            root: tree.to_string_lossy().into_owned(),
+            algorithm: HashAlgorithm::Sha512,
+            expected: None,
+            manifest: Some(manifest.to_string_lossy().into_owned()),
+            output: None,
+            ownership: false,
+        };
+        let cfg = MeasuredBootConfig { kernel_fingerprint: Some(kernel.clone()), ..config(&key) };
+
+        std::fs::write(tree.join("modules/a.ko"), "rootkit").unwrap();
+        std::fs::remove_file(tree.join("modules/b.ko")).unwrap();
+        std::fs::write(tree.join("modules/c.ko"), "c").unwrap();
+        let err = verify(&measured(b"boot"), Some(&cfg), SignaturePolicy::Classical).unwrap_err().to_string();
+        assert!(err.contains(&format!("expected {}", recorded.fingerprint)), "{}", err);
+        for line in ["added modules/c.ko", "removed modules/b.ko", "modified modules/a.ko"] {
+            assert!(err.contains(line), "{} missing from {}", line, err);
+        }
+        assert!(!err.contains("vmlinuz"), "{}", err);
+
+        // An `expected` value alone cannot say what changed, but still fails the boot.
+        let cfg = MeasuredBootConfig {
+            kernel_fingerprint: Some(KernelFingerprintConfig { expected: Some(recorded.fingerprint), manifest: None, ..kernel }),
+            ..config(&key)
+        };
+        let err = verify(&measured(b"boot"), Some(&cfg), SignaturePolicy::Classical).unwrap_err().to_string();
+        assert!(!err.contains("modules/"), "{}", err);
+    }
+}
@@ -1,177 +1,162 @@
-use sha2::{Sha512, Digest};
-use std::{
-    fs::{self, File},
-    io::{self, Read, Write},
-    path::Path,
-    process::Command,
-    time::Duration,
-};
-use tokio::time::sleep;
-use anyhow::{Result, Context};
-
-const STATE_HASH_PATH: &str = "/secure/state_hashes/vsc_master_kernel_fingerprint.sha512";
-const ENCRYPTED_HASH_PATH: &str = "/secure/state_hashes/vsc_master_kernel_fingerprint.sha512.gpg";
-const LATEST_IPFS_CID_PATH: &str = "/secure/state_hashes/_latest_ipfs_fingerprint.cid";
-const AUDIT_LOG_PATH: &str = "/secure/logs/kernel_resource_enforcement.log";
-
-/// Recursively read files in directory, compute SHA-512 fingerprint of concatenated sorted hashes.
-fn generate_sha512_fingerprint<P: AsRef<Path>>(directory: P) -> Result<String> {
-    let mut hashes = Vec::new();
-
-    for entry in walkdir::WalkDir::new(directory) {
-        let entry = entry?;
-        if entry.file_type().is_file() {
-            let mut file = File::open(entry.path())?;
-            let mut hasher = Sha512::new();
-            let mut buffer = [0u8; 8192];
-            loop {
-                let n = file.read(&mut buffer)?;
-                if n == 0 { break; }
-                hasher.update(&buffer[..n]);
-            }
-            let hash_result = hasher.finalize_reset();
-            hashes.push((entry.path().to_owned(), hex::encode(hash_result)));
+//! Command-line front end for `mesh_sec_ai_boot::fingerprint`. With no options it
+//! fingerprints `./rust_master_system` and writes the result and an audit log line to
+//! the paths the tool has always used; `--encrypt` and `--ipfs` add the GPG and IPFS
+//! publishing steps. `--cache PATH` reuses digests of files unchanged since the last run
+//! recorded there. `diff OLD NEW` compares two manifests written with `--manifest`;
+//! `prove` and `verify-proof` produce and check Merkle inclusion proofs for single files.
+
+use mesh_sec_ai_boot::error::{BootError, ErrorDetail};
+use mesh_sec_ai_boot::fingerprint::{self, Fingerprinter, GpgSink, InclusionProof, IpfsSink, Manifest};
+use mesh_sec_ai_boot::schema::HashAlgorithm;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "usage: kernel_fingerprint [--root DIR] [--algorithm sha256|sha512] [--output PATH]
+                          [--manifest PATH] [--audit-log PATH] [--encrypt] [--ipfs CID_FILE]
+                          [--start-ipfs-daemon] [--cache PATH] [--ownership]
+                          [--json]
+       kernel_fingerprint diff [--json] OLD_MANIFEST NEW_MANIFEST
+       kernel_fingerprint prove MANIFEST PATH
+       kernel_fingerprint verify-proof PROOF FINGERPRINT [FILE]
+
+  --root DIR        tree to fingerprint (default ./rust_master_system)
+  --output PATH     write the hex fingerprint here, mode 0600
+                    (default /secure/state_hashes/vsc_master_kernel_fingerprint.sha512)
+  --manifest PATH   write every entry's path, kind, size, mode, mtime and digest or
+                    symlink target as JSON
+  --audit-log PATH  append a line naming the fingerprint and where it was published
+                    (default /secure/logs/kernel_resource_enforcement.log)
+  --encrypt         encrypt the output with `gpg --symmetric` into PATH.gpg
+  --ipfs CID_FILE   add the (encrypted) output to IPFS and write the CID to CID_FILE
+  --start-ipfs-daemon
+                    start `ipfs daemon` before adding
+  --cache PATH      reuse digests of files whose inode, size, mtime and ctime are
+                    unchanged since the last run recorded in PATH, mode 0600;
+                    without it every file is read and hashed
+  --ownership       include each entry's uid and gid in the fingerprint
+  --json            print the result as JSON
+
+The fingerprint covers every entry's path, kind, permissions and contents or symlink
+target; entries that cannot be read are included as unreadable and listed as warnings.
+diff lists entries added (+), removed (-), modified (~), with changed permissions (m)
+and with changed owner (o) between two manifests; exit status 0 when identical, 65 when they differ.
+prove prints the inclusion proof for PATH (relative to the manifest's root) as JSON;
+verify-proof checks it against a trusted FINGERPRINT and, given FILE, that FILE is
+the proven file; exit status 65 when it does not hold";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err((message, code)) => {
+            eprintln!("Error: {}", message);
+            ExitCode::from(code)
         }
     }
-    // Sort by path to ensure consistency
-    hashes.sort_by_key(|(path, _)| path.clone());
-
-    // Concatenate hashes only
-    let concat = hashes.iter().map(|(_, h)| h.clone()).collect::<String>();
-    let mut final_hasher = Sha512::new();
-    final_hasher.update(concat.as_bytes());
-    Ok(hex::encode(final_hasher.finalize()))
 }
 
-/// Write fingerprint to file with strict permissions.
-fn write_fingerprint_to_file(fingerprint: &str) -> io::Result<()> {
-    fs::create_dir_all(Path::new(STATE_HASH_PATH).parent().unwrap())?;
-    let mut file = File::create(STATE_HASH_PATH)?;
-    file.write_all(fingerprint.as_bytes())?;
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        fs::set_permissions(STATE_HASH_PATH, fs::Permissions::from_mode(0o600))?;
+fn run() -> Result<(), (String, u8)> {
+    let usage = |message: &str| (format!("{}\n{}", message, USAGE), 64);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["diff", old, new] => return diff(old, new, false),
+        ["diff", "--json", old, new] => return diff(old, new, true),
+        ["diff", ..] => return Err(usage("diff needs two manifests")),
+        ["prove", manifest, path] => return prove(manifest, path),
+        ["verify-proof", proof, fingerprint] => return verify_proof(proof, fingerprint, None),
+        ["verify-proof", proof, fingerprint, file] => return verify_proof(proof, fingerprint, Some(file)),
+        ["prove" | "verify-proof", ..] => return Err(usage("wrong number of arguments")),
+        _ => {}
     }
-    Ok(())
-}
-
-/// Encrypt fingerprint file symmetrically using AES256 via GPG CLI.
-fn encrypt_fingerprint_file() -> Result<()> {
-    let status = Command::new("gpg")
-        .arg("--batch")
-        .arg("--yes")
-        .arg("--symmetric")
-        .arg("--cipher-algo")
-        .arg("AES256")
-        .arg(STATE_HASH_PATH)
-        .status()
-        .context("Failed to execute gpg encryption")?;
-
-    if !status.success() {
-        anyhow::bail!("GPG encryption process failed with: {:?}", status);
+    let (mut manifest, mut cache, mut ownership) = (None, None, false);
+    let (mut root, mut output, mut audit_log) =
+        ("./rust_master_system".to_string(), fingerprint::STATE_HASH_PATH.to_string(), fingerprint::AUDIT_LOG_PATH.to_string());
+    let (mut algorithm, mut encrypt, mut ipfs, mut start_daemon, mut json) = (HashAlgorithm::Sha512, false, None, false, false);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| usage(&format!("{} needs a value", arg)));
+        match arg.as_str() {
+            "--root" => root = value()?,
+            "--output" => output = value()?,
+            "--manifest" => manifest = Some(value()?),
+            "--audit-log" => audit_log = value()?,
+            "--algorithm" => algorithm = match value()?.as_str() {
+                "sha256" => HashAlgorithm::Sha256,
+                "sha512" => HashAlgorithm::Sha512,
+                other => return Err(usage(&format!("unknown algorithm `{}`", other))),
+            },
+            "--encrypt" => encrypt = true,
+            "--ipfs" => ipfs = Some(value()?),
+            "--start-ipfs-daemon" => start_daemon = true,
+            "--cache" => cache = Some(value()?),
+            "--ownership" => ownership = true,
+            "--json" => json = true,
+            "-h" | "--help" => return Err((USAGE.to_string(), 64)),
+            other => return Err(usage(&format!("unknown argument `{}`", other))),
+        }
     }
-    Ok(())
-}
 
-/// Ensure IPFS installed and initialized; install if missing.
-fn ensure_ipfs_installed() -> Result<()> {
-    if Command::new("ipfs").arg("version").output().is_err() {
-        // Installation logic here; omitted for brevity.
-        anyhow::bail!("IPFS not installed; manual installation required.");
+    let mut fingerprinter = Fingerprinter::new(root).algorithm(algorithm).ownership(ownership).output(output);
+    if let Some(cache) = cache {
+        fingerprinter = fingerprinter.cache(cache);
     }
-    if !Path::new("/root/.ipfs").exists() {
-        let status = Command::new("ipfs")
-            .arg("init")
-            .status()
-            .context("Failed to initialize IPFS")?;
-        if !status.success() { anyhow::bail!("IPFS init failed"); }
+    if let Some(manifest) = manifest {
+        fingerprinter = fingerprinter.manifest(manifest);
+    }
+    if encrypt {
+        fingerprinter = fingerprinter.sink(GpgSink);
+    }
+    if let Some(cid_file) = ipfs {
+        fingerprinter = fingerprinter.sink(IpfsSink {
+            cid_file: cid_file.into(),
+            start_daemon: start_daemon.then_some(Duration::from_secs(7)),
+        });
+    }
+    let result = fingerprinter.audit_log(audit_log).run().map_err(|e| (e.to_string(), e.exit_code()))?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result).expect("FingerprintResult serializes to JSON"));
+    } else {
+        println!("🧬 {} of {} ({} files, {} bytes, {} cached): {}", result.algorithm, result.root.display(),
+                 result.files, result.bytes, result.cache_hits, result.fingerprint);
+        for published in &result.published {
+            println!("   → {}: {}", published.sink, published.location);
+        }
+    }
+    for entry in result.unreadable() {
+        eprintln!("⚠️ unreadable: {} ({})", entry.path, entry.error.as_deref().unwrap_or("unknown error"));
     }
     Ok(())
 }
 
-/// Start IPFS daemon asynchronously.
-async fn start_ipfs_daemon() -> Result<()> {
-    let mut child = Command::new("ipfs")
-        .arg("daemon")
-        .spawn()
-        .context("Failed to start IPFS daemon")?;
-    sleep(Duration::from_secs(7)).await;
-    // Optionally capture child's PID, stdout/stderr here.
-    Ok(())
-}
-
-/// Add encrypted fingerprint file to IPFS, returning CID.
-fn ipfs_add(path: &str) -> Result<String> {
-    let output = Command::new("ipfs")
-        .arg("add")
-        .arg("-Q")  // print only hash
-        .arg(path)
-        .output()
-        .context("Failed to run ipfs add")?;
-
-    if !output.status.success() {
-        anyhow::bail!("ipfs add failed with stderr: {}", String::from_utf8_lossy(&output.stderr));
-    }
-    let cid = String::from_utf8(output.stdout)?.trim().to_string();
-    Ok(cid)
+fn fail(error: ErrorDetail) -> (String, u8) {
+    let error = BootError::Integrity(error);
+    (error.to_string(), error.exit_code())
 }
 
-/// Write last CID to marker file.
-fn write_latest_ipfs_cid(cid: &str) -> io::Result<()> {
-    fs::write(LATEST_IPFS_CID_PATH, cid)
+fn prove(manifest: &str, path: &str) -> Result<(), (String, u8)> {
+    let proof = Manifest::load(Path::new(manifest)).and_then(|m| m.prove(path)).map_err(fail)?;
+    println!("{}", proof.to_json());
+    Ok(())
 }
 
-/// Append event log with timestamp.
-fn append_to_audit_log(fingerprint: &str, cid: &str) -> io::Result<()> {
-    use chrono::Utc;
-    let timestamp = Utc::now().to_rfc3339();
-    let log_entry = format!(
-        "[{}] Kernel Fingerprint Encrypted & IPFS Synced: HASH={} | CID={}\n",
-        timestamp, fingerprint, cid
-    );
-    fs::create_dir_all(Path::new(AUDIT_LOG_PATH).parent().unwrap())?;
-    fs::OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(AUDIT_LOG_PATH)?
-        .write_all(log_entry.as_bytes())?;
+fn verify_proof(proof: &str, fingerprint: &str, file: Option<&str>) -> Result<(), (String, u8)> {
+    let proof = InclusionProof::load(Path::new(proof)).map_err(fail)?;
+    match file {
+        Some(file) => proof.verify_file(fingerprint, Path::new(file)),
+        None => proof.verify(fingerprint),
+    }.map_err(fail)?;
+    println!("✅ {} is included in {}", proof.entry.path, fingerprint);
     Ok(())
 }
 
-/// Main orchestration encompassing fingerprint generation, encryption, IPFS syncing, and logging.
-#[tokio::main]
-async fn main() -> Result<()> {
-    println!("╔═════════════════════════════════════════════════════════════════╗");
-    println!("║ ENCRYPTION + IPFS-SYNC: KERNEL FINGERPRINT MODE [ACTIVE]       ║");
-    println!("╚═════════════════════════════════════════════════════════════════╝");
-
-    let fingerprint = generate_sha512_fingerprint("./rust_master_system")?;
-    println!("[+] Fingerprint Hash Generated: {}", &fingerprint);
-
-    write_fingerprint_to_file(&fingerprint)?;
-    println!("[+] Fingerprint written to {}", STATE_HASH_PATH);
-
-    encrypt_fingerprint_file()?;
-    println!("[+] Encrypted fingerprint file created: {}", ENCRYPTED_HASH_PATH);
-
-    ensure_ipfs_installed()?;
-
-    start_ipfs_daemon().await?;
-    println!("[+] IPFS Daemon Initialized");
-
-    let ipfs_hash = ipfs_add(ENCRYPTED_HASH_PATH)?;
-    println!("[+] IPFS Sync Complete — File Hash: {}", &ipfs_hash);
-
-    write_latest_ipfs_cid(&ipfs_hash)?;
-
-    append_to_audit_log(&fingerprint, &ipfs_hash)?;
-
-    println!("╔═════════════════════════════════════════════════════════════════╗");
-    println!("║ ➤ KERNEL FINGERPRINT DEPLOYED & IPFS-VERIFIED (CID Logged)     ║");
-    println!("║ ➤ SHA512: {}                                          ║", fingerprint);
-    println!("║ ➤ IPFS CID: {}                                          ║", ipfs_hash);
-    println!("╚═════════════════════════════════════════════════════════════════╝");
-
-    Ok(())
+fn diff(old: &str, new: &str, json: bool) -> Result<(), (String, u8)> {
+    let load = |path: &str| Manifest::load(Path::new(path)).map_err(fail);
+    let diff = load(old)?.diff(&load(new)?).map_err(fail)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff).expect("ManifestDiff serializes to JSON"));
+    } else {
+        println!("{}", diff);
+    }
+    if diff.is_empty() { Ok(()) } else { Err((format!("{} and {} differ", old, new), 65)) }
 }
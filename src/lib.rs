@@ -1,16 +1,29 @@
-
-cargo init --name mesh_sec_ai_boot --vcs none .
-`src/main.rs`:
-fn main() -> Result<(), String> {
-    mesh_sec_ai_boot::boot::launch()
-}
 pub mod boot;
-pub mod core;
+pub mod stage;
+pub mod profile;
+pub mod error;
+pub mod report;
 pub mod ai;
 pub mod fs;
 pub mod compliance;
 pub mod integrity;
 pub mod security;
+pub mod signature;
+pub mod shell;
+pub mod sandbox;
+pub mod firmware;
 pub mod schema;
+pub mod config;
+pub mod migrate;
+pub mod validate;
+pub mod policy;
 pub mod hal;
 pub mod drivers;
+pub mod audit;
+pub mod measure;
+#[cfg(feature = "tpm")]
+pub mod tpm;
+pub mod tpm_quote;
+pub mod attest;
+pub mod fingerprint;
+pub mod merkle;
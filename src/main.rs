@@ -0,0 +1,410 @@
+use mesh_sec_ai_boot::attest::{self, AttestationPolicy, Attester, NodeKey};
+use mesh_sec_ai_boot::audit::Auditor;
+use mesh_sec_ai_boot::error::{BootError, ErrorDetail};
+use mesh_sec_ai_boot::measure::EventLog;
+use mesh_sec_ai_boot::policy::{Policy, TestSuite};
+use mesh_sec_ai_boot::signature::{self, SignatureBundle};
+use mesh_sec_ai_boot::{boot, config::{self, Loader}, integrity, migrate::CURRENT_SCHEMA_VERSION, schema::{BootConfig, KeyAlgorithm}};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: mesh_sec_ai_boot [--config PATH] [--set KEY=VALUE]... [--profile NAME] [--report PATH] [--dry-run] [COMMAND]
+
+profiles: production (default), staging, recovery, developer
+
+commands:
+  (none)            resolve the layered config and boot; with --dry-run, print
+                    what each stage would do instead
+  validate CONFIG   load and semantically validate a single config file
+  config explain    print every resolved value and the layer it came from
+  config migrate [--write] CONFIG...
+                    upgrade config files to the current schema version; --write
+                    rewrites them in place, keeping comments and the original as
+                    CONFIG.bak (CONFIG.bak.1, ... if that exists)
+  policy test POLICY FIXTURES
+                    evaluate each fixture request against a policy file and
+                    compare with its expected decision
+  audit [--root DIR] [--apply] [--apply-irreversible] [--json] [--min-score N]
+                    score kernel hardening (sysctls, mount options, module
+                    loading) against the baseline; DIR stands in for / when
+                    auditing a fake procfs tree; --apply writes the
+                    recommended sysctls (root only) except those that stay
+                    set until reboot, which --apply-irreversible adds
+  measure replay LOG
+                    replay a measured boot event log and print the final PCR
+                    values as [[measured_boot.golden]] tables, e.g. for
+                    measured_boot.golden_file
+  measure sign KEY FILE
+                    sign a golden file or kernel manifest with a node key from
+                    `attest keygen`, adding the signature to FILE.sig; list the
+                    key's [[trusted_keys]] entry under measured_boot
+  tpm seal SECRET OUT
+                    seal the data key in file SECRET to the current values of
+                    measured_boot.tpm.seal_pcrs, writing the blob to OUT
+  tpm quote NONCE   print a TPM quote over the stage PCRs for a hex nonce
+                    (tpm commands need a build with --features tpm)
+  attest keygen ALGORITHM ID OUT
+                    write a new node signing key to OUT and print its
+                    [[trusted_keys]] entry for verifier policies
+  attest serve      answer GET /quote?nonce=HEX on attestation.listen with
+                    signed quotes of measured_boot.event_log; software-only
+                    unless built with --features tpm and measured_boot.tpm is
+                    set, which adds a TPM quote
+  attest quote NONCE
+                    print a signed quote for a hex nonce
+  attest verify POLICY ADDR
+                    request a quote from the node at ADDR with a fresh nonce
+                    and check it against an attestation policy file
+
+exit status: 0 ok, 64 usage, 65 rejected (integrity/signature; do not retry),
+69 unsupported platform, 70 internal, 71 recovery shell could not start,
+74 report not written, 75 transient I/O (retry), 77 recovery shell not permitted,
+78 invalid config";
+
+enum CliError {
+    Usage(String),
+    Io(String),
+    Boot(BootError),
+}
+
+impl CliError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Usage(_) => 64,
+            CliError::Io(_) => 74,
+            CliError::Boot(e) => e.exit_code(),
+        }
+    }
+}
+
+impl<E: Into<BootError>> From<E> for CliError {
+    fn from(error: E) -> Self {
+        CliError::Boot(error.into())
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            match &error {
+                CliError::Usage(message) | CliError::Io(message) => eprintln!("Error: {}", message),
+                CliError::Boot(e) => {
+                    eprintln!("Error: {}", e);
+                    for cause in e.chain().iter().skip(1) {
+                        eprintln!("  caused by: {}", cause);
+                    }
+                }
+            }
+            ExitCode::from(error.exit_code())
+        }
+    }
+}
+
+fn run() -> Result<(), CliError> {
+    let mut loader = Loader::new();
+    let mut report_path = None;
+    let mut dry_run = false;
+    let mut rest = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => loader = loader.user_file(Some(PathBuf::from(value(&mut args, "--config")?))),
+            "--set" => loader = loader.set(value(&mut args, "--set")?),
+            "--profile" => loader = loader.set(format!("profile={}", value(&mut args, "--profile")?)),
+            "--report" => report_path = Some(PathBuf::from(value(&mut args, "--report")?)),
+            "--dry-run" => dry_run = true,
+            "-h" | "--help" => return Err(CliError::Usage(USAGE.to_string())),
+            _ => rest.push(arg),
+        }
+    }
+    let mut rest: Vec<&str> = rest.iter().map(String::as_str).collect();
+    // A lone positional argument is the node's config file, as before layering existed.
+    if let [path] = rest.as_slice() {
+        if !path.starts_with('-') && !is_command(path) {
+            loader = loader.user_file(Some(PathBuf::from(path)));
+            rest.clear();
+        }
+    }
+    match rest.as_slice() {
+        [] if dry_run => {
+            println!("{}", boot::dry_run(loader.resolve()?.config)?);
+            Ok(())
+        }
+        [] => run_boot(loader.resolve()?.config, report_path.as_deref()),
+        ["validate", path] => validate(path),
+        ["config", "explain"] => {
+            println!("{}", loader.resolve()?.explain());
+            Ok(())
+        }
+        ["config", "migrate", files @ ..] if !files.is_empty() => migrate(files),
+        ["policy", "test", policy, fixtures] => policy_test(policy, fixtures),
+        ["audit", args @ ..] => audit(args),
+        ["tpm", args @ ..] => tpm_command(loader.resolve()?.config, args),
+        ["attest", "keygen", algorithm, id, out] => attest_keygen(algorithm, id, out),
+        ["attest", "verify", policy, addr] => attest_verify(policy, addr),
+        ["attest", args @ ..] => attest_command(loader.resolve()?.config, args),
+        ["measure", "replay", log] => {
+            print!("{}", integrity::golden_toml(&EventLog::load(Path::new(log))?)?);
+            Ok(())
+        }
+        ["measure", "sign", key, file] => measure_sign(key, file),
+        _ => Err(CliError::Usage(USAGE.to_string())),
+    }
+}
+
+/// First words of the commands `run` dispatches on; keep in step with `USAGE`.
+const COMMANDS: &[&str] = &["validate", "config", "policy", "audit", "measure", "tpm", "attest"];
+
+fn is_command(word: &str) -> bool {
+    COMMANDS.contains(&word)
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, CliError> {
+    args.next().ok_or_else(|| CliError::Usage(format!("{} needs a value\n{}", flag, USAGE)))
+}
+
+/// Boot, print the stage table and optionally write the JSON report for fleet tooling.
+/// The report file is created before booting, while the filesystem sandbox still
+/// allows it.
+fn run_boot(config: BootConfig, report_path: Option<&Path>) -> Result<(), CliError> {
+    let report_error = |path: &Path, e: std::io::Error| {
+        CliError::Io(format!("{}: cannot write boot report: {}", path.display(), e))
+    };
+    let report_file = report_path
+        .map(|path| File::create(path).map_err(|e| report_error(path, e)).map(|file| (path, file)))
+        .transpose()?;
+    let (report, result) = match boot::launch_with(config) {
+        Ok(report) => (report, Ok(())),
+        Err(failure) => (*failure.report, Err(CliError::Boot(failure.error))),
+    };
+    println!("\n{}", report);
+    if let Some((path, mut file)) = report_file {
+        file.write_all(report.to_json().as_bytes()).map_err(|e| report_error(path, e))?;
+    }
+    result
+}
+
+/// Run only the load, profile and semantic validation passes, as a boot would, for
+/// linting configs in CI.
+fn validate(path: &str) -> Result<(), CliError> {
+    let mut config = BootConfig::from_path(path)?;
+    for note in config.profile.apply(&mut config) {
+        println!("🧭 {}", note);
+    }
+    let report = config.validate();
+    println!("{}", report);
+    if report.has_errors() {
+        return Err(BootError::Config(ErrorDetail::invalid(
+            format!("{}: validation failed", path))).into());
+    }
+    Ok(())
+}
+
+fn migrate(args: &[&str]) -> Result<(), CliError> {
+    let write = args.contains(&"--write");
+    let files: Vec<&str> = args.iter().copied().filter(|a| *a != "--write").collect();
+    if files.is_empty() {
+        return Err(CliError::Usage(USAGE.to_string()));
+    }
+    for file in files {
+        let outcome = config::migrate_file(Path::new(file), write)?;
+        if outcome.applied.is_empty() {
+            eprintln!("{}: already at schema version {}", file, CURRENT_SCHEMA_VERSION);
+            continue;
+        }
+        for step in &outcome.applied {
+            eprintln!("{}: v{} → v{}: {}", file, step.from, step.from + 1, step.description);
+        }
+        match &outcome.backup {
+            Some(backup) => eprintln!("{}: rewritten, original kept at {}", file, backup.display()),
+            None => print!("{}", outcome.rendered),
+        }
+    }
+    Ok(())
+}
+
+fn policy_test(policy: &str, fixtures: &str) -> Result<(), CliError> {
+    let policy = Policy::load(Path::new(policy))?;
+    let suite = TestSuite::load(Path::new(fixtures))?;
+    let mut failed = 0;
+    for case in &suite.cases {
+        let decision = policy.evaluate(&case.request());
+        let rule = decision.rule.as_deref().map_or("default".to_string(), |id| format!("rule `{}`", id));
+        if decision.effect == case.expect {
+            println!("✅ {}: {} ({})", case.name, decision.effect, rule);
+        } else {
+            failed += 1;
+            println!("❌ {}: expected {}, got {} ({})", case.name, case.expect, decision.effect, rule);
+        }
+    }
+    println!("{} passed, {} failed (policy version {})", suite.cases.len() - failed, failed, policy.version);
+    if failed > 0 {
+        return Err(BootError::Policy(ErrorDetail::rejected(
+            format!("{}: {} case(s) failed", fixtures, failed))).into());
+    }
+    Ok(())
+}
+
+fn audit(args: &[&str]) -> Result<(), CliError> {
+    let (mut root, mut apply, mut irreversible, mut json, mut min_score) = ("/", false, false, false, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--root" => root = args.next().ok_or_else(|| CliError::Usage(format!("--root needs a value\n{}", USAGE)))?,
+            "--apply" => apply = true,
+            "--apply-irreversible" => (apply, irreversible) = (true, true),
+            "--json" => json = true,
+            "--min-score" => min_score = Some(args.next().and_then(|n| n.parse::<u8>().ok())
+                .ok_or_else(|| CliError::Usage(format!("--min-score needs a number\n{}", USAGE)))?),
+            _ => return Err(CliError::Usage(USAGE.to_string())),
+        }
+    }
+    let auditor = Auditor::new(root).allow_irreversible(irreversible);
+    let mut report = auditor.run();
+    if apply {
+        for change in auditor.apply(&report)? {
+            eprintln!("🧰 {}", change);
+        }
+        report = auditor.run();
+    }
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        println!("{}", report);
+    }
+    match min_score {
+        Some(min) => report.require(min).map_err(|e| BootError::Enforcement(e).into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "tpm")]
+fn tpm_command(config: BootConfig, args: &[&str]) -> Result<(), CliError> {
+    use mesh_sec_ai_boot::{measure::STAGE_PCRS, schema::HashAlgorithm, tpm::Tpm};
+    let cfg = config.measured_boot.as_ref().and_then(|m| m.tpm.as_ref())
+        .ok_or_else(|| CliError::Usage("no [measured_boot.tpm] section configured".to_string()))?;
+    let tpm = Tpm::open(cfg).map_err(BootError::Integrity)?;
+    match args {
+        ["seal", secret, out] => {
+            let data = std::fs::read(secret)
+                .map_err(|e| BootError::Integrity(ErrorDetail::io(format!("cannot read {}", secret), e)))?;
+            let blob = tpm.seal(&data, &cfg.seal_pcrs).map_err(BootError::Integrity)?;
+            let json = serde_json::to_string_pretty(&blob).expect("SealedBlob serializes to JSON");
+            std::fs::write(out, json)
+                .map_err(|e| BootError::Integrity(ErrorDetail::io(format!("cannot write {}", out), e)))?;
+            eprintln!("sealed {} bytes to PCRs {:?} in {}", data.len(), cfg.seal_pcrs, out);
+            Ok(())
+        }
+        ["quote", nonce] => {
+            let nonce = hex::decode(nonce).map_err(|_| CliError::Usage("NONCE must be hex".to_string()))?;
+            let quote = tpm.quote(&nonce, HashAlgorithm::Sha256, STAGE_PCRS).map_err(BootError::Integrity)?;
+            println!("{}", serde_json::to_string_pretty(&quote).expect("Quote serializes to JSON"));
+            Ok(())
+        }
+        _ => Err(CliError::Usage(USAGE.to_string())),
+    }
+}
+
+#[cfg(not(feature = "tpm"))]
+fn tpm_command(_config: BootConfig, _args: &[&str]) -> Result<(), CliError> {
+    Err(BootError::Integrity(ErrorDetail::unsupported("built without the `tpm` feature")).into())
+}
+
+fn attest_keygen(algorithm: &str, id: &str, out: &str) -> Result<(), CliError> {
+    use std::os::unix::fs::OpenOptionsExt;
+    let algorithm: KeyAlgorithm = serde_json::from_value(serde_json::Value::String(algorithm.to_string()))
+        .map_err(|_| CliError::Usage("ALGORITHM must be ed25519, ecdsa-p256 or ml-dsa-65".to_string()))?;
+    let key = NodeKey::generate(id, algorithm)?;
+    let json = serde_json::to_string_pretty(&key).expect("NodeKey serializes to JSON");
+    std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(out)
+        .and_then(|mut f| f.write_all(json.as_bytes()))
+        .map_err(|e| CliError::Io(format!("cannot write {}: {}", out, e)))?;
+    let trusted = toml::to_string(&key.trusted_key()?).expect("TrustedKey serializes to TOML");
+    eprintln!("🔑 wrote {} key `{}` to {}", algorithm, id, out);
+    print!("[[trusted_keys]]\n{}\n", trusted);
+    Ok(())
+}
+
+/// Add a signature by `key` to `FILE.sig`, replacing an earlier one by the same key, so
+/// a classical and a post-quantum key can both sign for hybrid policies.
+fn measure_sign(key: &str, file: &str) -> Result<(), CliError> {
+    let key = NodeKey::load(Path::new(key))?;
+    let contents = std::fs::read(file).map_err(|e| CliError::Io(format!("cannot read {}: {}", file, e)))?;
+    let added = key.sign(&integrity::reference_message(&contents))?;
+    let path = integrity::signature_path(Path::new(file));
+    let mut signatures = match path.exists() {
+        true => signature::read_signatures(&path).map_err(BootError::Integrity)?,
+        false => Vec::new(),
+    };
+    signatures.retain(|s| s.key_id != added.key_id);
+    signatures.push(added);
+    let json = serde_json::to_string_pretty(&SignatureBundle { signatures }).expect("SignatureBundle serializes to JSON");
+    std::fs::write(&path, json).map_err(|e| CliError::Io(format!("cannot write {}: {}", path.display(), e)))?;
+    println!("🔏 signed {} with `{}` → {}", file, key.id, path.display());
+    Ok(())
+}
+
+fn attest_command(config: BootConfig, args: &[&str]) -> Result<(), CliError> {
+    let cfg = config.attestation.as_ref()
+        .ok_or_else(|| CliError::Usage("no [attestation] section configured".to_string()))?;
+    let event_log = config.measured_boot.as_ref().and_then(|m| m.event_log.as_deref())
+        .ok_or_else(|| CliError::Usage("attestation needs measured_boot.event_log".to_string()))?;
+    let attester = Attester::new(cfg, Path::new(event_log))?;
+    #[cfg(feature = "tpm")]
+    let attester = match config.measured_boot.as_ref().and_then(|m| m.tpm.as_ref()) {
+        Some(tpm) => attester.with_tpm(mesh_sec_ai_boot::tpm::Tpm::open(tpm).map_err(BootError::Attestation)?),
+        None => attester,
+    };
+    match args {
+        ["serve"] => {
+            let listener = std::net::TcpListener::bind(&cfg.listen).map_err(|e| BootError::Attestation(
+                ErrorDetail::io(format!("cannot listen on {}", cfg.listen), e)))?;
+            println!("🛰️ Attestation: serving quotes of {} on {}", event_log, cfg.listen);
+            Ok(attest::serve(listener, &attester)?)
+        }
+        ["quote", nonce] => {
+            let nonce = hex::decode(nonce).map_err(|_| CliError::Usage("NONCE must be hex".to_string()))?;
+            let quote = attester.quote(&nonce)?;
+            println!("{}", serde_json::to_string_pretty(&quote).expect("SignedQuote serializes to JSON"));
+            Ok(())
+        }
+        _ => Err(CliError::Usage(USAGE.to_string())),
+    }
+}
+
+fn attest_verify(policy: &str, addr: &str) -> Result<(), CliError> {
+    let policy = AttestationPolicy::load(Path::new(policy))?;
+    let nonce: [u8; 32] = attest::random_bytes()?;
+    let quote = attest::fetch(addr, &nonce)?;
+    let verdict = attest::verify(&quote, &policy, &nonce, chrono::Utc::now()).map_err(BootError::Attestation)?;
+    println!("✅ {} attested ({}): signed by {}, {} golden PCR value(s) match, kernel {}", addr,
+             if verdict.tpm_bound { "TPM-bound" } else { "software-only" },
+             verdict.key_ids.join(", "), verdict.golden_checked,
+             verdict.kernel_fingerprint.as_deref().unwrap_or("not fingerprinted"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_listed_command_is_recognised() {
+        let listed: Vec<&str> = USAGE.lines()
+            .skip_while(|line| *line != "commands:")
+            .take_while(|line| !line.starts_with("exit status"))
+            .filter_map(|line| line.strip_prefix("  ").filter(|l| !l.starts_with(' ') && !l.starts_with('(')))
+            .filter_map(|line| line.split_whitespace().next())
+            .collect();
+        for command in &listed {
+            assert!(is_command(command), "{}", command);
+        }
+        assert!(COMMANDS.iter().all(|c| listed.contains(c)), "{:?} vs {:?}", COMMANDS, listed);
+        for path in ["boot.toml", "config/boot.toml", "commands:", "exit", "(none)"] {
+            assert!(!is_command(path), "{}", path);
+        }
+    }
+}
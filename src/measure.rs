@@ -0,0 +1,454 @@
+//! Software measured boot. Stages hash what they are about to load into PCR banks with
+//! TPM extend semantics, `PCR = H(PCR || digest)`, and append a TCG-style event for
+//! every measurement so `integrity::verify` can replay the log. Each stage extends its
+//! own PCR, so stages of one wave may measure concurrently without changing the result.
+//! With the `tpm` feature and a configured TPM, every extend is mirrored into the TPM.
+
+use crate::error::{BootError, ErrorDetail};
+use crate::fingerprint;
+use crate::schema::HashAlgorithm;
+#[cfg(feature = "tpm")]
+use crate::tpm::Tpm;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256, Sha512};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+pub const PCR_COUNT: u32 = 24;
+/// Firmware image.
+pub const PCR_FIRMWARE: u32 = 0;
+/// Profile and enforcement configuration.
+pub const PCR_CONFIG: u32 = 1;
+/// Policy file and the keys that signed the firmware.
+pub const PCR_POLICY: u32 = 7;
+pub const PCR_MODELS: u32 = 8;
+pub const PCR_FILESYSTEM: u32 = 9;
+pub const PCR_COMPLIANCE: u32 = 10;
+/// Kernel tree fingerprint, see `integrity::KERNEL_EVENT`.
+pub const PCR_KERNEL: u32 = 11;
+/// Every PCR the built-in stages extend.
+pub const STAGE_PCRS: &[u32] = &[PCR_FIRMWARE, PCR_CONFIG, PCR_POLICY, PCR_MODELS, PCR_FILESYSTEM, PCR_COMPLIANCE, PCR_KERNEL];
+
+/// Subset of the TCG PC Client event types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EventType {
+    /// Firmware code.
+    EvPostCode,
+    /// Code or data loaded by a boot stage, e.g. a model.
+    EvIpl,
+    /// Configuration and policy.
+    EvEventTag,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventDigest {
+    pub algorithm: HashAlgorithm,
+    /// Hex digest of the measured data.
+    pub digest: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Event {
+    pub pcr_index: u32,
+    pub event_type: EventType,
+    /// One digest per bank.
+    pub digests: Vec<EventDigest>,
+    /// What was measured, e.g. `firmware /boot/node.img`.
+    pub event: String,
+}
+
+/// A PCR that was already extended, e.g. by platform firmware, when the boot began.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InitialPcr {
+    pub pcr: u32,
+    pub bank: HashAlgorithm,
+    pub value: String,
+}
+
+/// JSON event log, in extend order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventLog {
+    pub version: u32,
+    pub banks: Vec<HashAlgorithm>,
+    /// Starting values of non-zero PCRs; empty for software-only boots.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub initial: Vec<InitialPcr>,
+    pub events: Vec<Event>,
+}
+
+/// One bank of PCRs; registers never extended read as all zeros.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcrBank {
+    pub algorithm: HashAlgorithm,
+    pub values: BTreeMap<u32, Vec<u8>>,
+}
+
+pub fn hash(algorithm: HashAlgorithm, data: &[u8]) -> Vec<u8> {
+    match algorithm {
+        HashAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+        HashAlgorithm::Sha512 => Sha512::digest(data).to_vec(),
+    }
+}
+
+fn write_log(path: &Path, log: &EventLog) -> Result<(), BootError> {
+    fingerprint::write_private(path, log.to_json().as_bytes()).map_err(|e| BootError::Integrity(
+        ErrorDetail::io(format!("cannot write event log {}", path.display()), e)))
+}
+
+/// Hash `path` with every algorithm in one pass.
+pub fn hash_file(algorithms: &[HashAlgorithm], path: &Path) -> std::io::Result<Vec<Vec<u8>>> {
+    let (mut sha256, mut sha512) = (Sha256::new(), Sha512::new());
+    let mut file = File::open(path)?;
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 { break; }
+        sha256.update(&buffer[..n]);
+        sha512.update(&buffer[..n]);
+    }
+    let (sha256, sha512) = (sha256.finalize().to_vec(), sha512.finalize().to_vec());
+    Ok(algorithms.iter()
+        .map(|a| match a {
+            HashAlgorithm::Sha256 => sha256.clone(),
+            HashAlgorithm::Sha512 => sha512.clone(),
+        })
+        .collect())
+}
+
+impl PcrBank {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        PcrBank { algorithm, values: BTreeMap::new() }
+    }
+
+    pub fn read(&self, pcr: u32) -> Vec<u8> {
+        self.values.get(&pcr).cloned().unwrap_or_else(|| vec![0; self.algorithm.digest_len()])
+    }
+
+    pub fn extend(&mut self, pcr: u32, digest: &[u8]) {
+        let value = hash(self.algorithm, &[self.read(pcr).as_slice(), digest].concat());
+        self.values.insert(pcr, value);
+    }
+}
+
+impl EventLog {
+    pub const VERSION: u32 = 1;
+
+    pub fn load(path: &Path) -> Result<Self, BootError> {
+        let text = std::fs::read_to_string(path).map_err(|e| BootError::Integrity(
+            ErrorDetail::io(format!("cannot read event log {}", path.display()), e)))?;
+        serde_json::from_str(&text).map_err(|e| BootError::Integrity(
+            ErrorDetail::invalid(format!("malformed event log {}", path.display())).with_source(e)))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("EventLog serializes to JSON")
+    }
+
+    /// Recompute every bank from the initial PCR values. Fails on events outside the PCR
+    /// range or lacking a well-formed digest for one of the log's banks.
+    pub fn replay(&self) -> Result<Vec<PcrBank>, ErrorDetail> {
+        let mut banks: Vec<PcrBank> = self.banks.iter().map(|a| PcrBank::new(*a)).collect();
+        for initial in &self.initial {
+            let bank = banks.iter_mut().find(|b| b.algorithm == initial.bank)
+                .ok_or_else(|| ErrorDetail::rejected(format!("initial value for unlisted bank {}", initial.bank)))?;
+            let value = hex::decode(&initial.value).ok()
+                .filter(|v| v.len() == initial.bank.digest_len() && initial.pcr < PCR_COUNT)
+                .ok_or_else(|| ErrorDetail::rejected(format!("malformed initial value for {} PCR[{}]", initial.bank, initial.pcr)))?;
+            bank.values.insert(initial.pcr, value);
+        }
+        for (i, event) in self.events.iter().enumerate() {
+            if event.pcr_index >= PCR_COUNT {
+                return Err(ErrorDetail::rejected(format!("event {} extends PCR[{}]; only {} exist", i, event.pcr_index, PCR_COUNT)));
+            }
+            for bank in &mut banks {
+                let digest = event.digests.iter()
+                    .find(|d| d.algorithm == bank.algorithm)
+                    .and_then(|d| hex::decode(&d.digest).ok())
+                    .filter(|d| d.len() == bank.algorithm.digest_len())
+                    .ok_or_else(|| ErrorDetail::rejected(format!("event {} ({}) has no valid {} digest", i, event.event, bank.algorithm)))?;
+                bank.extend(event.pcr_index, &digest);
+            }
+        }
+        Ok(banks)
+    }
+
+    /// Descriptions of the events extended into `pcr`, in order.
+    pub fn events_for(&self, pcr: u32) -> impl Iterator<Item = &str> {
+        self.events.iter().filter(move |e| e.pcr_index == pcr).map(|e| e.event.as_str())
+    }
+}
+
+/// A file measured during boot, kept so the runtime watcher can re-hash it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeasuredFile {
+    pub pcr: u32,
+    pub path: PathBuf,
+    /// One digest per bank, in bank order.
+    pub digests: Vec<Vec<u8>>,
+}
+
+/// The live PCR banks and event log of one boot, shared by every stage.
+pub struct MeasuredBoot {
+    state: Mutex<(Vec<PcrBank>, EventLog)>,
+    files: Mutex<Vec<MeasuredFile>>,
+    /// Where `write_log` put the event log; every later measurement rewrites it.
+    log_file: OnceLock<PathBuf>,
+    #[cfg(feature = "tpm")]
+    tpm: Option<Tpm>,
+}
+
+impl MeasuredBoot {
+    pub fn new(banks: &[HashAlgorithm]) -> Self {
+        let log = EventLog { version: EventLog::VERSION, banks: banks.to_vec(), initial: Vec::new(), events: Vec::new() };
+        MeasuredBoot {
+            state: Mutex::new((banks.iter().map(|a| PcrBank::new(*a)).collect(), log)),
+            files: Mutex::new(Vec::new()),
+            log_file: OnceLock::new(),
+            #[cfg(feature = "tpm")]
+            tpm: None,
+        }
+    }
+
+    /// Mirror every extend into `tpm`, starting the software banks (and the log's
+    /// `initial` values) from what the TPM holds now.
+    #[cfg(feature = "tpm")]
+    pub fn with_tpm(mut self, tpm: Tpm) -> Result<Self, BootError> {
+        {
+            let state = self.state.get_mut().unwrap_or_else(|e| e.into_inner());
+            let (banks, log) = state;
+            let all: Vec<u32> = (0..PCR_COUNT).collect();
+            for bank in banks.iter_mut() {
+                for (pcr, value) in tpm.read(bank.algorithm, &all).map_err(BootError::Integrity)? {
+                    if value.iter().any(|b| *b != 0) {
+                        log.initial.push(InitialPcr { pcr, bank: bank.algorithm, value: hex::encode(&value) });
+                        bank.values.insert(pcr, value);
+                    }
+                }
+            }
+        }
+        self.tpm = Some(tpm);
+        Ok(self)
+    }
+
+    #[cfg(feature = "tpm")]
+    pub fn tpm(&self) -> Option<&Tpm> {
+        self.tpm.as_ref()
+    }
+
+    fn record(&self, pcr: u32, event_type: EventType, event: String, digests: Vec<Vec<u8>>) -> Result<(), BootError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (banks, log) = &mut *state;
+        #[cfg(feature = "tpm")]
+        if let Some(tpm) = &self.tpm {
+            let values: Vec<(HashAlgorithm, &[u8])> = log.banks.iter().copied().zip(digests.iter().map(Vec::as_slice)).collect();
+            tpm.extend(pcr, &values).map_err(BootError::Integrity)?;
+        }
+        for (bank, digest) in banks.iter_mut().zip(&digests) {
+            bank.extend(pcr, digest);
+        }
+        println!("📏 PCR[{}] ← {}", pcr, event);
+        log.events.push(Event {
+            pcr_index: pcr,
+            event_type,
+            digests: log.banks.iter().zip(digests).map(|(a, d)| EventDigest { algorithm: *a, digest: hex::encode(d) }).collect(),
+            event,
+        });
+        match self.log_file.get() {
+            Some(path) => write_log(path, log),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the event log to `path`, and rewrite it after every later measurement so it
+    /// keeps replaying to the live PCRs, e.g. once a reloaded policy is measured.
+    pub fn write_log(&self, path: &Path) -> Result<(), BootError> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let path = self.log_file.get_or_init(|| path.to_path_buf());
+        write_log(path, &state.1)
+    }
+
+    /// The file `write_log` wrote and whether it still holds the live log; `None` before
+    /// it was written. Holds the log while reading, so a concurrent measurement is never
+    /// mistaken for a rewrite.
+    pub fn log_unchanged(&self) -> Option<(&Path, std::io::Result<bool>)> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let path = self.log_file.get()?;
+        Some((path, std::fs::read_to_string(path).map(|text| text == state.1.to_json())))
+    }
+
+    pub fn measure(&self, pcr: u32, event_type: EventType, event: impl Into<String>, data: &[u8]) -> Result<(), BootError> {
+        let digests = self.banks().iter().map(|a| hash(*a, data)).collect();
+        self.record(pcr, event_type, event.into(), digests)
+    }
+
+    /// Measure the canonical JSON form of a config section.
+    pub fn measure_json<T: Serialize>(&self, pcr: u32, event: impl Into<String>, value: &T) -> Result<(), BootError> {
+        let data = serde_json::to_vec(value).expect("config serializes to JSON");
+        self.measure(pcr, EventType::EvEventTag, event, &data)
+    }
+
+    pub fn measure_file(&self, pcr: u32, event_type: EventType, event: &str, path: &Path) -> Result<(), BootError> {
+        let digests = hash_file(&self.banks(), path).map_err(|e| BootError::Integrity(
+            ErrorDetail::io(format!("cannot measure {}", path.display()), e)))?;
+        self.record_file(pcr, event_type, event, path, digests)
+    }
+
+    /// Measure `data`, already read from `path`, exactly as `measure_file` would measure
+    /// the file; for contents that were checked before being measured.
+    pub fn measure_contents(&self, pcr: u32, event_type: EventType, event: &str, path: &Path, data: &[u8])
+        -> Result<(), BootError>
+    {
+        let digests = self.banks().iter().map(|a| hash(*a, data)).collect();
+        self.record_file(pcr, event_type, event, path, digests)
+    }
+
+    fn record_file(&self, pcr: u32, event_type: EventType, event: &str, path: &Path, digests: Vec<Vec<u8>>)
+        -> Result<(), BootError>
+    {
+        self.record(pcr, event_type, format!("{} {}", event, path.display()), digests.clone())?;
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+            .push(MeasuredFile { pcr, path: path.to_path_buf(), digests });
+        Ok(())
+    }
+
+    pub fn files(&self) -> Vec<MeasuredFile> {
+        self.files.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn banks(&self) -> Vec<HashAlgorithm> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).1.banks.clone()
+    }
+
+    pub fn pcrs(&self) -> Vec<PcrBank> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).0.clone()
+    }
+
+    pub fn log(&self) -> EventLog {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).1.clone()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BANKS: &[HashAlgorithm] = &[HashAlgorithm::Sha256, HashAlgorithm::Sha512];
+
+    fn booted() -> MeasuredBoot {
+        let measured = MeasuredBoot::new(BANKS);
+        measured.measure(PCR_FIRMWARE, EventType::EvPostCode, "firmware", b"image").unwrap();
+        measured.measure_json(PCR_CONFIG, "config", &BANKS).unwrap();
+        measured.measure(PCR_FIRMWARE, EventType::EvIpl, "loader", b"stage two").unwrap();
+        measured
+    }
+
+    #[test]
+    fn extends_chain_hashes_from_zero_in_order() {
+        for algorithm in BANKS.iter().copied() {
+            let mut bank = PcrBank::new(algorithm);
+            assert_eq!(bank.read(PCR_MODELS), vec![0; algorithm.digest_len()]);
+            let (a, b) = (hash(algorithm, b"a"), hash(algorithm, b"b"));
+            bank.extend(PCR_MODELS, &a);
+            let once = hash(algorithm, &[vec![0; algorithm.digest_len()], a.clone()].concat());
+            assert_eq!(bank.read(PCR_MODELS), once);
+            bank.extend(PCR_MODELS, &b);
+            assert_eq!(bank.read(PCR_MODELS), hash(algorithm, &[once, b.clone()].concat()));
+
+            let mut swapped = PcrBank::new(algorithm);
+            swapped.extend(PCR_MODELS, &b);
+            swapped.extend(PCR_MODELS, &a);
+            assert_ne!(swapped.read(PCR_MODELS), bank.read(PCR_MODELS));
+            assert_eq!(bank.values.keys().collect::<Vec<_>>(), [&PCR_MODELS]);
+        }
+    }
+
+    #[test]
+    fn replaying_the_log_reproduces_the_live_banks() {
+        let measured = booted();
+        let log = measured.log();
+        assert_eq!(log.events.len(), 3);
+        assert_eq!(log.events_for(PCR_FIRMWARE).collect::<Vec<_>>(), ["firmware", "loader"]);
+        assert_eq!(log.replay().unwrap(), measured.pcrs());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.json");
+        std::fs::write(&path, b"x").unwrap();
+        measured.write_log(&path).unwrap();
+        assert_eq!(EventLog::load(&path).unwrap(), log);
+        assert!(matches!(measured.log_unchanged(), Some((p, Ok(true))) if p == path));
+        // Later measurements keep the written log in step with the banks.
+        measured.measure(PCR_POLICY, EventType::EvEventTag, "policy", b"v2").unwrap();
+        assert_eq!(EventLog::load(&path).unwrap().replay().unwrap(), measured.pcrs());
+        std::fs::write(&path, log.to_json()).unwrap();
+        assert!(matches!(measured.log_unchanged(), Some((_, Ok(false)))));
+    }
+
+    #[test]
+    fn replay_starts_from_initial_values() {
+        let mut log = booted().log();
+        let start = vec![0xab; HashAlgorithm::Sha256.digest_len()];
+        log.initial.push(InitialPcr { pcr: PCR_FIRMWARE, bank: HashAlgorithm::Sha256, value: hex::encode(&start) });
+        let banks = log.replay().unwrap();
+        let mut expected = PcrBank::new(HashAlgorithm::Sha256);
+        expected.values.insert(PCR_FIRMWARE, start);
+        for event in log.events.iter().filter(|e| e.pcr_index == PCR_FIRMWARE) {
+            expected.extend(PCR_FIRMWARE, &hex::decode(&event.digests[0].digest).unwrap());
+        }
+        assert_eq!(banks[0].read(PCR_FIRMWARE), expected.read(PCR_FIRMWARE));
+        assert_eq!(banks[1], booted().pcrs()[1]);
+    }
+
+    #[test]
+    fn replay_rejects_malformed_logs() {
+        type Corrupt = fn(&mut EventLog);
+        let log = booted().log();
+        let broken: [(&str, Corrupt); 7] = [
+            ("PCR out of range", |l| l.events[1].pcr_index = PCR_COUNT),
+            ("missing bank digest", |l| { l.events[0].digests.pop(); }),
+            ("short digest", |l| l.events[2].digests[0].digest.truncate(62)),
+            ("digest not hex", |l| l.events[2].digests[1].digest.replace_range(..2, "zz")),
+            ("unlisted initial bank", |l| {
+                l.banks.pop();
+                l.initial.push(InitialPcr { pcr: 0, bank: HashAlgorithm::Sha512, value: hex::encode([1; 64]) });
+            }),
+            ("initial PCR out of range", |l| {
+                l.initial.push(InitialPcr { pcr: PCR_COUNT, bank: HashAlgorithm::Sha256, value: hex::encode([1; 32]) });
+            }),
+            ("short initial value", |l| {
+                l.initial.push(InitialPcr { pcr: 0, bank: HashAlgorithm::Sha256, value: hex::encode([1; 20]) });
+            }),
+        ];
+        for (what, corrupt) in broken {
+            let mut log = log.clone();
+            corrupt(&mut log);
+            assert!(log.replay().is_err(), "{}", what);
+        }
+    }
+
+    #[test]
+    fn files_are_measured_as_their_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.bin");
+        let data = vec![7u8; 200 * 1024];
+        std::fs::write(&path, &data).unwrap();
+        assert_eq!(hash_file(BANKS, &path).unwrap(), BANKS.iter().map(|a| hash(*a, &data)).collect::<Vec<_>>());
+
+        let (from_file, from_contents) = (MeasuredBoot::new(BANKS), MeasuredBoot::new(BANKS));
+        from_file.measure_file(PCR_MODELS, EventType::EvIpl, "model", &path).unwrap();
+        from_contents.measure_contents(PCR_MODELS, EventType::EvIpl, "model", &path, &data).unwrap();
+        assert_eq!(from_file.pcrs(), from_contents.pcrs());
+        assert_eq!(from_file.log(), from_contents.log());
+        assert_eq!(from_file.files(), from_contents.files());
+        assert!(from_file.measure_file(PCR_MODELS, EventType::EvIpl, "model", &dir.path().join("gone")).is_err());
+        assert_eq!(from_file.files().len(), 1);
+    }
+}
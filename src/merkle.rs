@@ -0,0 +1,152 @@
+//! Merkle trees in the shape of RFC 6962 / RFC 9162 (Certificate Transparency), over
+//! either configured hash. Leaves and interior nodes are domain-separated by a `0x00` or
+//! `0x01` prefix, so a node can never be passed off as a leaf. An inclusion proof is the
+//! audit path from one leaf to the root.
+
+use crate::error::ErrorDetail;
+use crate::measure;
+use crate::schema::HashAlgorithm;
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+pub fn leaf_hash(algorithm: HashAlgorithm, data: &[u8]) -> Vec<u8> {
+    measure::hash(algorithm, &[&[LEAF_PREFIX], data].concat())
+}
+
+pub fn node_hash(algorithm: HashAlgorithm, left: &[u8], right: &[u8]) -> Vec<u8> {
+    measure::hash(algorithm, &[&[NODE_PREFIX], left, right].concat())
+}
+
+/// Root over already-hashed leaves, in order. The empty tree hashes the empty string.
+pub fn root(algorithm: HashAlgorithm, leaves: &[Vec<u8>]) -> Vec<u8> {
+    match leaves.len() {
+        0 => measure::hash(algorithm, b""),
+        1 => leaves[0].clone(),
+        n => {
+            let k = split(n);
+            node_hash(algorithm, &root(algorithm, &leaves[..k]), &root(algorithm, &leaves[k..]))
+        }
+    }
+}
+
+/// Sibling hashes from leaf `index` up to the root, bottom first.
+pub fn audit_path(algorithm: HashAlgorithm, leaves: &[Vec<u8>], index: usize) -> Vec<Vec<u8>> {
+    let n = leaves.len();
+    if n <= 1 {
+        return Vec::new();
+    }
+    let k = split(n);
+    let (mut path, sibling) = if index < k {
+        (audit_path(algorithm, &leaves[..k], index), root(algorithm, &leaves[k..]))
+    } else {
+        (audit_path(algorithm, &leaves[k..], index - k), root(algorithm, &leaves[..k]))
+    };
+    path.push(sibling);
+    path
+}
+
+/// The root implied by `leaf` sitting at `index` of a `size`-leaf tree with `path` as its
+/// audit path (RFC 9162 section 2.1.3.2). Compare the result with the trusted root. The
+/// root does not commit to the size: sizes that give `index` the same audit path imply
+/// the same root, so only a size that contradicts the path is rejected.
+pub fn root_from_path(algorithm: HashAlgorithm, leaf: &[u8], index: u64, size: u64, path: &[Vec<u8>])
+    -> Result<Vec<u8>, ErrorDetail>
+{
+    if index >= size {
+        return Err(ErrorDetail::rejected(format!("leaf index {} is outside a tree of {} leaves", index, size)));
+    }
+    let (mut fn_, mut sn) = (index, size - 1);
+    let mut r = leaf.to_vec();
+    for p in path {
+        if sn == 0 {
+            return Err(ErrorDetail::rejected("audit path is longer than the tree is deep"));
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            r = node_hash(algorithm, p, &r);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            r = node_hash(algorithm, &r, p);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    if sn != 0 {
+        return Err(ErrorDetail::rejected("audit path is shorter than the tree is deep"));
+    }
+    Ok(r)
+}
+
+/// Largest power of two strictly below `n` (n > 1).
+fn split(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA256: HashAlgorithm = HashAlgorithm::Sha256;
+
+    fn leaves(n: usize) -> Vec<Vec<u8>> {
+        (0..n).map(|i| leaf_hash(SHA256, format!("entry {}", i).as_bytes())).collect()
+    }
+
+    fn proves(leaf: &[u8], index: u64, size: u64, path: &[Vec<u8>], root: &[u8]) -> bool {
+        root_from_path(SHA256, leaf, index, size, path).is_ok_and(|r| r == root)
+    }
+
+    #[test]
+    fn every_leaf_proves_into_trees_of_one_to_nine() {
+        assert_eq!(root(SHA256, &[]), measure::hash(SHA256, b""));
+        for n in 1..=9 {
+            let leaves = leaves(n);
+            let root = root(SHA256, &leaves);
+            for (i, leaf) in leaves.iter().enumerate() {
+                let path = audit_path(SHA256, &leaves, i);
+                assert!(proves(leaf, i as u64, n as u64, &path, &root), "leaf {} of {}", i, n);
+            }
+        }
+    }
+
+    #[test]
+    fn proofs_reject_wrong_indices_sizes_and_path_lengths() {
+        for n in 1..=9u64 {
+            let leaves = leaves(n as usize);
+            let root = root(SHA256, &leaves);
+            for i in 0..n {
+                let (leaf, path) = (&leaves[i as usize], audit_path(SHA256, &leaves, i as usize));
+                for other in (0..=n).filter(|o| *o != i) {
+                    assert!(!proves(leaf, other, n, &path, &root), "leaf {} of {} as {}", i, n, other);
+                }
+                for size in [i, 2 * n + 1] {
+                    assert!(!proves(leaf, i, size, &path, &root), "leaf {} of {} in {}", i, n, size);
+                }
+                if let Some((_, truncated)) = path.split_last() {
+                    assert!(!proves(leaf, i, n, truncated, &root), "leaf {} of {} truncated", i, n);
+                }
+                let extended = [path.clone(), vec![root.clone()]].concat();
+                assert!(!proves(leaf, i, n, &extended, &root), "leaf {} of {} extended", i, n);
+            }
+        }
+        // The last of five leaves hangs off the root; in six it has a sibling.
+        let (leaves, five) = (leaves(5), root(SHA256, &leaves(5)));
+        assert!(!proves(&leaves[4], 4, 6, &audit_path(SHA256, &leaves, 4), &five));
+    }
+
+    #[test]
+    fn nodes_cannot_pass_as_leaves() {
+        let leaves = leaves(4);
+        let node = node_hash(SHA256, &leaves[0], &leaves[1]);
+        // As a hash, the node proves into a two-leaf view of the tree...
+        let path = [node_hash(SHA256, &leaves[2], &leaves[3])];
+        assert!(proves(&node, 0, 2, &path, &root(SHA256, &leaves)));
+        // ...but no leaf data hashes to it, prefixed or not.
+        assert_ne!(leaf_hash(SHA256, &[leaves[0].as_slice(), &leaves[1]].concat()), node);
+        assert_ne!(leaf_hash(SHA256, &[&[NODE_PREFIX], leaves[0].as_slice(), &leaves[1]].concat()), node);
+    }
+}
@@ -0,0 +1,147 @@
+use serde_json::Value;
+use std::fmt;
+
+/// The `schema_version` the structs in `schema.rs` describe.
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+/// One step of the upgrade chain, taking a document from `from` to `from + 1`.
+#[derive(Debug)]
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    apply: fn(&mut Value) -> Result<(), MigrationError>,
+}
+
+/// Ordered by `from`; each entry must pick up where the previous one left off.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        description: "split filesystem.encryption.algorithm into cipher and kem",
+        apply: v1_split_algorithm,
+    },
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationError {
+    /// Dotted path of the offending field.
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at `{}`: {}", self.field, self.message)
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+fn error(field: &str, message: impl Into<String>) -> MigrationError {
+    MigrationError { field: field.to_string(), message: message.into() }
+}
+
+/// Version a document declares; documents predating the field are version 1.
+pub fn version_of(doc: &Value) -> Result<u32, MigrationError> {
+    match doc.get("schema_version") {
+        None => Ok(1),
+        Some(v) => v.as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v >= 1)
+            .ok_or_else(|| error("schema_version", format!("expected a positive integer, found {}", v))),
+    }
+}
+
+/// Upgrade `doc` in place to `CURRENT_SCHEMA_VERSION`, returning the steps applied.
+pub fn migrate(doc: &mut Value) -> Result<Vec<&'static Migration>, MigrationError> {
+    let mut version = version_of(doc)?;
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(error("schema_version", format!(
+            "version {} is newer than this build supports ({})", version, CURRENT_SCHEMA_VERSION)));
+    }
+    let mut applied = Vec::new();
+    while version < CURRENT_SCHEMA_VERSION {
+        let step = MIGRATIONS.iter().find(|m| m.from == version)
+            .ok_or_else(|| error("schema_version", format!("no migration from version {}", version)))?;
+        (step.apply)(doc)?;
+        version += 1;
+        if let Some(map) = doc.as_object_mut() {
+            map.insert("schema_version".to_string(), Value::from(version));
+        }
+        applied.push(step);
+    }
+    Ok(applied)
+}
+
+/// v1 named a single `algorithm` such as `PQ-AES-256-GCM`; v2 names the data cipher and the
+/// key-encapsulation mechanism separately, with the `PQ-` prefix becoming an ML-KEM KEM.
+fn v1_split_algorithm(doc: &mut Value) -> Result<(), MigrationError> {
+    let field = "filesystem.encryption.algorithm";
+    let Some(encryption) = doc.pointer_mut("/filesystem/encryption").and_then(Value::as_object_mut) else {
+        return Ok(());
+    };
+    let Some(algorithm) = encryption.remove("algorithm") else {
+        return Ok(());
+    };
+    let algorithm = algorithm.as_str().ok_or_else(|| error(field, "expected a string"))?;
+    let (cipher, kem) = match algorithm.strip_prefix("PQ-") {
+        Some(cipher) => (cipher, "ML-KEM-768"),
+        None => (algorithm, "X25519"),
+    };
+    encryption.insert("cipher".to_string(), Value::from(cipher));
+    encryption.insert("kem".to_string(), Value::from(kem));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn v1_post_quantum_algorithm_splits_into_cipher_and_ml_kem() {
+        let mut doc = json!({"filesystem": {"encryption": {"algorithm": "PQ-AES-256-GCM", "quantum_resistant": true}}});
+        let applied = migrate(&mut doc).unwrap();
+        assert_eq!(applied.iter().map(|m| m.from).collect::<Vec<_>>(), [1]);
+        assert_eq!(doc, json!({
+            "schema_version": 2,
+            "filesystem": {"encryption": {"cipher": "AES-256-GCM", "kem": "ML-KEM-768", "quantum_resistant": true}},
+        }));
+    }
+
+    #[test]
+    fn v1_classical_algorithm_gets_x25519() {
+        let mut doc = json!({"filesystem": {"encryption": {"algorithm": "ChaCha20-Poly1305"}}});
+        migrate(&mut doc).unwrap();
+        assert_eq!(doc["filesystem"]["encryption"], json!({"cipher": "ChaCha20-Poly1305", "kem": "X25519"}));
+    }
+
+    #[test]
+    fn v1_without_encryption_only_gains_a_version() {
+        let mut doc = json!({"profile": "staging"});
+        migrate(&mut doc).unwrap();
+        assert_eq!(doc, json!({"profile": "staging", "schema_version": 2}));
+    }
+
+    #[test]
+    fn current_documents_are_untouched() {
+        let mut doc = json!({"schema_version": 2, "filesystem": {"encryption": {"algorithm": "kept as is"}}});
+        let before = doc.clone();
+        assert!(migrate(&mut doc).unwrap().is_empty());
+        assert_eq!(doc, before);
+    }
+
+    #[test]
+    fn newer_and_malformed_versions_are_refused() {
+        let err = migrate(&mut json!({"schema_version": 3})).unwrap_err();
+        assert_eq!(err.field, "schema_version");
+        assert!(err.message.contains("newer"), "{}", err);
+        assert!(migrate(&mut json!({"schema_version": 0})).is_err());
+        assert!(migrate(&mut json!({"schema_version": "2"})).is_err());
+    }
+
+    #[test]
+    fn non_string_algorithm_names_the_field() {
+        let err = migrate(&mut json!({"filesystem": {"encryption": {"algorithm": 256}}})).unwrap_err();
+        assert_eq!(err.field, "filesystem.encryption.algorithm");
+    }
+}
@@ -0,0 +1,507 @@
+use crate::config::{self, Format};
+use crate::error::{BootError, ErrorDetail};
+use crate::measure::{self, EventType, MeasuredBoot};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// A versioned set of rules deciding whether a subject may perform an action on a
+/// resource. Loaded from TOML or JSON, picked by extension.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Monotonic revision; a reload never replaces a policy with an older or equal one.
+    pub version: u64,
+    /// Decision when no rule matches.
+    #[serde(default = "default_effect")]
+    pub default: Effect,
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+/// Matches when any pattern in each of `subjects`, `actions` and `resources` matches and
+/// every condition holds. Patterns are globs: `*` is any run of characters, `?` one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub id: String,
+    pub effect: Effect,
+    pub subjects: Vec<String>,
+    pub actions: Vec<String>,
+    pub resources: Vec<String>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// A test on one request attribute. A missing attribute fails every condition.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    pub attribute: String,
+    pub op: Op,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// The attribute equals one of the elements of the array `value`.
+    In,
+    /// The string attribute matches the glob `value`.
+    Matches,
+}
+
+/// Something a component wants to do, e.g. `model:APU-3.0` / `load` / `model/APU-3.0@v1`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Request {
+    pub subject: String,
+    pub action: String,
+    pub resource: String,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Decision {
+    pub effect: Effect,
+    /// The deciding rule; `None` when the policy default applied.
+    pub rule: Option<String>,
+    pub policy_version: u64,
+}
+
+fn default_effect() -> Effect {
+    Effect::Allow
+}
+
+impl Request {
+    pub fn new(subject: impl Into<String>, action: impl Into<String>, resource: impl Into<String>) -> Self {
+        Request { subject: subject.into(), action: action.into(), resource: resource.into(), attributes: BTreeMap::new() }
+    }
+
+    pub fn with(mut self, attribute: &str, value: impl Into<Value>) -> Self {
+        self.attributes.insert(attribute.to_string(), value.into());
+        self
+    }
+}
+
+impl Policy {
+    /// Allows everything; used when no policy file is configured.
+    pub fn permissive() -> Self {
+        Policy { version: 0, default: Effect::Allow, rules: Vec::new() }
+    }
+
+    pub fn parse(text: &str, format: Format) -> Result<Self, String> {
+        match format {
+            Format::Toml => toml::from_str(text).map_err(|e| config::toml_message(&e, text)),
+            Format::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, BootError> {
+        Policy::read(path).map(|(policy, _)| policy)
+    }
+
+    /// The policy at `path` and the text it was parsed from.
+    fn read(path: &Path) -> Result<(Self, String), BootError> {
+        let format = Format::from_path(path).ok_or_else(|| BootError::Policy(ErrorDetail::invalid(
+            format!("{}: policy files must be .toml or .json", path.display()))))?;
+        let text = std::fs::read_to_string(path).map_err(|e| BootError::Policy(
+            ErrorDetail::io(format!("cannot read policy {}", path.display()), e)))?;
+        let policy = Policy::parse(&text, format).map_err(|message| BootError::Policy(
+            ErrorDetail::invalid(format!("{}: {}", path.display(), message))))?;
+        policy.check().map_err(|message| BootError::Policy(
+            ErrorDetail::invalid(format!("{}: {}", path.display(), message))))?;
+        Ok((policy, text))
+    }
+
+    /// Structural checks the deserializer cannot express.
+    fn check(&self) -> Result<(), String> {
+        let mut seen = std::collections::HashSet::new();
+        for rule in &self.rules {
+            if !seen.insert(rule.id.as_str()) {
+                return Err(format!("duplicate rule id `{}`", rule.id));
+            }
+            if rule.subjects.is_empty() || rule.actions.is_empty() || rule.resources.is_empty() {
+                return Err(format!("rule `{}` needs at least one subject, action and resource", rule.id));
+            }
+            for condition in &rule.conditions {
+                if condition.op == Op::In && !condition.value.is_array() {
+                    return Err(format!("rule `{}`: `in` on `{}` needs an array value", rule.id, condition.attribute));
+                }
+                if condition.op == Op::Matches && !condition.value.is_string() {
+                    return Err(format!("rule `{}`: `matches` on `{}` needs a string value", rule.id, condition.attribute));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Deny overrides allow: the first matching deny rule wins, then the first matching
+    /// allow rule, then `default`.
+    pub fn evaluate(&self, request: &Request) -> Decision {
+        let matching = |effect| self.rules.iter().find(|r| r.effect == effect && r.matches(request));
+        let (effect, rule) = match matching(Effect::Deny).or_else(|| matching(Effect::Allow)) {
+            Some(rule) => (rule.effect, Some(rule.id.clone())),
+            None => (self.default, None),
+        };
+        Decision { effect, rule, policy_version: self.version }
+    }
+}
+
+impl Rule {
+    pub fn matches(&self, request: &Request) -> bool {
+        let any = |patterns: &[String], value: &str| patterns.iter().any(|p| glob(p, value));
+        any(&self.subjects, &request.subject)
+            && any(&self.actions, &request.action)
+            && any(&self.resources, &request.resource)
+            && self.conditions.iter().all(|c| c.holds(&request.attributes))
+    }
+}
+
+impl Condition {
+    fn holds(&self, attributes: &BTreeMap<String, Value>) -> bool {
+        let Some(actual) = attributes.get(&self.attribute) else { return false };
+        let ordering = || match (actual, &self.value) {
+            (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            _ => None,
+        };
+        match self.op {
+            Op::Eq => actual == &self.value,
+            Op::Ne => actual != &self.value,
+            Op::Lt => ordering().is_some_and(|o| o.is_lt()),
+            Op::Le => ordering().is_some_and(|o| o.is_le()),
+            Op::Gt => ordering().is_some_and(|o| o.is_gt()),
+            Op::Ge => ordering().is_some_and(|o| o.is_ge()),
+            Op::In => self.value.as_array().is_some_and(|values| values.contains(actual)),
+            Op::Matches => match (actual.as_str(), self.value.as_str()) {
+                (Some(actual), Some(pattern)) => glob(pattern, actual),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// `*` matches any run of characters (including none), `?` exactly one.
+pub fn glob(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack = None;
+    while ti < t.len() {
+        match p.get(pi) {
+            Some('*') => {
+                backtrack = Some((pi, ti));
+                pi += 1;
+            }
+            Some(&c) if c == '?' || c == t[ti] => {
+                pi += 1;
+                ti += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    pi = star + 1;
+                    ti = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// The active policy, replaceable at runtime. Readers take an `Arc` snapshot, so a reload
+/// never changes a decision half-way through a check.
+#[derive(Debug)]
+pub struct PolicyStore {
+    path: Option<PathBuf>,
+    current: RwLock<Arc<Policy>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl PolicyStore {
+    pub fn permissive() -> Self {
+        PolicyStore { path: None, current: RwLock::new(Arc::new(Policy::permissive())), modified: Mutex::new(None) }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, BootError> {
+        let modified = modified_at(path);
+        let policy = Policy::load(path)?;
+        Ok(PolicyStore {
+            path: Some(path.to_path_buf()),
+            current: RwLock::new(Arc::new(policy)),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    pub fn current(&self) -> Arc<Policy> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn evaluate(&self, request: &Request) -> Decision {
+        self.current().evaluate(request)
+    }
+
+    /// The decision when `request` is allowed; otherwise a rejection naming the deciding rule.
+    pub fn check(&self, request: &Request) -> Result<Decision, ErrorDetail> {
+        let decision = self.evaluate(request);
+        match decision.effect {
+            Effect::Allow => Ok(decision),
+            Effect::Deny => Err(ErrorDetail::rejected(format!("policy v{} denies {} {} {} ({})",
+                decision.policy_version, request.subject, request.action, request.resource,
+                decision.rule.as_deref().map_or("default".to_string(), |id| format!("rule `{}`", id))))),
+        }
+    }
+
+    /// Re-read the policy file if it changed on disk. Returns the new version when the
+    /// policy was replaced; a broken or older file leaves the current policy in place.
+    /// A replacement is first measured into `measure::PCR_POLICY`, so the event log and
+    /// attestation quotes cover the policy actually enforced; if that fails, the current
+    /// policy stays.
+    pub fn reload(&self, measurements: &MeasuredBoot) -> Result<Option<u64>, BootError> {
+        let Some(path) = &self.path else { return Ok(None) };
+        let modified = modified_at(path);
+        let mut last = self.modified.lock().unwrap_or_else(|e| e.into_inner());
+        if modified.is_some() && modified == *last {
+            return Ok(None);
+        }
+        *last = modified;
+        let (policy, text) = Policy::read(path)?;
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        if policy.version <= current.version {
+            return Err(BootError::Policy(ErrorDetail::rejected(format!(
+                "{}: version {} does not supersede active version {}", path.display(), policy.version, current.version))));
+        }
+        let version = policy.version;
+        measurements.measure_contents(measure::PCR_POLICY, EventType::EvEventTag, "policy", path, text.as_bytes())?;
+        *current = Arc::new(policy);
+        Ok(Some(version))
+    }
+
+    /// Poll the policy file every `interval` on a background thread, swapping in newer
+    /// versions as they appear and measuring each into `measurements`. Stops when the last
+    /// other reference to the store is dropped.
+    pub fn watch(self: &Arc<Self>, measurements: Arc<MeasuredBoot>, interval: Duration) -> thread::JoinHandle<()> {
+        let store = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            let Some(store) = store.upgrade() else { return };
+            match store.reload(&measurements) {
+                Ok(Some(version)) => println!("📜 Policy reloaded: version {}", version),
+                Ok(None) => {}
+                Err(e) => println!("⚠️ policy reload refused: {}", e),
+            }
+        })
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Effect::Allow => "allow",
+            Effect::Deny => "deny",
+        })
+    }
+}
+
+/// A fixture for `policy test`: a request and the decision it should get.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestCase {
+    pub name: String,
+    pub subject: String,
+    pub action: String,
+    pub resource: String,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, Value>,
+    pub expect: Effect,
+}
+
+impl TestCase {
+    pub fn request(&self) -> Request {
+        Request {
+            subject: self.subject.clone(),
+            action: self.action.clone(),
+            resource: self.resource.clone(),
+            attributes: self.attributes.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestSuite {
+    pub cases: Vec<TestCase>,
+}
+
+impl TestSuite {
+    pub fn load(path: &Path) -> Result<Self, BootError> {
+        let invalid = |message: String| BootError::Policy(ErrorDetail::invalid(format!("{}: {}", path.display(), message)));
+        let format = Format::from_path(path).ok_or_else(|| invalid("fixtures must be .toml or .json".to_string()))?;
+        let text = std::fs::read_to_string(path).map_err(|e| BootError::Policy(
+            ErrorDetail::io(format!("cannot read fixtures {}", path.display()), e)))?;
+        match format {
+            Format::Toml => toml::from_str(&text).map_err(|e| invalid(config::toml_message(&e, &text))),
+            Format::Json => serde_json::from_str(&text).map_err(|e| invalid(e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrity::{IntegrityState, WatchList};
+    use crate::schema::HashAlgorithm;
+    use serde_json::json;
+    use std::time::UNIX_EPOCH;
+
+    fn rule(id: &str, effect: Effect, resources: &[&str]) -> Rule {
+        Rule {
+            id: id.to_string(),
+            effect,
+            subjects: vec!["model:*".to_string()],
+            actions: vec!["load".to_string()],
+            resources: resources.iter().map(|r| r.to_string()).collect(),
+            conditions: Vec::new(),
+            description: None,
+        }
+    }
+
+    fn condition(attribute: &str, op: Op, value: Value) -> Condition {
+        Condition { attribute: attribute.to_string(), op, value }
+    }
+
+    /// Write `version` to `path` with a distinct mtime, so `reload` never mistakes it for
+    /// the file it last read however coarse the filesystem clock is.
+    fn write_version(path: &Path, version: u64) {
+        std::fs::write(path, format!("version = {}\ndefault = \"deny\"\n", version)).unwrap();
+        std::fs::File::options().write(true).open(path).unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(version * 60)).unwrap();
+    }
+
+    #[test]
+    fn globs_match_runs_and_single_characters() {
+        assert!(glob("model/*", "model/APU-3.0@v1"));
+        assert!(glob("model/*", "model/"));
+        assert!(glob("*@v?", "model/chat@v1"));
+        assert!(glob("a*b*c", "axxbyybzc"));
+        assert!(glob("**", ""));
+        assert!(!glob("*@v?", "model/chat@v10"));
+        assert!(!glob("model/*", "models/chat"));
+        assert!(!glob("a*b", "ab-"));
+        assert!(!glob("?", ""));
+    }
+
+    #[test]
+    fn conditions_need_the_attribute_and_a_comparable_value() {
+        let request = Request::new("model:chat", "load", "model/chat")
+            .with("security_level", 4)
+            .with("tier", "gold")
+            .with("isolated", true);
+        let holds = |attribute, op, value| condition(attribute, op, value).holds(&request.attributes);
+        assert!(holds("security_level", Op::Eq, json!(4)));
+        assert!(holds("security_level", Op::Ne, json!(5)));
+        assert!(holds("security_level", Op::Ge, json!(4)) && holds("security_level", Op::Lt, json!(4.5)));
+        assert!(!holds("security_level", Op::Gt, json!(4)));
+        assert!(holds("tier", Op::Gt, json!("bronze")) && holds("tier", Op::Le, json!("gold")));
+        assert!(holds("tier", Op::In, json!(["silver", "gold"])));
+        assert!(holds("tier", Op::Matches, json!("g*d")));
+        // Mixed types never order, and a missing attribute fails even `ne`.
+        assert!(!holds("tier", Op::Lt, json!(9)) && !holds("tier", Op::Ge, json!(9)));
+        assert!(!holds("isolated", Op::Matches, json!("*")));
+        assert!(!holds("region", Op::Ne, json!("eu")));
+    }
+
+    #[test]
+    fn deny_overrides_allow_and_the_default_applies_last() {
+        let mut shared = rule("shared-s4", Effect::Deny, &["model/*"]);
+        shared.conditions = vec![condition("isolated", Op::Eq, json!(false))];
+        let policy = Policy {
+            version: 3,
+            default: Effect::Deny,
+            rules: vec![rule("models", Effect::Allow, &["model/*"]), shared],
+        };
+        let decide = |resource: &str, isolated: bool| {
+            let decision = policy.evaluate(&Request::new("model:chat", "load", resource).with("isolated", isolated));
+            (decision.effect, decision.rule)
+        };
+        assert_eq!(decide("model/chat", true), (Effect::Allow, Some("models".to_string())));
+        assert_eq!(decide("model/chat", false), (Effect::Deny, Some("shared-s4".to_string())));
+        assert_eq!(decide("dataset/chat", true), (Effect::Deny, None));
+        assert_eq!(policy.evaluate(&Request::new("model:chat", "load", "x")).policy_version, 3);
+    }
+
+    #[test]
+    fn reloads_only_move_to_newer_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.toml");
+        write_version(&path, 2);
+        let store = PolicyStore::load(&path).unwrap();
+        let measurements = MeasuredBoot::new(&[HashAlgorithm::Sha256]);
+        assert_eq!(store.reload(&measurements).unwrap(), None);
+
+        write_version(&path, 1);
+        assert!(store.reload(&measurements).unwrap_err().to_string().contains("does not supersede"));
+        write_version(&path, 2);
+        assert!(store.reload(&measurements).is_err());
+        std::fs::write(&path, "version = \"three\"").unwrap();
+        assert!(store.reload(&measurements).is_err());
+        assert_eq!(store.current().version, 2);
+        assert!(measurements.log().events.is_empty());
+
+        write_version(&path, 3);
+        assert_eq!(store.reload(&measurements).unwrap(), Some(3));
+        assert_eq!(store.current().version, 3);
+        assert_eq!(PolicyStore::permissive().reload(&measurements).unwrap(), None);
+    }
+
+    #[test]
+    fn accepted_reloads_are_measured_and_logged() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, log) = (dir.path().join("policy.toml"), dir.path().join("events.json"));
+        write_version(&path, 1);
+        let store = PolicyStore::load(&path).unwrap();
+        let measurements = Arc::new(MeasuredBoot::new(&[HashAlgorithm::Sha256]));
+        measurements.measure_file(measure::PCR_POLICY, EventType::EvEventTag, "policy", &path).unwrap();
+        measurements.write_log(&log).unwrap();
+        let boot = measurements.pcrs()[0].read(measure::PCR_POLICY);
+        let watch = WatchList::new(&measurements).ignore(&path);
+
+        write_version(&path, 2);
+        assert_eq!(store.reload(&measurements).unwrap(), Some(2));
+        let logged = crate::measure::EventLog::load(&log).unwrap();
+        assert_eq!(logged.events.len(), 2);
+        let replayed = logged.replay().unwrap();
+        assert_ne!(replayed[0].read(measure::PCR_POLICY), boot);
+        assert_eq!(replayed[0].read(measure::PCR_POLICY), measurements.pcrs()[0].read(measure::PCR_POLICY));
+        // The rewritten log is the live one, not tampering.
+        assert_eq!(watch.check(), Ok(()));
+        std::fs::write(&log, "{}").unwrap();
+        assert_eq!(watch.check().unwrap_err().0, IntegrityState::Compromised);
+    }
+}
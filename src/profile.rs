@@ -0,0 +1,184 @@
+use crate::schema::{BootConfig, EnforcementLayer};
+use crate::stage::{self, StageRegistry};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The context a node boots in. Selected with `profile = "..."` in the config, the
+/// `MESH_BOOT__PROFILE` environment variable or `--profile`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BootProfile {
+    #[default]
+    Production,
+    Staging,
+    /// Skip AI models, still verify integrity, then drop into a restricted shell.
+    Recovery,
+    Developer,
+}
+
+/// What the stage runner does when a stage fails, and what enforcement does when the
+/// kernel cannot provide a requested control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Stop, roll back completed stages and report the error.
+    FailClosed,
+    /// Record the failure as a warning and keep booting.
+    Warn,
+}
+
+impl BootProfile {
+    pub fn failure_policy(self) -> FailurePolicy {
+        match self {
+            BootProfile::Production | BootProfile::Staging | BootProfile::Recovery => FailurePolicy::FailClosed,
+            BootProfile::Developer => FailurePolicy::Warn,
+        }
+    }
+
+    /// Stages registered by default that this profile does not run.
+    pub fn skipped_stages(self) -> &'static [&'static str] {
+        match self {
+            BootProfile::Recovery => &[stage::MODELS],
+            _ => &[],
+        }
+    }
+
+    /// The enforcement this profile boots with, given what the config asked for.
+    /// Production and recovery enable every control; developer relaxes the shell,
+    /// kernel and filesystem controls; staging keeps the config as written.
+    pub fn enforcement(self, configured: &EnforcementLayer) -> EnforcementLayer {
+        match self {
+            BootProfile::Production | BootProfile::Recovery => EnforcementLayer {
+                restrict_shell: true,
+                lock_resources: true,
+                harden_kernel: true,
+                sandbox_filesystem: true,
+                ..configured.clone()
+            },
+            BootProfile::Staging => configured.clone(),
+            BootProfile::Developer => EnforcementLayer {
+                restrict_shell: false,
+                harden_kernel: false,
+                sandbox_filesystem: false,
+                ..configured.clone()
+            },
+        }
+    }
+
+    /// Rewrite `config` for this profile, returning a note for every value that changed.
+    pub fn apply(self, config: &mut BootConfig) -> Vec<String> {
+        let adjusted = self.enforcement(&config.enforcement);
+        let mut notes = Vec::new();
+        let fields = [
+            ("restrict_shell", config.enforcement.restrict_shell, adjusted.restrict_shell),
+            ("lock_resources", config.enforcement.lock_resources, adjusted.lock_resources),
+            ("harden_kernel", config.enforcement.harden_kernel, adjusted.harden_kernel),
+            ("sandbox_filesystem", config.enforcement.sandbox_filesystem, adjusted.sandbox_filesystem),
+        ];
+        for (name, was, now) in fields {
+            if was != now {
+                notes.push(format!("{} profile sets enforcement.{} = {}", self, name, now));
+            }
+        }
+        config.enforcement = adjusted;
+        notes
+    }
+
+    /// The default stages, adjusted for this profile.
+    pub fn stages(self) -> StageRegistry {
+        let mut registry = StageRegistry::with_defaults();
+        for name in self.skipped_stages() {
+            registry.skip(name);
+        }
+        if self == BootProfile::Recovery {
+            registry.register(stage::RecoveryShellStage);
+        }
+        registry
+    }
+}
+
+impl fmt::Display for BootProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BootProfile::Production => "production",
+            BootProfile::Staging => "staging",
+            BootProfile::Recovery => "recovery",
+            BootProfile::Developer => "developer",
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [BootProfile; 4] = [BootProfile::Production, BootProfile::Staging, BootProfile::Recovery, BootProfile::Developer];
+
+    fn controls(enforcement: &EnforcementLayer) -> [bool; 4] {
+        [enforcement.restrict_shell, enforcement.lock_resources, enforcement.harden_kernel, enforcement.sandbox_filesystem]
+    }
+
+    fn configured(on: bool) -> BootConfig {
+        let defaults = BootConfig::default();
+        BootConfig {
+            enforcement: EnforcementLayer {
+                restrict_shell: on,
+                lock_resources: on,
+                harden_kernel: on,
+                sandbox_filesystem: on,
+                ..defaults.enforcement.clone()
+            },
+            ..defaults
+        }
+    }
+
+    #[test]
+    fn profiles_force_their_controls_and_note_each_change() {
+        for (profile, from_off, from_on) in [
+            (BootProfile::Production, [true; 4], [true; 4]),
+            (BootProfile::Recovery, [true; 4], [true; 4]),
+            (BootProfile::Staging, [false; 4], [true; 4]),
+            (BootProfile::Developer, [false; 4], [false, true, false, false]),
+        ] {
+            for (on, expected) in [(false, from_off), (true, from_on)] {
+                let mut config = configured(on);
+                let notes = profile.apply(&mut config);
+                assert_eq!(controls(&config.enforcement), expected, "{} from {}", profile, on);
+                assert_eq!(notes.len(), expected.iter().filter(|e| **e != on).count(), "{} from {}: {:?}", profile, on, notes);
+                assert!(notes.iter().all(|n| n.starts_with(&format!("{} profile sets enforcement.", profile))), "{:?}", notes);
+                // The rest of the enforcement section is kept as written.
+                assert_eq!(config.enforcement.limits, configured(on).enforcement.limits);
+                assert!(profile.apply(&mut config).is_empty(), "{} is not idempotent", profile);
+            }
+        }
+        let mut config = configured(false);
+        assert_eq!(BootProfile::Production.apply(&mut config)[0], "production profile sets enforcement.restrict_shell = true");
+    }
+
+    #[test]
+    fn only_recovery_skips_models_and_adds_the_shell() {
+        for profile in ALL {
+            let stages = profile.stages();
+            let names: Vec<&str> = stages.stages().map(|s| s.name()).collect();
+            let recovery = profile == BootProfile::Recovery;
+            assert_eq!(stages.is_skipped(stage::MODELS), recovery, "{}", profile);
+            assert_eq!(profile.skipped_stages().contains(&stage::MODELS), recovery, "{}", profile);
+            assert_eq!(names.contains(&stage::RECOVERY_SHELL), recovery, "{}", profile);
+            // Skipped stages stay planned so their dependents still order after them.
+            assert!(names.contains(&stage::MODELS), "{}", profile);
+            assert!(stages.plan().is_ok(), "{}", profile);
+            for name in names.iter().filter(|n| **n != stage::MODELS) {
+                assert!(!stages.is_skipped(name), "{} skips {}", profile, name);
+            }
+        }
+    }
+
+    #[test]
+    fn only_developer_tolerates_failures() {
+        for profile in ALL {
+            let expected = if profile == BootProfile::Developer { FailurePolicy::Warn } else { FailurePolicy::FailClosed };
+            assert_eq!(profile.failure_policy(), expected, "{}", profile);
+        }
+    }
+}
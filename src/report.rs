@@ -0,0 +1,337 @@
+use crate::integrity::{IntegrityState, Transition};
+use crate::profile::BootProfile;
+use crate::stage::{RollbackOutcome, RollbackResult};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageOutcome {
+    Succeeded,
+    Failed,
+    /// Failed, but the boot profile's warn policy let the boot continue.
+    Tolerated,
+    /// Not run, because the profile skips it or an earlier wave failed.
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StageRecord {
+    pub name: &'static str,
+    /// `None` for skipped stages.
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: f64,
+    pub outcome: StageOutcome,
+    pub warnings: Vec<String>,
+    /// Stable `BootError::code`, e.g. `integrity.rejected`, when the stage failed.
+    pub error_code: Option<String>,
+    /// Outermost error first; empty unless the stage failed.
+    pub error_chain: Vec<String>,
+}
+
+impl StageRecord {
+    pub fn skipped(name: &'static str) -> Self {
+        StageRecord {
+            name,
+            started_at: None,
+            finished_at: None,
+            duration_ms: 0.0,
+            outcome: StageOutcome::Skipped,
+            warnings: Vec::new(),
+            error_code: None,
+            error_chain: Vec::new(),
+        }
+    }
+}
+
+/// What happened during one boot, in plan order; ingested by fleet tooling as JSON.
+#[derive(Debug, Clone, Serialize)]
+pub struct BootReport {
+    pub profile: BootProfile,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub duration_ms: f64,
+    pub succeeded: bool,
+    /// Non-fatal findings from `BootConfig::validate`.
+    pub config_warnings: Vec<String>,
+    pub stages: Vec<StageRecord>,
+    /// Compensations run after a failure, most recently completed stage first.
+    pub rollbacks: Vec<RollbackOutcome>,
+    /// Integrity state when the stages finished, and every transition leading to it.
+    pub integrity: IntegrityState,
+    pub integrity_transitions: Vec<Transition>,
+}
+
+impl BootReport {
+    /// An empty, not yet successful report for a boot starting now.
+    pub fn begin() -> Self {
+        let now = Utc::now();
+        BootReport {
+            profile: BootProfile::default(),
+            started_at: now,
+            finished_at: now,
+            duration_ms: 0.0,
+            succeeded: false,
+            config_warnings: Vec::new(),
+            stages: Vec::new(),
+            rollbacks: Vec::new(),
+            integrity: IntegrityState::Unverified,
+            integrity_transitions: Vec::new(),
+        }
+    }
+
+    pub fn stage(&self, name: &str) -> Option<&StageRecord> {
+        self.stages.iter().find(|s| s.name == name)
+    }
+
+    /// The stage that stopped the boot, if any; tolerated failures do not count.
+    pub fn failed_stage(&self) -> Option<&StageRecord> {
+        self.stages.iter().find(|s| s.outcome == StageOutcome::Failed)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("BootReport serializes to JSON")
+    }
+}
+
+impl fmt::Display for StageOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StageOutcome::Succeeded => "ok",
+            StageOutcome::Failed => "FAILED",
+            StageOutcome::Tolerated => "tolerated",
+            StageOutcome::Skipped => "skipped",
+        })
+    }
+}
+
+/// Human-readable table of stages and the integrity state, followed by warnings, errors
+/// and rollbacks.
+impl fmt::Display for BootReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.stages.iter().map(|s| s.name.len()).max().unwrap_or(5).max(5);
+        writeln!(f, "{:<width$}  {:<8}  {:>10}  {:>8}", "STAGE", "OUTCOME", "DURATION", "WARNINGS")?;
+        for stage in &self.stages {
+            writeln!(f, "{:<width$}  {:<8}  {:>7.1} ms  {:>8}",
+                     stage.name, stage.outcome.to_string(), stage.duration_ms, stage.warnings.len())?;
+        }
+        write!(f, "{:<width$}  {:<8}  {:>7.1} ms  ({} profile)",
+               "total", if self.succeeded { "ok" } else { "FAILED" }, self.duration_ms, self.profile)?;
+        write!(f, "\n🔐 integrity: {}", self.integrity)?;
+        if let Some(last) = self.integrity_transitions.last() {
+            write!(f, " ({})", last.reason)?;
+        }
+
+        for warning in &self.config_warnings {
+            write!(f, "\n⚠️ config: {}", warning)?;
+        }
+        for stage in &self.stages {
+            for warning in &stage.warnings {
+                write!(f, "\n⚠️ {}: {}", stage.name, warning)?;
+            }
+            if let Some((head, causes)) = stage.error_chain.split_first() {
+                write!(f, "\n❌ {}: {}", stage.name, head)?;
+                for cause in causes {
+                    write!(f, "\n   caused by: {}", cause)?;
+                }
+            }
+        }
+        for outcome in &self.rollbacks {
+            match &outcome.result {
+                RollbackResult::RolledBack => write!(f, "\n↩ {}: rolled back", outcome.stage)?,
+                RollbackResult::Failed(e) => write!(f, "\n✗ {}: rollback failed: {}", outcome.stage, e)?,
+                RollbackResult::Unsupported => write!(f, "\n· {}: nothing to roll back", outcome.stage)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedStage {
+    pub name: &'static str,
+    pub dependencies: Vec<&'static str>,
+    /// Skipped by the boot profile; `actions` is then empty.
+    pub skipped: bool,
+    /// What the stage would do, as reported by `BootStage::describe`.
+    pub actions: Vec<String>,
+}
+
+/// The output of a dry run: the resolved stage plan and each stage's intended actions.
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRun {
+    pub profile: BootProfile,
+    pub config_warnings: Vec<String>,
+    /// Stages in a wave would run concurrently.
+    pub waves: Vec<Vec<PlannedStage>>,
+}
+
+impl DryRun {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("DryRun serializes to JSON")
+    }
+}
+
+impl fmt::Display for DryRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "📝 Dry run ({} profile) — nothing below has been executed", self.profile)?;
+        for warning in &self.config_warnings {
+            write!(f, "\n⚠️ config: {}", warning)?;
+        }
+        for (i, wave) in self.waves.iter().enumerate() {
+            write!(f, "\nwave {}{}", i + 1, if wave.len() > 1 { " (concurrent)" } else { "" })?;
+            for stage in wave {
+                write!(f, "\n  {}", stage.name)?;
+                if !stage.dependencies.is_empty() {
+                    write!(f, " (after {})", stage.dependencies.join(", "))?;
+                }
+                if stage.skipped {
+                    write!(f, " — skipped by profile")?;
+                }
+                for action in &stage.actions {
+                    write!(f, "\n    → {}", action)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(name: &'static str, outcome: StageOutcome) -> StageRecord {
+        let now = Utc::now();
+        StageRecord {
+            started_at: Some(now),
+            finished_at: Some(now),
+            duration_ms: 1.5,
+            outcome,
+            ..StageRecord::skipped(name)
+        }
+    }
+
+    fn failed_boot() -> BootReport {
+        let now = Utc::now();
+        BootReport {
+            profile: BootProfile::Staging,
+            duration_ms: 12.5,
+            config_warnings: vec!["no firmware image configured".to_string()],
+            stages: vec![
+                StageRecord { warnings: vec!["no TPM".to_string()], ..record("firmware", StageOutcome::Succeeded) },
+                StageRecord {
+                    error_code: Some("filesystem.io".to_string()),
+                    error_chain: vec!["[filesystem.io] cannot mount".to_string(), "device busy".to_string()],
+                    ..record("filesystem", StageOutcome::Failed)
+                },
+                StageRecord::skipped("integrity"),
+            ],
+            rollbacks: vec![
+                RollbackOutcome { stage: "firmware", result: RollbackResult::Failed("stuck".to_string()) },
+                RollbackOutcome { stage: "enforcement", result: RollbackResult::RolledBack },
+                RollbackOutcome { stage: "models", result: RollbackResult::Unsupported },
+            ],
+            integrity: IntegrityState::Degraded,
+            integrity_transitions: vec![Transition {
+                from: IntegrityState::Unverified,
+                to: IntegrityState::Degraded,
+                reason: "filesystem failed".to_string(),
+                at: now,
+            }],
+            ..BootReport::begin()
+        }
+    }
+
+    #[test]
+    fn tolerated_failures_do_not_stop_the_boot() {
+        let mut report = failed_boot();
+        assert_eq!(report.failed_stage().map(|s| s.name), Some("filesystem"));
+        assert_eq!(report.stage("integrity").map(|s| s.outcome), Some(StageOutcome::Skipped));
+        assert!(report.stage("models").is_none());
+        report.stages[1].outcome = StageOutcome::Tolerated;
+        assert!(report.failed_stage().is_none());
+    }
+
+    #[test]
+    fn reports_render_stages_then_warnings_errors_and_rollbacks() {
+        let text = failed_boot().to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[..5], [
+            "STAGE       OUTCOME     DURATION  WARNINGS",
+            "firmware    ok            1.5 ms         1",
+            "filesystem  FAILED        1.5 ms         0",
+            "integrity   skipped       0.0 ms         0",
+            "total       FAILED       12.5 ms  (staging profile)",
+        ]);
+        assert_eq!(lines[5..], [
+            "🔐 integrity: degraded (filesystem failed)",
+            "⚠️ config: no firmware image configured",
+            "⚠️ firmware: no TPM",
+            "❌ filesystem: [filesystem.io] cannot mount",
+            "   caused by: device busy",
+            "✗ firmware: rollback failed: stuck",
+            "↩ enforcement: rolled back",
+            "· models: nothing to roll back",
+        ]);
+    }
+
+    #[test]
+    fn reports_serialize_for_fleet_tooling() {
+        let report: serde_json::Value = serde_json::from_str(&failed_boot().to_json()).unwrap();
+        assert_eq!(report["profile"], "staging");
+        assert_eq!(report["succeeded"], false);
+        assert_eq!(report["integrity"], "degraded");
+        assert_eq!(report["stages"][1]["outcome"], "failed");
+        assert_eq!(report["stages"][1]["error_code"], "filesystem.io");
+        assert_eq!(report["stages"][2], json!({
+            "name": "integrity",
+            "started_at": null,
+            "finished_at": null,
+            "duration_ms": 0.0,
+            "outcome": "skipped",
+            "warnings": [],
+            "error_code": null,
+            "error_chain": [],
+        }));
+        assert_eq!(report["rollbacks"], json!([
+            { "stage": "firmware", "result": { "failed": "stuck" } },
+            { "stage": "enforcement", "result": "rolled_back" },
+            { "stage": "models", "result": "unsupported" },
+        ]));
+    }
+
+    #[test]
+    fn dry_runs_list_waves_and_skipped_stages() {
+        let planned = |name, dependencies: &[&'static str], skipped| PlannedStage {
+            name,
+            dependencies: dependencies.to_vec(),
+            skipped,
+            actions: if skipped { Vec::new() } else { vec![format!("check {}", name)] },
+        };
+        let plan = DryRun {
+            profile: BootProfile::Recovery,
+            config_warnings: vec!["recovery profile sets enforcement.harden_kernel = true".to_string()],
+            waves: vec![
+                vec![planned("firmware", &[], false)],
+                vec![planned("models", &["firmware"], true), planned("filesystem", &["firmware"], false)],
+            ],
+        };
+        assert_eq!(plan.to_string(), [
+            "📝 Dry run (recovery profile) — nothing below has been executed",
+            "⚠️ config: recovery profile sets enforcement.harden_kernel = true",
+            "wave 1",
+            "  firmware",
+            "    → check firmware",
+            "wave 2 (concurrent)",
+            "  models (after firmware) — skipped by profile",
+            "  filesystem (after firmware)",
+            "    → check filesystem",
+        ].join("\n"));
+        let json: serde_json::Value = serde_json::from_str(&plan.to_json()).unwrap();
+        assert_eq!(json["waves"][1][0], json!({ "name": "models", "dependencies": ["firmware"], "skipped": true, "actions": [] }));
+    }
+}
@@ -0,0 +1,281 @@
+//! Linux process confinement behind `security::enforce`. Restrictions apply to the
+//! calling process (rlimits, seccomp) or the calling thread and every thread and child
+//! it creates afterwards (capabilities, `no_new_privs`, Landlock), so enforcement must
+//! run on the boot thread before workers are spawned; the enforcement stage is
+//! `BootStage::exclusive` for that reason. None of it can be undone.
+
+use crate::error::{BootError, ErrorDetail, ErrorKind};
+use crate::schema::ResourceLimits;
+
+#[cfg(target_os = "linux")]
+fn io_error(message: impl Into<String>) -> BootError {
+    BootError::Enforcement(ErrorDetail::io(message, std::io::Error::last_os_error()))
+}
+
+/// Apply each configured limit as both soft and hard rlimit, never raising the current
+/// hard limit. Returns one line per limit set.
+#[cfg(target_os = "linux")]
+pub fn lock_resources(limits: &ResourceLimits) -> Result<Vec<String>, BootError> {
+    let requested = [
+        ("RLIMIT_AS", libc::RLIMIT_AS, limits.max_memory_mb.map(|mb| mb.saturating_mul(1024 * 1024))),
+        ("RLIMIT_NOFILE", libc::RLIMIT_NOFILE, limits.max_open_files),
+        ("RLIMIT_NPROC", libc::RLIMIT_NPROC, limits.max_processes),
+    ];
+    let mut applied = Vec::new();
+    for (name, resource, value) in requested {
+        let Some(value) = value else { continue };
+        let mut current = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        if unsafe { libc::getrlimit(resource, &mut current) } != 0 {
+            return Err(io_error(format!("cannot read {}", name)));
+        }
+        let value = (value as libc::rlim_t).min(current.rlim_max);
+        let limit = libc::rlimit { rlim_cur: value, rlim_max: value };
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            return Err(io_error(format!("cannot set {} to {}", name, value)));
+        }
+        applied.push(format!("{} = {}", name, value));
+    }
+    Ok(applied)
+}
+
+/// Set `no_new_privs`, install the seccomp deny-list on every thread and drop all
+/// capabilities except `retain`.
+#[cfg(target_os = "linux")]
+pub fn harden_kernel(retain: &[String]) -> Result<(), BootError> {
+    set_no_new_privs()?;
+    install_seccomp()?;
+    drop_capabilities(retain)
+}
+
+#[cfg(target_os = "linux")]
+pub fn set_no_new_privs() -> Result<(), BootError> {
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(io_error("cannot set no_new_privs"));
+    }
+    Ok(())
+}
+
+/// Syscalls that load code into or reconfigure the kernel, or inspect other processes.
+/// They fail with `EPERM` once the filter is installed.
+#[cfg(target_os = "linux")]
+pub const DENIED_SYSCALLS: &[(&str, libc::c_long)] = &[
+    ("kexec_load", libc::SYS_kexec_load),
+    ("kexec_file_load", libc::SYS_kexec_file_load),
+    ("init_module", libc::SYS_init_module),
+    ("finit_module", libc::SYS_finit_module),
+    ("delete_module", libc::SYS_delete_module),
+    ("reboot", libc::SYS_reboot),
+    ("swapon", libc::SYS_swapon),
+    ("swapoff", libc::SYS_swapoff),
+    ("acct", libc::SYS_acct),
+    ("bpf", libc::SYS_bpf),
+    ("perf_event_open", libc::SYS_perf_event_open),
+    ("userfaultfd", libc::SYS_userfaultfd),
+    ("ptrace", libc::SYS_ptrace),
+    ("process_vm_readv", libc::SYS_process_vm_readv),
+    ("process_vm_writev", libc::SYS_process_vm_writev),
+    ("open_by_handle_at", libc::SYS_open_by_handle_at),
+    ("add_key", libc::SYS_add_key),
+    ("request_key", libc::SYS_request_key),
+    ("keyctl", libc::SYS_keyctl),
+    #[cfg(target_arch = "x86_64")]
+    ("iopl", libc::SYS_iopl),
+    #[cfg(target_arch = "x86_64")]
+    ("ioperm", libc::SYS_ioperm),
+];
+
+#[cfg(target_os = "linux")]
+pub fn install_seccomp() -> Result<(), BootError> {
+    use seccompiler::{BpfProgram, SeccompAction, SeccompFilter, TargetArch};
+    let arch = TargetArch::try_from(std::env::consts::ARCH).map_err(|_| BootError::Enforcement(
+        ErrorDetail::unsupported(format!("no seccomp filter for {}", std::env::consts::ARCH))))?;
+    let rules = DENIED_SYSCALLS.iter().map(|(_, nr)| (*nr, Vec::new())).collect();
+    let filter = SeccompFilter::new(rules, SeccompAction::Allow, SeccompAction::Errno(libc::EPERM as u32), arch)
+        .map_err(|e| BootError::Enforcement(ErrorDetail::internal("cannot build seccomp filter").with_source(e)))?;
+    let program: BpfProgram = filter.try_into()
+        .map_err(|e: seccompiler::BackendError| {
+            BootError::Enforcement(ErrorDetail::internal("cannot compile seccomp filter").with_source(e))
+        })?;
+    seccompiler::apply_filter_all_threads(&program)
+        .map_err(|e| BootError::Enforcement(ErrorDetail::unsupported("cannot install seccomp filter").with_source(e)))
+}
+
+/// Shrink the bounding, ambient, inheritable, permitted and effective sets to `retain`.
+/// The bounding set is left alone when the process lacks `CAP_SETPCAP`, since it then
+/// cannot regain anything outside its (already reduced) permitted set anyway.
+#[cfg(target_os = "linux")]
+pub fn drop_capabilities(retain: &[String]) -> Result<(), BootError> {
+    use caps::{CapSet, Capability, CapsHashSet};
+    let caps_error = |what: &str, e: caps::errors::CapsError| {
+        BootError::Enforcement(ErrorDetail::new(ErrorKind::Io, what).with_source(e))
+    };
+    let keep: CapsHashSet = retain.iter()
+        .map(|name| name.parse::<Capability>().map_err(|_| BootError::Enforcement(
+            ErrorDetail::invalid(format!("unknown capability `{}`", name)))))
+        .collect::<Result<_, _>>()?;
+
+    if caps::has_cap(None, CapSet::Effective, Capability::CAP_SETPCAP).unwrap_or(false) {
+        for cap in caps::read(None, CapSet::Bounding).map_err(|e| caps_error("cannot read bounding set", e))? {
+            if !keep.contains(&cap) {
+                caps::drop(None, CapSet::Bounding, cap).map_err(|e| caps_error("cannot shrink bounding set", e))?;
+            }
+        }
+    }
+    caps::clear(None, CapSet::Ambient).map_err(|e| caps_error("cannot clear ambient set", e))?;
+    let permitted = caps::read(None, CapSet::Permitted).map_err(|e| caps_error("cannot read permitted set", e))?;
+    let kept: CapsHashSet = permitted.intersection(&keep).copied().collect();
+    for set in [CapSet::Inheritable, CapSet::Effective, CapSet::Permitted] {
+        caps::set(None, set, &kept).map_err(|e| caps_error("cannot drop capabilities", e))?;
+    }
+    Ok(())
+}
+
+/// Allow `execve` only of files under `allowlist`, using a Landlock ruleset that handles
+/// nothing but execution. Fails with `ErrorKind::Unsupported` when the kernel cannot
+/// enforce it.
+#[cfg(target_os = "linux")]
+pub fn restrict_exec(allowlist: &[String]) -> Result<(), BootError> {
+    use landlock::{path_beneath_rules, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus};
+    let landlock_error = |e: landlock::RulesetError| {
+        BootError::Enforcement(ErrorDetail::unsupported("cannot restrict execve with Landlock").with_source(e))
+    };
+    let status = Ruleset::default()
+        .handle_access(AccessFs::Execute).map_err(landlock_error)?
+        .create().map_err(landlock_error)?
+        .add_rules(path_beneath_rules(allowlist, AccessFs::Execute)).map_err(landlock_error)?
+        .restrict_self().map_err(landlock_error)?;
+    match status.ruleset {
+        RulesetStatus::NotEnforced => Err(BootError::Enforcement(ErrorDetail::unsupported(
+            "kernel does not support Landlock; execve cannot be restricted"))),
+        _ => Ok(()),
+    }
+}
+
+/// Confine the process to `read_only` and `read_write` paths with a Landlock ruleset
+/// handling every filesystem access the running kernel knows. A rule cannot cover a
+/// path that does not exist yet, so a missing `read_write` path fails with
+/// `ErrorKind::Invalid` before anything is restricted; missing `read_only` paths are
+/// returned. Fails with `ErrorKind::Unsupported` when Landlock is unavailable.
+#[cfg(target_os = "linux")]
+pub fn confine_filesystem(read_only: &[String], read_write: &[String]) -> Result<Vec<String>, BootError> {
+    use landlock::{
+        path_beneath_rules, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus, ABI,
+    };
+    let missing = |paths: &[String]| -> Vec<String> {
+        paths.iter().filter(|p| !std::path::Path::new(p).exists()).cloned().collect()
+    };
+    let unwritable = missing(read_write);
+    if !unwritable.is_empty() {
+        return Err(BootError::Enforcement(ErrorDetail::invalid(format!(
+            "cannot grant write access to {}: no such path", unwritable.join(", ")))));
+    }
+    let abi = ABI::V5;
+    let landlock_error = |e: landlock::RulesetError| {
+        BootError::Enforcement(ErrorDetail::unsupported("cannot confine the filesystem with Landlock").with_source(e))
+    };
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi)).map_err(landlock_error)?
+        .create().map_err(landlock_error)?
+        .add_rules(path_beneath_rules(read_only, AccessFs::from_read(abi))).map_err(landlock_error)?
+        .add_rules(path_beneath_rules(read_write, AccessFs::from_all(abi))).map_err(landlock_error)?
+        .restrict_self().map_err(landlock_error)?;
+    match status.ruleset {
+        RulesetStatus::NotEnforced => Err(BootError::Enforcement(ErrorDetail::unsupported(
+            "kernel does not support Landlock; the filesystem is not confined"))),
+        _ => Ok(missing(read_only)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported(what: &str) -> BootError {
+    BootError::Enforcement(ErrorDetail::unsupported(format!("{} is only implemented on Linux", what)))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn lock_resources(_limits: &ResourceLimits) -> Result<Vec<String>, BootError> {
+    Err(unsupported("resource locking"))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn harden_kernel(_retain: &[String]) -> Result<(), BootError> {
+    Err(unsupported("kernel hardening"))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn restrict_exec(_allowlist: &[String]) -> Result<(), BootError> {
+    Err(unsupported("execve restriction"))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn confine_filesystem(_read_only: &[String], _read_write: &[String]) -> Result<Vec<String>, BootError> {
+    Err(unsupported("filesystem confinement"))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::process::Command;
+
+    /// Set in the re-executed test binary, naming the directory it may write to.
+    const CHILD_ENV: &str = "MESH_SANDBOX_TEST_DIR";
+
+    /// Everything here is irreversible, so the test re-runs itself in a child process
+    /// and checks the confinement from inside it.
+    #[test]
+    fn confinement_holds_in_a_child_process() {
+        let Ok(dir) = std::env::var(CHILD_ENV) else {
+            let dir = tempfile::tempdir().unwrap();
+            let output = Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "sandbox::tests::confinement_holds_in_a_child_process", "--nocapture", "--test-threads=1"])
+                .env(CHILD_ENV, dir.path())
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "child failed:\n{}{}",
+                    String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+            return;
+        };
+        confine_child(Path::new(&dir));
+    }
+
+    fn confine_child(dir: &Path) {
+        let limits = ResourceLimits { max_open_files: Some(64), ..ResourceLimits::default() };
+        assert_eq!(lock_resources(&limits).unwrap(), vec!["RLIMIT_NOFILE = 64".to_string()]);
+        let mut current = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        assert_eq!(unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut current) }, 0);
+        assert_eq!((current.rlim_cur, current.rlim_max), (64, 64));
+
+        match harden_kernel(&[]) {
+            Ok(()) => {
+                let ret = unsafe { libc::syscall(libc::SYS_ptrace, libc::PTRACE_TRACEME, 0, 0, 0) };
+                assert_eq!(ret, -1, "ptrace must be denied");
+                assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EPERM));
+                assert_eq!(unsafe { libc::prctl(libc::PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) }, 1);
+            }
+            Err(e) if e.kind() == ErrorKind::Unsupported => println!("seccomp unavailable, skipped: {}", e),
+            Err(e) => panic!("{}", e),
+        }
+
+        let writable = dir.to_string_lossy().into_owned();
+        let missing = dir.join("absent").to_string_lossy().into_owned();
+        match confine_filesystem(std::slice::from_ref(&missing), std::slice::from_ref(&writable)) {
+            Ok(skipped) => {
+                assert_eq!(skipped, vec![missing]);
+                std::fs::write(dir.join("inside"), b"ok").unwrap();
+                let denied = std::fs::read("/etc/passwd").unwrap_err();
+                assert_eq!(denied.kind(), std::io::ErrorKind::PermissionDenied);
+            }
+            Err(e) if e.kind() == ErrorKind::Unsupported => println!("Landlock unavailable, skipped: {}", e),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn missing_writable_path_is_refused_before_confining() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("not-mounted-yet").to_string_lossy().into_owned();
+        let err = confine_filesystem(&[], std::slice::from_ref(&missing)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Invalid);
+        assert!(err.to_string().contains(&missing));
+    }
+}
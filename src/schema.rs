@@ -1,30 +1,445 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+use crate::config::{self, ConfigError};
+use crate::migrate::CURRENT_SCHEMA_VERSION;
+use crate::profile::BootProfile;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct BootConfig {
+    /// Layout version of this document; older files are upgraded by `migrate` on load.
+    pub schema_version: u32,
+    #[serde(default)]
+    pub profile: BootProfile,
     pub enforcement: EnforcementLayer,
     pub ai_models: Vec<AIModelDescriptor>,
     pub filesystem: FileSystemConfig,
     pub compliance: ComplianceConfig,
+    /// Signed firmware image to verify before anything else runs. Without it the
+    /// firmware stage only warns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firmware: Option<FirmwareConfig>,
+    /// Rules consulted by enforcement, the model loader and the recovery shell. Without
+    /// it everything the booleans above permit is allowed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PolicyConfig>,
+    /// Banks, event log location and golden PCR values for measured boot. Without it
+    /// stages still measure into SHA-256 PCRs, but nothing is compared.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub measured_boot: Option<MeasuredBootConfig>,
+    /// Keys and endpoint for `attest serve`, which quotes the measured boot to a controller.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationConfig>,
 }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AttestationConfig {
+    /// JSON node key files (`attest keygen`); list a classical and an ML-DSA-65 key for
+    /// hybrid quotes.
+    pub signing_keys: Vec<String>,
+    #[serde(default = "default_attestation_listen")]
+    pub listen: String,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MeasuredBootConfig {
+    /// PCR banks every measurement is extended into.
+    #[serde(default = "default_banks")]
+    pub banks: Vec<HashAlgorithm>,
+    /// Write the JSON event log here before verifying; must be writable under the
+    /// filesystem sandbox.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_log: Option<String>,
+    /// Expected final PCR values, e.g. from `measure replay` on a known-good boot. Only
+    /// as trustworthy as this config; prefer a signed `golden_file`.
+    #[serde(default)]
+    pub golden: Vec<GoldenPcr>,
+    /// `measure replay` output holding further golden values, signed with `measure sign`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub golden_file: Option<String>,
+    /// Keys that sign `golden_file` and `kernel_fingerprint.manifest`; each needs a
+    /// detached signature at `<file>.sig`, checked under the filesystem's signature policy.
+    #[serde(default)]
+    pub trusted_keys: Vec<TrustedKey>,
+    /// Key ids that must no longer be accepted, even if still listed as trusted.
+    #[serde(default)]
+    pub revoked_keys: Vec<String>,
+    /// After a successful boot, re-hash the measured files and the event log this often
+    /// and mark the node compromised when they change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watch_interval_secs: Option<u64>,
+    /// Extend a TPM 2.0 alongside the software PCRs. Needs the `tpm` cargo feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tpm: Option<TpmConfig>,
+    /// Fingerprint a directory tree (the kernel build) during the integrity stage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel_fingerprint: Option<KernelFingerprintConfig>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KernelFingerprintConfig {
+    pub root: String,
+    #[serde(default = "default_fingerprint_algorithm")]
+    pub algorithm: HashAlgorithm,
+    /// Hex fingerprint of a known-good tree; any other value fails the boot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    /// Manifest of the known-good tree from `kernel_fingerprint --manifest`, signed with
+    /// `measure sign`. Supplies `expected` when unset, and a mismatch then names the
+    /// changed files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<String>,
+    /// Also write the computed fingerprint here; quotes take it from the event log.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Include each entry's uid and gid in the fingerprint.
+    #[serde(default)]
+    pub ownership: bool,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TpmConfig {
+    /// TSS TCTI, e.g. `device:/dev/tpmrm0` or `swtpm:host=localhost,port=2321`.
+    #[serde(default = "default_tcti")]
+    pub tcti: String,
+    /// JSON blob from `tpm seal`; the filesystem stage unseals the data key from it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_key: Option<String>,
+    /// SHA-256 PCRs `tpm seal` binds the data key to. Every stage extending them must
+    /// run before the filesystem stage.
+    #[serde(default = "default_seal_pcrs")]
+    pub seal_pcrs: Vec<u32>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GoldenPcr {
+    pub pcr: u32,
+    pub bank: HashAlgorithm,
+    /// Hex digest.
+    pub value: String,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha512,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// `.toml` or `.json` policy file, see `policy::Policy`.
+    pub path: String,
+    /// Poll `path` this often and swap in newer policy versions while running, measuring
+    /// each into PCR_POLICY.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reload_interval_secs: Option<u64>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct EnforcementLayer {
     pub restrict_shell: bool,
     pub lock_resources: bool,
     pub harden_kernel: bool,
+    /// Applied by `lock_resources`.
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// Files, or directories of binaries, that may still be executed under `restrict_shell`.
+    #[serde(default = "default_exec_allowlist")]
+    pub exec_allowlist: Vec<String>,
+    /// Capabilities `harden_kernel` keeps, e.g. `CAP_NET_BIND_SERVICE`; all others are dropped.
+    #[serde(default)]
+    pub retain_capabilities: Vec<String>,
+    /// Confine the process and its children to `filesystem.mount_at` plus the configured
+    /// `read_only` and `read_write` paths with Landlock.
+    #[serde(default)]
+    pub sandbox_filesystem: bool,
+    /// Sysctl, mount and module checks run by `harden_kernel`; see `audit`.
+    #[serde(default)]
+    pub kernel_audit: KernelAudit,
+}
+/// How `harden_kernel` treats the host's hardening baseline.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KernelAudit {
+    /// Write the recommended value of every failing sysctl; needs root.
+    pub apply: bool,
+    /// With `apply`, also write sysctls that lock until reboot (`kernel.modules_disabled`,
+    /// `kernel.kexec_load_disabled`).
+    pub apply_irreversible: bool,
+    /// Fail enforcement when the audit scores below this (0 to 100), or as unsupported
+    /// when no check is available to score.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_score: Option<u8>,
 }
+/// Hard and soft rlimits; unset fields keep the inherited limit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceLimits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_memory_mb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_open_files: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_processes: Option<u64>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AIModelDescriptor {
-    pub name: &'static str,
-    pub version: &'static str,
+    pub name: String,
+    pub version: String,
     pub security_level: u8,
     pub isolated: bool,
 }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct FileSystemConfig {
-    pub mount_at: &'static str,
+    pub mount_at: String,
     pub encryption: CryptoProfile,
+    /// Readable (and executable) under `enforcement.sandbox_filesystem`.
+    #[serde(default = "default_read_only")]
+    pub read_only: Vec<String>,
+    /// Writable under `enforcement.sandbox_filesystem`, in addition to `mount_at`.
+    #[serde(default = "default_read_write")]
+    pub read_write: Vec<String>,
 }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct CryptoProfile {
-    pub algorithm: &'static str,
+    /// Symmetric data cipher, e.g. `AES-256-GCM`.
+    pub cipher: String,
+    /// Key-encapsulation mechanism wrapping the data key, e.g. `ML-KEM-768`.
+    pub kem: String,
     pub quantum_resistant: bool,
+    /// Which signature families must verify on signed artifacts. Defaults to `both` when
+    /// `quantum_resistant` is set and `classical` otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature_policy: Option<SignaturePolicy>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignaturePolicy {
+    Classical,
+    PostQuantum,
+    /// Hybrid: a classical and a post-quantum signature must both verify.
+    Both,
 }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ComplianceConfig {
     pub gdpr: bool,
     pub ccpa: bool,
     pub audit_log: bool,
 }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FirmwareConfig {
+    pub image: String,
+    /// Detached JSON signature file for `image`, see `signature::SignatureBundle`.
+    pub signature: String,
+    pub trusted_keys: Vec<TrustedKey>,
+    /// Key ids that must no longer be accepted, even if still listed as trusted.
+    #[serde(default)]
+    pub revoked_keys: Vec<String>,
+    /// Hex SHA-256 digests of images that must not boot even with a valid signature.
+    #[serde(default)]
+    pub revoked_images: Vec<String>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrustedKey {
+    pub id: String,
+    pub algorithm: KeyAlgorithm,
+    /// Hex. Ed25519: 32-byte key. ECDSA P-256: SEC1 point, compressed or not.
+    /// ML-DSA-65: 1952-byte encoded key.
+    pub public_key: String,
+    /// Validity window as quoted RFC 3339 strings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<DateTime<Utc>>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyAlgorithm {
+    Ed25519,
+    EcdsaP256,
+    /// FIPS 204 ML-DSA-65 (Dilithium3).
+    #[serde(rename = "ml-dsa-65")]
+    MlDsa65,
+}
+
+impl KeyAlgorithm {
+    pub fn is_post_quantum(self) -> bool {
+        self == KeyAlgorithm::MlDsa65
+    }
+}
+
+impl SignaturePolicy {
+    /// Whether signatures of the given family must verify under this policy.
+    pub fn requires(self, post_quantum: bool) -> bool {
+        match self {
+            SignaturePolicy::Classical => !post_quantum,
+            SignaturePolicy::PostQuantum => post_quantum,
+            SignaturePolicy::Both => true,
+        }
+    }
+}
+
+impl CryptoProfile {
+    pub fn signature_policy(&self) -> SignaturePolicy {
+        self.signature_policy.unwrap_or(if self.quantum_resistant {
+            SignaturePolicy::Both
+        } else {
+            SignaturePolicy::Classical
+        })
+    }
+}
+
+impl fmt::Display for SignaturePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SignaturePolicy::Classical => "classical",
+            SignaturePolicy::PostQuantum => "post-quantum",
+            SignaturePolicy::Both => "both",
+        })
+    }
+}
+
+impl HashAlgorithm {
+    pub fn digest_len(self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+        })
+    }
+}
+
+impl fmt::Display for KeyAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeyAlgorithm::Ed25519 => "ed25519",
+            KeyAlgorithm::EcdsaP256 => "ecdsa-p256",
+            KeyAlgorithm::MlDsa65 => "ml-dsa-65",
+        })
+    }
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        ResourceLimits { max_memory_mb: None, max_open_files: Some(4096), max_processes: None }
+    }
+}
+
+/// System binary and library directories (the dynamic loader needs execute access too):
+/// `restrict_shell` then stops binaries dropped anywhere else, such as data mounts,
+/// `/tmp` or home directories, from running. Missing entries are ignored.
+fn default_exec_allowlist() -> Vec<String> {
+    ["/usr/sbin", "/usr/bin", "/sbin", "/bin", "/usr/lib", "/usr/lib64", "/lib", "/lib64"]
+        .iter().map(|p| p.to_string()).collect()
+}
+
+/// What a booted node needs to read outside its secure mount: binaries, libraries,
+/// configuration and kernel interfaces.
+fn default_read_only() -> Vec<String> {
+    ["/usr", "/bin", "/sbin", "/lib", "/lib64", "/etc", "/proc", "/sys"].iter().map(|p| p.to_string()).collect()
+}
+
+fn default_banks() -> Vec<HashAlgorithm> {
+    vec![HashAlgorithm::Sha256]
+}
+
+fn default_attestation_listen() -> String {
+    "127.0.0.1:7443".to_string()
+}
+
+fn default_fingerprint_algorithm() -> HashAlgorithm {
+    HashAlgorithm::Sha512
+}
+
+fn default_tcti() -> String {
+    "device:/dev/tpmrm0".to_string()
+}
+
+/// Firmware, profile/enforcement and policy: everything measured before the mount.
+fn default_seal_pcrs() -> Vec<u32> {
+    vec![crate::measure::PCR_FIRMWARE, crate::measure::PCR_CONFIG, crate::measure::PCR_POLICY]
+}
+
+/// Device nodes such as `/dev/null` and terminals are opened for writing.
+fn default_read_write() -> Vec<String> {
+    vec!["/dev".to_string()]
+}
+
+impl FileSystemConfig {
+    /// Paths writable inside the filesystem sandbox: `mount_at` first, then `read_write`.
+    pub fn writable_paths(&self) -> Vec<String> {
+        std::iter::once(self.mount_at.clone()).chain(self.read_write.iter().cloned()).collect()
+    }
+}
+
+impl BootConfig {
+    /// Load a config from a `.toml` or `.json` file, picked by extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        config::load(path.as_ref())
+    }
+}
+
+/// The built-in node profile that `boot::launch` used to hard-code.
+impl Default for BootConfig {
+    fn default() -> Self {
+        BootConfig {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            profile: BootProfile::default(),
+            enforcement: EnforcementLayer {
+                restrict_shell: true,
+                lock_resources: true,
+                harden_kernel: true,
+                limits: ResourceLimits::default(),
+                exec_allowlist: default_exec_allowlist(),
+                retain_capabilities: Vec::new(),
+                sandbox_filesystem: true,
+                kernel_audit: KernelAudit::default(),
+            },
+            ai_models: vec![
+                AIModelDescriptor {
+                    name: "APU-3.0".to_string(),
+                    version: "v1.68.2".to_string(),
+                    security_level: 5,
+                    isolated: true,
+                }
+            ],
+            filesystem: FileSystemConfig {
+                mount_at: "/secure_data".to_string(),
+                encryption: CryptoProfile {
+                    cipher: "AES-256-GCM".to_string(),
+                    kem: "ML-KEM-768".to_string(),
+                    quantum_resistant: true,
+                    signature_policy: None,
+                },
+                read_only: default_read_only(),
+                read_write: default_read_write(),
+            },
+            compliance: ComplianceConfig {
+                gdpr: true,
+                ccpa: true,
+                audit_log: true,
+            },
+            firmware: None,
+            policy: None,
+            measured_boot: None,
+            attestation: None,
+        }
+    }
+}
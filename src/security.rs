@@ -1,11 +1,158 @@
-use crate::schema::EnforcementLayer;
-pub fn validate_firmware() -> Result<(), String> {
-    println!("🛡️ Validating firmware signature via TPM...");
-    Ok(())
-}
-pub fn enforce(layer: &EnforcementLayer) -> Result<(), String> {
-    if layer.lock_resources { println!("🔐 Resource lock"); }
-    if layer.restrict_shell { println!("🚫 Shell restricted"); }
-    if layer.harden_kernel { println!("🧬 Kernel hardening"); }
-    Ok(())
+use crate::audit::Auditor;
+use crate::error::{BootError, ErrorDetail, ErrorKind};
+use crate::measure::{self, EventType, MeasuredBoot};
+use crate::{firmware, sandbox};
+use crate::policy::{PolicyStore, Request};
+use crate::profile::{BootProfile, FailurePolicy};
+use crate::schema::{EnforcementLayer, FileSystemConfig, FirmwareConfig, KernelAudit, SignaturePolicy};
+use chrono::Utc;
+use std::path::Path;
+/// Verify the firmware image, then measure it and the keys that signed it; with a TPM
+/// configured both land in the TPM's PCRs as well.
+pub fn validate_firmware(cfg: &FirmwareConfig, policy: SignaturePolicy, measurements: &MeasuredBoot)
+    -> Result<(), BootError>
+{
+    println!("🛡️ Validating firmware signature: {} ({} policy)", cfg.image, policy);
+    let verified = firmware::verify(cfg, policy, Utc::now())?;
+    println!("🛡️ Firmware sha256 {} signed by {}", hex::encode(verified.sha256), verified.key_ids.join(" + "));
+    measurements.measure_contents(measure::PCR_FIRMWARE, EventType::EvPostCode, "firmware", Path::new(&cfg.image),
+                                  &verified.bytes)?;
+    let authority = verified.key_ids.join(",");
+    measurements.measure(measure::PCR_POLICY, EventType::EvEventTag, format!("firmware authority {}", authority),
+                         authority.as_bytes())
+}
+/// Policy requests `enforce` makes before touching the process: one `disable` of
+/// `enforcement/<control>` per control `layer` leaves off, and one `allow-exec` per
+/// `exec_allowlist` entry, all with subject `profile:<profile>`.
+pub fn policy_requests(layer: &EnforcementLayer, profile: BootProfile) -> Vec<Request> {
+    let subject = format!("profile:{}", profile);
+    let controls = [
+        ("restrict_shell", layer.restrict_shell),
+        ("lock_resources", layer.lock_resources),
+        ("harden_kernel", layer.harden_kernel),
+        ("sandbox_filesystem", layer.sandbox_filesystem),
+    ];
+    let disabled = controls.into_iter()
+        .filter(|(_, enabled)| !enabled)
+        .map(|(control, _)| Request::new(subject.as_str(), "disable", format!("enforcement/{}", control)));
+    let exec = layer.exec_allowlist.iter()
+        .filter(|_| layer.restrict_shell)
+        .map(|path| Request::new(subject.as_str(), "allow-exec", path.as_str()));
+    disabled.chain(exec).collect()
+}
+
+/// Confine this process as `layer` asks, once `policy` agrees with every relaxation;
+/// irreversible, see `sandbox`. Controls the kernel cannot provide fail the stage when
+/// the profile fails closed and are returned as warnings otherwise.
+pub fn enforce(layer: &EnforcementLayer, fs: &FileSystemConfig, profile: BootProfile, policy: &PolicyStore)
+    -> Result<Vec<String>, BootError>
+{
+    for request in policy_requests(layer, profile) {
+        policy.check(&request).map_err(BootError::Enforcement)?;
+    }
+    // Audit first: sysctls can only be applied before the filesystem sandbox makes
+    // /proc/sys read-only and capabilities are dropped.
+    let mut warnings = if layer.harden_kernel { audit_kernel(&layer.kernel_audit)? } else { Vec::new() };
+    // Landlock rules only cover existing paths, and the filesystem stage mounts
+    // `mount_at` after enforcement, so the mount point has to exist now.
+    if layer.sandbox_filesystem {
+        std::fs::create_dir_all(&fs.mount_at).map_err(|e| BootError::Enforcement(
+            ErrorDetail::io(format!("cannot create mount point {}", fs.mount_at), e)))?;
+    }
+    let mut skipped = Vec::new();
+    let on_unsupported = profile.failure_policy();
+    let mut degrade = |result: Result<(), BootError>| match result {
+        Err(e) if e.kind() == ErrorKind::Unsupported && on_unsupported == FailurePolicy::Warn => {
+            println!("🚨 UNCONFINED: {}", e);
+            warnings.push(format!("running without this control: {}", e));
+            Ok(false)
+        }
+        other => other.map(|()| true),
+    };
+    if layer.lock_resources {
+        let applied = sandbox::lock_resources(&layer.limits)?;
+        println!("🔐 Resource lock: {}", if applied.is_empty() { "no limits configured".to_string() } else { applied.join(", ") });
+    }
+    if layer.restrict_shell && degrade(sandbox::restrict_exec(&layer.exec_allowlist))? {
+        println!("🚫 Shell restricted: execve limited to {}", layer.exec_allowlist.join(", "));
+    }
+    if layer.sandbox_filesystem
+        && degrade(sandbox::confine_filesystem(&fs.read_only, &fs.writable_paths()).map(|missing| skipped = missing))?
+    {
+        println!("📦 Filesystem sandbox: read-write {}; read-only {}", fs.writable_paths().join(", "), fs.read_only.join(", "));
+    }
+    if layer.harden_kernel && degrade(sandbox::harden_kernel(&layer.retain_capabilities))? {
+        println!("🧬 Kernel hardening: no_new_privs, seccomp deny-list, capabilities dropped{}",
+                 if layer.retain_capabilities.is_empty() { String::new() } else { format!(" (kept {})", layer.retain_capabilities.join(", ")) });
+    }
+    warnings.extend(skipped.into_iter().map(|p| format!("read-only path {} does not exist and is not reachable in the sandbox", p)));
+    Ok(warnings)
+}
+/// Audit the host against `audit::SYSCTL_BASELINE`, applying fixes when configured.
+/// Every failing check becomes a warning; a score under `min_score`, or no score at all,
+/// fails enforcement.
+fn audit_kernel(cfg: &KernelAudit) -> Result<Vec<String>, BootError> {
+    let auditor = Auditor::default().allow_irreversible(cfg.apply_irreversible);
+    let mut report = auditor.run();
+    if cfg.apply && report.failures().next().is_some() {
+        let applied = auditor.apply(&report)?;
+        println!("🧰 Applied sysctls: {}", applied.join(", "));
+        report = auditor.run();
+    }
+    match report.score {
+        Some(score) => println!("🔎 Kernel audit: score {}/100, {} failing check(s)", score, report.failures().count()),
+        None => println!("🔎 Kernel audit: no checks available"),
+    }
+    if let Some(min) = cfg.min_score {
+        report.require(min).map_err(BootError::Enforcement)?;
+    }
+    Ok(report.failures()
+        .map(|f| format!("kernel audit: {} is {} (expected {})", f.id, f.actual.as_deref().unwrap_or("unset"), f.expected))
+        .collect())
+}
+pub fn describe_firmware(cfg: Option<&FirmwareConfig>, policy: SignaturePolicy) -> Vec<String> {
+    match cfg {
+        Some(cfg) => vec![
+            format!("hash {} with SHA-256", cfg.image),
+            format!("verify {} signature(s) in {} against {} trusted key(s), {} revoked",
+                    policy, cfg.signature, cfg.trusted_keys.len(), cfg.revoked_keys.len()),
+        ],
+        None => vec!["skip firmware signature check (no [firmware] section)".to_string()],
+    }
+}
+pub fn describe_enforce(layer: &EnforcementLayer, fs: &FileSystemConfig) -> Vec<String> {
+    let mut actions = Vec::new();
+    if layer.lock_resources {
+        let limits = &layer.limits;
+        actions.push(format!("set rlimits: memory {}, open files {}, processes {}",
+                             show(limits.max_memory_mb.map(|mb| format!("{} MB", mb))),
+                             show(limits.max_open_files), show(limits.max_processes)));
+    }
+    if layer.restrict_shell {
+        actions.push(format!("allow execve only under {} (Landlock)", layer.exec_allowlist.join(", ")));
+    }
+    if layer.sandbox_filesystem {
+        actions.push(format!("confine filesystem to read-write {}; read-only {} (Landlock)",
+                             fs.writable_paths().join(", "), fs.read_only.join(", ")));
+    }
+    if layer.harden_kernel {
+        actions.push(format!("audit sysctls, mount options and module loading{}{}",
+                             match (layer.kernel_audit.apply, layer.kernel_audit.apply_irreversible) {
+                                 (true, true) => ", applying failing sysctls including irreversible ones",
+                                 (true, false) => ", applying failing reversible sysctls",
+                                 (false, _) => "",
+                             },
+                             layer.kernel_audit.min_score.map_or(String::new(), |min| format!(", requiring score >= {}", min))));
+        actions.push("set no_new_privs and install seccomp deny-list (kexec, modules, bpf, ptrace, ...)".to_string());
+        actions.push(match layer.retain_capabilities.as_slice() {
+            [] => "drop all capabilities".to_string(),
+            kept => format!("drop all capabilities except {}", kept.join(", ")),
+        });
+    }
+    if actions.is_empty() { actions.push("no enforcement controls enabled".to_string()); }
+    actions
+}
+
+fn show(limit: Option<impl ToString>) -> String {
+    limit.map_or_else(|| "inherited".to_string(), |n| n.to_string())
 }
@@ -0,0 +1,47 @@
+use crate::error::{BootError, ErrorDetail};
+use crate::policy::{PolicyStore, Request};
+use crate::schema::FileSystemConfig;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process::Command;
+
+/// Restricted shells tried in order; `rbash` semantics forbid `cd`, changing `PATH`
+/// and running commands by path.
+const RESTRICTED_SHELLS: &[(&str, &[&str])] = &[
+    ("/bin/rbash", &[]),
+    ("/bin/bash", &["--restricted", "--noprofile", "--norc"]),
+];
+
+/// Minimal `PATH` for the recovery shell.
+const RECOVERY_PATH: &str = "/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Drop the operator into a restricted shell in the secure mount, with a scrubbed
+/// environment. Returns once the shell exits; skipped when there is no terminal.
+pub fn open_restricted(fs: &FileSystemConfig, policy: &PolicyStore) -> Result<(), BootError> {
+    if !std::io::stdin().is_terminal() {
+        println!("🐚 Recovery shell skipped: no terminal attached");
+        return Ok(());
+    }
+    let (shell, args) = RESTRICTED_SHELLS.iter()
+        .find(|(shell, _)| Path::new(shell).exists())
+        .ok_or_else(|| BootError::Shell(ErrorDetail::unsupported("no restricted shell (rbash or bash) installed")))?;
+    let workdir = if Path::new(&fs.mount_at).is_dir() { fs.mount_at.as_str() } else { "/" };
+    let request = Request::new("operator", "open-shell", format!("shell{}", shell)).with("workdir", workdir);
+    policy.check(&request).map_err(BootError::Shell)?;
+    println!("🐚 Recovery shell: {} in {} (exit to continue)", shell, workdir);
+    let status = Command::new(shell)
+        .args(*args)
+        .current_dir(workdir)
+        .env_clear()
+        .env("PATH", RECOVERY_PATH)
+        .env("PS1", "recovery$ ")
+        .status()
+        .map_err(|e| BootError::Shell(ErrorDetail::io(format!("cannot start {}", shell), e)))?;
+    println!("🐚 Recovery shell exited with {}", status);
+    Ok(())
+}
+
+pub fn describe_restricted(fs: &FileSystemConfig) -> Vec<String> {
+    vec![format!("open a restricted shell in {} with PATH={} (only when a terminal is attached)",
+                 fs.mount_at, RECOVERY_PATH)]
+}
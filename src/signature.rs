@@ -0,0 +1,286 @@
+use crate::error::ErrorDetail;
+use crate::schema::{KeyAlgorithm, SignaturePolicy, TrustedKey};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// One signature over a message, by one trusted key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DetachedSignature {
+    /// `id` of the `TrustedKey` that produced the signature.
+    pub key_id: String,
+    pub algorithm: KeyAlgorithm,
+    /// Hex. Ed25519: 64 bytes. ECDSA P-256: 64-byte `r || s` or DER. ML-DSA-65: 3309 bytes.
+    pub signature: String,
+}
+
+/// A hybrid signature file: classical and post-quantum signatures over the same message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignatureBundle {
+    pub signatures: Vec<DetachedSignature>,
+}
+
+/// Signature files hold either a bundle or, as written before hybrid signing, a single
+/// `DetachedSignature`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SignatureFile {
+    Bundle(SignatureBundle),
+    Single(DetachedSignature),
+}
+
+/// Read a JSON signature file. A missing file means the message is unsigned.
+pub fn read_signatures(path: &Path) -> Result<Vec<DetachedSignature>, ErrorDetail> {
+    let text = std::fs::read_to_string(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => ErrorDetail::rejected(format!("unsigned: {} does not exist", path.display())),
+        _ => ErrorDetail::io(format!("cannot read signature {}", path.display()), e),
+    })?;
+    match serde_json::from_str(&text) {
+        Ok(SignatureFile::Bundle(bundle)) => Ok(bundle.signatures),
+        Ok(SignatureFile::Single(signature)) => Ok(vec![signature]),
+        Err(e) => Err(ErrorDetail::rejected(format!("malformed signature file {}", path.display())).with_source(e)),
+    }
+}
+
+/// Check `signatures` over `message` against `keys` as `policy` demands, returning the ids
+/// of the keys whose signatures were verified.
+///
+/// Every signature in a family the policy requires must verify with a trusted, unrevoked,
+/// currently valid key, and each required family needs at least one; signatures in other
+/// families are ignored. Under `Both`, stripping either half of a hybrid signature fails.
+pub fn verify_all(
+    message: &[u8],
+    signatures: &[DetachedSignature],
+    keys: &[TrustedKey],
+    revoked: &[String],
+    policy: SignaturePolicy,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>, ErrorDetail> {
+    let mut verified = Vec::new();
+    let (mut classical, mut post_quantum) = (false, false);
+    for signature in signatures {
+        let pq = signature.algorithm.is_post_quantum();
+        if !policy.requires(pq) {
+            continue;
+        }
+        if revoked.contains(&signature.key_id) {
+            return Err(ErrorDetail::rejected(format!("signing key `{}` is revoked", signature.key_id)));
+        }
+        let key = keys.iter()
+            .find(|k| k.id == signature.key_id)
+            .ok_or_else(|| ErrorDetail::rejected(format!("signing key `{}` is not trusted", signature.key_id)))?;
+        if key.algorithm != signature.algorithm {
+            return Err(ErrorDetail::rejected(format!("signature claims {} but key `{}` is {}",
+                                                     signature.algorithm, key.id, key.algorithm)));
+        }
+        check_validity(key, now)?;
+        let bytes = hex::decode(signature.signature.trim())
+            .map_err(|_| ErrorDetail::rejected(format!("signature by `{}` is not valid hex", key.id)))?;
+        verify(key, message, &bytes)?;
+        if pq { post_quantum = true } else { classical = true }
+        verified.push(key.id.clone());
+    }
+    if policy.requires(false) && !classical {
+        return Err(ErrorDetail::rejected(format!("{} policy requires a classical signature; none present", policy)));
+    }
+    if policy.requires(true) && !post_quantum {
+        return Err(ErrorDetail::rejected(format!("{} policy requires a post-quantum signature; none present", policy)));
+    }
+    Ok(verified)
+}
+
+fn check_validity(key: &TrustedKey, now: DateTime<Utc>) -> Result<(), ErrorDetail> {
+    if let Some(not_before) = key.not_before {
+        if now < not_before {
+            return Err(ErrorDetail::rejected(format!("key `{}` is not valid before {}", key.id, not_before.to_rfc3339())));
+        }
+    }
+    if let Some(not_after) = key.not_after {
+        if now > not_after {
+            return Err(ErrorDetail::rejected(format!("key `{}` expired at {}", key.id, not_after.to_rfc3339())));
+        }
+    }
+    Ok(())
+}
+
+/// Sign `message` with a 32-byte secret: an Ed25519 seed, a P-256 scalar or an ML-DSA-65
+/// seed. ECDSA signatures are 64-byte `r || s`; ML-DSA uses the deterministic variant.
+pub fn sign(algorithm: KeyAlgorithm, secret: &[u8], message: &[u8]) -> Result<Vec<u8>, ErrorDetail> {
+    let secret: [u8; 32] = secret.try_into()
+        .map_err(|_| ErrorDetail::invalid(format!("{} secret key must be 32 bytes", algorithm)))?;
+    match algorithm {
+        KeyAlgorithm::Ed25519 => {
+            use ed25519_dalek::{Signer, SigningKey};
+            Ok(SigningKey::from_bytes(&secret).sign(message).to_bytes().to_vec())
+        }
+        KeyAlgorithm::EcdsaP256 => {
+            use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+            let key = SigningKey::from_slice(&secret)
+                .map_err(|_| ErrorDetail::invalid("P-256 secret key is out of range"))?;
+            let signature: Signature = key.sign(message);
+            Ok(signature.to_bytes().to_vec())
+        }
+        KeyAlgorithm::MlDsa65 => {
+            use mysten_mldsa_native_rs::SigningKeySeed;
+            let (key, _) = SigningKeySeed::from(secret).expand();
+            key.sign(message, &[], &[0u8; 32])
+                .map(|s| s.as_bytes().to_vec())
+                .map_err(|_| ErrorDetail::internal("ML-DSA-65 signing failed"))
+        }
+    }
+}
+
+/// The public key for a secret accepted by `sign`, encoded as `TrustedKey::public_key` expects.
+pub fn public_key(algorithm: KeyAlgorithm, secret: &[u8]) -> Result<Vec<u8>, ErrorDetail> {
+    let secret: [u8; 32] = secret.try_into()
+        .map_err(|_| ErrorDetail::invalid(format!("{} secret key must be 32 bytes", algorithm)))?;
+    match algorithm {
+        KeyAlgorithm::Ed25519 => Ok(ed25519_dalek::SigningKey::from_bytes(&secret).verifying_key().to_bytes().to_vec()),
+        KeyAlgorithm::EcdsaP256 => {
+            let key = p256::ecdsa::SigningKey::from_slice(&secret)
+                .map_err(|_| ErrorDetail::invalid("P-256 secret key is out of range"))?;
+            Ok(key.verifying_key().to_encoded_point(true).as_bytes().to_vec())
+        }
+        KeyAlgorithm::MlDsa65 => {
+            let (_, key) = mysten_mldsa_native_rs::SigningKeySeed::from(secret).expand();
+            Ok(key.as_bytes().to_vec())
+        }
+    }
+}
+
+/// Verify one `signature` over `message` with `key`; any mismatch is `ErrorKind::Rejected`.
+pub fn verify(key: &TrustedKey, message: &[u8], signature: &[u8]) -> Result<(), ErrorDetail> {
+    let public = hex::decode(key.public_key.trim())
+        .map_err(|_| ErrorDetail::invalid(format!("public key of `{}` is not valid hex", key.id)))?;
+    let mismatch = || ErrorDetail::rejected(format!("{} signature does not match key `{}`", key.algorithm, key.id));
+    match key.algorithm {
+        KeyAlgorithm::Ed25519 => {
+            use ed25519_dalek::{Signature, VerifyingKey};
+            let public: [u8; 32] = public.as_slice().try_into()
+                .map_err(|_| ErrorDetail::invalid(format!("Ed25519 key `{}` must be 32 bytes", key.id)))?;
+            let verifying = VerifyingKey::from_bytes(&public)
+                .map_err(|_| ErrorDetail::invalid(format!("Ed25519 key `{}` is not a valid point", key.id)))?;
+            let signature = Signature::from_slice(signature)
+                .map_err(|_| ErrorDetail::rejected("Ed25519 signature must be 64 bytes"))?;
+            verifying.verify_strict(message, &signature).map_err(|_| mismatch())
+        }
+        KeyAlgorithm::EcdsaP256 => {
+            use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+            let verifying = VerifyingKey::from_sec1_bytes(&public)
+                .map_err(|_| ErrorDetail::invalid(format!("P-256 key `{}` is not a valid SEC1 point", key.id)))?;
+            let signature = Signature::from_slice(signature)
+                .or_else(|_| Signature::from_der(signature))
+                .map_err(|_| ErrorDetail::rejected("ECDSA signature is neither 64-byte r||s nor DER"))?;
+            verifying.verify(message, &signature).map_err(|_| mismatch())
+        }
+        KeyAlgorithm::MlDsa65 => {
+            use mysten_mldsa_native_rs::{Signature, VerifyingKey, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+            let verifying = VerifyingKey::from_bytes(&public)
+                .map_err(|_| ErrorDetail::invalid(format!("ML-DSA-65 key `{}` must be {} bytes", key.id, PUBLIC_KEY_LENGTH)))?;
+            let signature = Signature::from_bytes(signature)
+                .map_err(|_| ErrorDetail::rejected(format!("ML-DSA-65 signature must be {} bytes", SIGNATURE_LENGTH)))?;
+            verifying.verify(message, &[], &signature).map_err(|_| mismatch())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    const MESSAGE: &[u8] = b"mesh-sec-ai-boot test message";
+
+    fn key(id: &str, algorithm: KeyAlgorithm, secret: u8) -> TrustedKey {
+        TrustedKey {
+            id: id.to_string(),
+            algorithm,
+            public_key: hex::encode(public_key(algorithm, &[secret; 32]).unwrap()),
+            not_before: None,
+            not_after: None,
+        }
+    }
+
+    fn signed(key: &TrustedKey, secret: u8, message: &[u8]) -> DetachedSignature {
+        DetachedSignature {
+            key_id: key.id.clone(),
+            algorithm: key.algorithm,
+            signature: hex::encode(sign(key.algorithm, &[secret; 32], message).unwrap()),
+        }
+    }
+
+    fn check(signatures: &[DetachedSignature], keys: &[TrustedKey], revoked: &[&str], policy: SignaturePolicy)
+        -> Result<Vec<String>, ErrorDetail>
+    {
+        let revoked: Vec<String> = revoked.iter().map(|id| id.to_string()).collect();
+        verify_all(MESSAGE, signatures, keys, &revoked, policy, Utc::now())
+    }
+
+    fn rejected(result: Result<Vec<String>, ErrorDetail>, needle: &str) {
+        let err = result.unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Rejected, "{}", err.message);
+        assert!(err.message.contains(needle), "`{}` does not mention `{}`", err.message, needle);
+    }
+
+    #[test]
+    fn accepts_each_algorithm() {
+        for (id, algorithm) in [("ed", KeyAlgorithm::Ed25519), ("p256", KeyAlgorithm::EcdsaP256), ("mldsa", KeyAlgorithm::MlDsa65)] {
+            let key = key(id, algorithm, 7);
+            let policy = if algorithm.is_post_quantum() { SignaturePolicy::PostQuantum } else { SignaturePolicy::Classical };
+            assert_eq!(check(&[signed(&key, 7, MESSAGE)], &[key], &[], policy).unwrap(), vec![id.to_string()]);
+        }
+    }
+
+    #[test]
+    fn accepts_a_hybrid_bundle_and_refuses_either_half_alone() {
+        let (ed, mldsa) = (key("ed", KeyAlgorithm::Ed25519, 1), key("mldsa", KeyAlgorithm::MlDsa65, 2));
+        let bundle = [signed(&ed, 1, MESSAGE), signed(&mldsa, 2, MESSAGE)];
+        let keys = [ed, mldsa];
+        assert_eq!(check(&bundle, &keys, &[], SignaturePolicy::Both).unwrap(), vec!["ed", "mldsa"]);
+        rejected(check(&bundle[..1], &keys, &[], SignaturePolicy::Both), "post-quantum");
+        rejected(check(&bundle[1..], &keys, &[], SignaturePolicy::Both), "classical");
+    }
+
+    #[test]
+    fn rejects_a_tampered_message_or_the_wrong_key() {
+        let ed = key("ed", KeyAlgorithm::Ed25519, 1);
+        rejected(check(&[signed(&ed, 1, b"something else")], std::slice::from_ref(&ed), &[], SignaturePolicy::Classical),
+                 "does not match key `ed`");
+        rejected(check(&[signed(&ed, 9, MESSAGE)], std::slice::from_ref(&ed), &[], SignaturePolicy::Classical), "does not match");
+        rejected(check(&[signed(&ed, 1, MESSAGE)], &[], &[], SignaturePolicy::Classical), "is not trusted");
+        rejected(check(&[], &[ed], &[], SignaturePolicy::Classical), "none present");
+    }
+
+    #[test]
+    fn rejects_revoked_keys() {
+        let ed = key("ed", KeyAlgorithm::Ed25519, 1);
+        rejected(check(&[signed(&ed, 1, MESSAGE)], &[ed], &["ed"], SignaturePolicy::Classical), "`ed` is revoked");
+    }
+
+    #[test]
+    fn rejects_keys_outside_their_validity_window() {
+        let mut expired = key("old", KeyAlgorithm::EcdsaP256, 3);
+        expired.not_after = Some(Utc::now() - Duration::days(1));
+        rejected(check(&[signed(&expired, 3, MESSAGE)], &[expired], &[], SignaturePolicy::Classical), "expired");
+        let mut future = key("new", KeyAlgorithm::EcdsaP256, 3);
+        future.not_before = Some(Utc::now() + Duration::days(1));
+        rejected(check(&[signed(&future, 3, MESSAGE)], &[future], &[], SignaturePolicy::Classical), "not valid before");
+    }
+
+    #[test]
+    fn reads_single_and_bundled_signature_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let ed = key("ed", KeyAlgorithm::Ed25519, 1);
+        let signature = signed(&ed, 1, MESSAGE);
+        let single = dir.path().join("single.sig");
+        std::fs::write(&single, serde_json::to_string(&signature).unwrap()).unwrap();
+        let bundle = dir.path().join("bundle.sig");
+        std::fs::write(&bundle, serde_json::to_string(&SignatureBundle { signatures: vec![signature.clone()] }).unwrap()).unwrap();
+        assert_eq!(read_signatures(&single).unwrap(), vec![signature.clone()]);
+        assert_eq!(read_signatures(&bundle).unwrap(), vec![signature]);
+        let missing = read_signatures(&dir.path().join("missing.sig")).unwrap_err();
+        assert_eq!(missing.kind, crate::error::ErrorKind::Rejected);
+    }
+}
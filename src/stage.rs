@@ -0,0 +1,800 @@
+use crate::error::{BootError, ErrorDetail, ErrorKind};
+use crate::integrity::{IntegrityMonitor, IntegrityState};
+use crate::report::{BootReport, DryRun, PlannedStage, StageOutcome, StageRecord};
+use crate::measure::{self, EventType, MeasuredBoot};
+use crate::policy::PolicyStore;
+use crate::profile::FailurePolicy;
+use crate::schema::{BootConfig, HashAlgorithm};
+use crate::{ai, compliance, fs, integrity, security, shell};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+pub const FIRMWARE: &str = "firmware";
+pub const ENFORCEMENT: &str = "enforcement";
+pub const MODELS: &str = "models";
+pub const FILESYSTEM: &str = "filesystem";
+pub const COMPLIANCE: &str = "compliance";
+pub const INTEGRITY: &str = "integrity";
+pub const RECOVERY_SHELL: &str = "recovery-shell";
+
+/// State shared by every stage of one boot.
+pub struct BootContext {
+    pub config: BootConfig,
+    /// Permissive unless the config names a policy file.
+    pub policy: Arc<PolicyStore>,
+    /// PCRs and event log every stage extends with what it loads.
+    pub measurements: Arc<MeasuredBoot>,
+    /// The process-wide `integrity::monitor()` unless replaced.
+    pub integrity: Arc<IntegrityMonitor>,
+    warnings: Mutex<Vec<(&'static str, String)>>,
+}
+
+impl BootContext {
+    pub fn new(config: BootConfig) -> Self {
+        let banks = config.measured_boot.as_ref().map_or(vec![HashAlgorithm::Sha256], |m| m.banks.clone());
+        BootContext {
+            config,
+            policy: Arc::new(PolicyStore::permissive()),
+            measurements: Arc::new(MeasuredBoot::new(&banks)),
+            integrity: integrity::monitor(),
+            warnings: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: Arc<PolicyStore>) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_measurements(mut self, measurements: MeasuredBoot) -> Self {
+        self.measurements = Arc::new(measurements);
+        self
+    }
+
+    pub fn with_integrity(mut self, integrity: Arc<IntegrityMonitor>) -> Self {
+        self.integrity = integrity;
+        self
+    }
+
+    /// Record a non-fatal finding against `stage`; it ends up in the `BootReport`.
+    pub fn warn(&self, stage: &'static str, message: impl Into<String>) {
+        let message = message.into();
+        println!("⚠️ {}: {}", stage, message);
+        self.warnings.lock().unwrap_or_else(|e| e.into_inner()).push((stage, message));
+    }
+
+    fn take_warnings(&self, stage: &'static str) -> Vec<String> {
+        let mut warnings = self.warnings.lock().unwrap_or_else(|e| e.into_inner());
+        let (mine, rest) = warnings.drain(..).partition(|(s, _)| *s == stage);
+        *warnings = rest;
+        mine.into_iter().map(|(_, w)| w).collect()
+    }
+}
+
+/// One step of the boot sequence. Stages run once all their `dependencies` have succeeded;
+/// stages whose dependencies are satisfied at the same time run concurrently.
+pub trait BootStage: Send + Sync {
+    /// Unique name other stages refer to in `dependencies`.
+    fn name(&self) -> &'static str;
+
+    fn dependencies(&self) -> &[&'static str] {
+        &[]
+    }
+
+    fn run(&self, ctx: &BootContext) -> Result<(), BootError>;
+
+    /// Whether the stage must run alone, on the thread that called `StageRegistry::run`,
+    /// because what it does applies to the calling thread and the threads it spawns
+    /// afterwards. Ready siblings wait for the next wave.
+    fn exclusive(&self) -> bool {
+        false
+    }
+
+    /// What `run` would do with this context, one action per line, without side effects.
+    fn describe(&self, _ctx: &BootContext) -> Vec<String> {
+        vec!["(no description)".to_string()]
+    }
+
+    /// Compensate for a successful `run` after a later stage failed. `None` means the
+    /// stage has nothing to undo.
+    fn rollback(&self, _ctx: &BootContext) -> Option<Result<(), BootError>> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RollbackResult {
+    RolledBack,
+    Failed(String),
+    /// The stage does not provide a rollback action.
+    Unsupported,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RollbackOutcome {
+    pub stage: &'static str,
+    pub result: RollbackResult,
+}
+
+/// A boot that stopped at `stage`. The report records the unwinding of the stages
+/// completed before it.
+#[derive(Debug)]
+pub struct BootFailure {
+    pub stage: &'static str,
+    pub error: BootError,
+    pub report: Box<BootReport>,
+}
+
+impl BootFailure {
+    pub fn exit_code(&self) -> u8 {
+        self.error.exit_code()
+    }
+
+    /// Whether every completed stage that could be undone was undone.
+    pub fn fully_rolled_back(&self) -> bool {
+        !self.report.rollbacks.iter().any(|r| matches!(r.result, RollbackResult::Failed(_)))
+    }
+}
+
+impl fmt::Display for BootFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stage `{}` failed: {}", self.stage, self.error)?;
+        for outcome in &self.report.rollbacks {
+            match &outcome.result {
+                RollbackResult::RolledBack => write!(f, "\n  ↩ {}: rolled back", outcome.stage)?,
+                RollbackResult::Failed(e) => write!(f, "\n  ✗ {}: rollback failed: {}", outcome.stage, e)?,
+                RollbackResult::Unsupported => write!(f, "\n  · {}: nothing to roll back", outcome.stage)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BootFailure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanError {
+    DuplicateStage(&'static str),
+    MissingDependency { stage: &'static str, dependency: &'static str },
+    /// Stages that could not be ordered because they depend on each other.
+    Cycle(Vec<&'static str>),
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanError::DuplicateStage(name) => write!(f, "stage `{}` is registered twice", name),
+            PlanError::MissingDependency { stage, dependency } => {
+                write!(f, "stage `{}` depends on unknown stage `{}`", stage, dependency)
+            }
+            PlanError::Cycle(stages) => write!(f, "dependency cycle among stages: {}", stages.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+/// Stages in execution order: every stage in a wave depends only on stages of earlier waves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub waves: Vec<Vec<&'static str>>,
+}
+
+#[derive(Default)]
+pub struct StageRegistry {
+    stages: Vec<Box<dyn BootStage>>,
+    /// Planned (so dependents still order correctly) but never run.
+    skipped: HashSet<&'static str>,
+}
+
+impl StageRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in sequence formerly hard-wired into `boot::launch`.
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        registry.register(FirmwareStage);
+        registry.register(EnforcementStage);
+        registry.register(ModelStage);
+        registry.register(FilesystemStage);
+        registry.register(ComplianceStage);
+        registry.register(IntegrityStage);
+        registry
+    }
+
+    pub fn register<S: BootStage + 'static>(&mut self, stage: S) -> &mut Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Keep `name` in the plan but do not run it; its dependents treat it as satisfied.
+    pub fn skip(&mut self, name: &'static str) -> &mut Self {
+        self.skipped.insert(name);
+        self
+    }
+
+    pub fn is_skipped(&self, name: &str) -> bool {
+        self.skipped.contains(name)
+    }
+
+    pub fn stages(&self) -> impl Iterator<Item = &dyn BootStage> {
+        self.stages.iter().map(|s| s.as_ref())
+    }
+
+    /// Order the registered stages topologically, rejecting duplicates, dangling
+    /// dependencies and cycles before anything runs.
+    pub fn plan(&self) -> Result<Plan, PlanError> {
+        let mut names = HashSet::new();
+        for stage in &self.stages {
+            if !names.insert(stage.name()) {
+                return Err(PlanError::DuplicateStage(stage.name()));
+            }
+        }
+        for stage in &self.stages {
+            if let Some(missing) = stage.dependencies().iter().find(|d| !names.contains(*d)) {
+                return Err(PlanError::MissingDependency { stage: stage.name(), dependency: missing });
+            }
+        }
+
+        let mut pending: HashMap<&'static str, HashSet<&'static str>> = self.stages.iter()
+            .map(|s| (s.name(), s.dependencies().iter().copied().collect()))
+            .collect();
+        let mut waves = Vec::new();
+        while !pending.is_empty() {
+            // Keep registration order within a wave so output is deterministic.
+            let mut ready: Vec<&'static str> = self.stages.iter()
+                .map(|s| s.name())
+                .filter(|name| pending.get(name).is_some_and(HashSet::is_empty))
+                .collect();
+            if let Some(barrier) = self.stages.iter().find(|s| s.exclusive() && ready.contains(&s.name())) {
+                ready = vec![barrier.name()];
+            }
+            if ready.is_empty() {
+                let blocked = self.stages.iter()
+                    .map(|s| s.name())
+                    .filter(|name| pending.contains_key(name))
+                    .collect();
+                return Err(PlanError::Cycle(blocked));
+            }
+            for name in &ready {
+                pending.remove(name);
+            }
+            for deps in pending.values_mut() {
+                deps.retain(|d| !ready.contains(d));
+            }
+            waves.push(ready);
+        }
+        Ok(Plan { waves })
+    }
+
+    /// Plan the boot and collect every stage's `describe` output instead of running it.
+    pub fn dry_run(&self, ctx: &BootContext) -> Result<DryRun, PlanError> {
+        let plan = self.plan()?;
+        let waves = plan.waves.iter()
+            .map(|wave| wave.iter()
+                .map(|name| {
+                    let stage = self.stages().find(|s| s.name() == *name).expect("planned stage is registered");
+                    let skipped = self.is_skipped(stage.name());
+                    PlannedStage {
+                        name: stage.name(),
+                        dependencies: stage.dependencies().to_vec(),
+                        skipped,
+                        actions: if skipped { Vec::new() } else { stage.describe(ctx) },
+                    }
+                })
+                .collect())
+            .collect();
+        Ok(DryRun { profile: ctx.config.profile, config_warnings: Vec::new(), waves })
+    }
+
+    /// Run the plan wave by wave, stages within a wave on their own threads; a wave of one
+    /// stage, as every `BootStage::exclusive` stage is, runs on the calling thread. When a
+    /// stage fails under `FailurePolicy::FailClosed`, its wave is allowed to finish and
+    /// every completed stage is then rolled back in reverse plan order; under
+    /// `FailurePolicy::Warn` the failure is recorded and the boot carries on.
+    pub fn run(&self, ctx: &BootContext) -> Result<BootReport, BootFailure> {
+        let clock = Instant::now();
+        let mut report = BootReport { profile: ctx.config.profile, ..BootReport::begin() };
+        let plan = match self.plan() {
+            Ok(plan) => plan,
+            Err(e) => {
+                let error = BootError::Plan(ErrorDetail::invalid("cannot order boot stages").with_source(e));
+                return Err(BootFailure { stage: "plan", error, report: Box::new(report) });
+            }
+        };
+
+        let policy = ctx.config.profile.failure_policy();
+        let mut completed: Vec<&dyn BootStage> = Vec::new();
+        let mut failure = None;
+        for wave in &plan.waves {
+            let (skipped, stages): (Vec<&dyn BootStage>, Vec<&dyn BootStage>) = wave.iter()
+                .map(|name| self.stages().find(|s| s.name() == *name).expect("planned stage is registered"))
+                .partition(|s| self.is_skipped(s.name()));
+            report.stages.extend(skipped.iter().map(|s| StageRecord {
+                warnings: vec![format!("skipped by {} profile", ctx.config.profile)],
+                ..StageRecord::skipped(s.name())
+            }));
+            if failure.is_some() {
+                report.stages.extend(stages.iter().map(|s| StageRecord::skipped(s.name())));
+                continue;
+            }
+            if stages.is_empty() {
+                continue;
+            }
+            let records: Vec<(StageRecord, Option<BootError>)> = if stages.len() == 1 {
+                vec![run_timed(stages[0], ctx)]
+            } else {
+                thread::scope(|scope| {
+                    let handles: Vec<_> = stages.iter()
+                        .map(|stage| (stage.name(), scope.spawn(move || run_timed(*stage, ctx))))
+                        .collect();
+                    handles.into_iter()
+                        .map(|(name, handle)| handle.join().unwrap_or_else(|_| {
+                            let error = BootError::Stage(name, ErrorDetail::internal("stage panicked"));
+                            let record = StageRecord {
+                                outcome: StageOutcome::Failed,
+                                error_code: Some(error.code()),
+                                error_chain: error.chain(),
+                                ..StageRecord::skipped(name)
+                            };
+                            (record, Some(error))
+                        }))
+                        .collect()
+                })
+            };
+            for (stage, (mut record, error)) in stages.into_iter().zip(records) {
+                if let Some(error) = &error {
+                    // A refused recovery shell says nothing about the node's integrity.
+                    let state = match (error, error.kind()) {
+                        (BootError::Shell(_), _) => IntegrityState::Degraded,
+                        (_, ErrorKind::Rejected) => IntegrityState::Compromised,
+                        _ => IntegrityState::Degraded,
+                    };
+                    ctx.integrity.transition(state, format!("{} stage failed: {}", stage.name(), error));
+                }
+                match error {
+                    Some(error) if policy == FailurePolicy::Warn => {
+                        println!("⚠️ {}: {} (continuing under warn policy)", stage.name(), error);
+                        record.outcome = StageOutcome::Tolerated;
+                        record.warnings.push(format!("failure tolerated by {} profile", ctx.config.profile));
+                    }
+                    Some(error) => {
+                        failure.get_or_insert((stage.name(), error));
+                    }
+                    None => completed.push(stage),
+                }
+                report.stages.push(record);
+            }
+        }
+
+        if let Some((_, error)) = &failure {
+            println!("↩️ Boot failed ({}), rolling back {} stage(s)", error, completed.len());
+            report.rollbacks = unwind(&completed, ctx);
+        }
+        report.finished_at = Utc::now();
+        report.duration_ms = clock.elapsed().as_secs_f64() * 1000.0;
+        report.succeeded = failure.is_none();
+        report.integrity = ctx.integrity.state();
+        report.integrity_transitions = ctx.integrity.transitions();
+        match failure {
+            None => Ok(report),
+            Some((stage, error)) => Err(BootFailure { stage, error, report: Box::new(report) }),
+        }
+    }
+}
+
+fn run_timed(stage: &dyn BootStage, ctx: &BootContext) -> (StageRecord, Option<BootError>) {
+    let started_at = Utc::now();
+    let clock = Instant::now();
+    let result = stage.run(ctx);
+    let duration_ms = clock.elapsed().as_secs_f64() * 1000.0;
+    let record = StageRecord {
+        name: stage.name(),
+        started_at: Some(started_at),
+        finished_at: Some(Utc::now()),
+        duration_ms,
+        outcome: if result.is_ok() { StageOutcome::Succeeded } else { StageOutcome::Failed },
+        warnings: ctx.take_warnings(stage.name()),
+        error_code: result.as_ref().err().map(BootError::code),
+        error_chain: result.as_ref().err().map(BootError::chain).unwrap_or_default(),
+    };
+    (record, result.err())
+}
+
+fn unwind(completed: &[&dyn BootStage], ctx: &BootContext) -> Vec<RollbackOutcome> {
+    completed.iter().rev()
+        .map(|stage| {
+            let result = match stage.rollback(ctx) {
+                None => RollbackResult::Unsupported,
+                Some(Ok(())) => RollbackResult::RolledBack,
+                Some(Err(e)) => RollbackResult::Failed(e.to_string()),
+            };
+            RollbackOutcome { stage: stage.name(), result }
+        })
+        .collect()
+}
+
+struct FirmwareStage;
+struct EnforcementStage;
+struct ModelStage;
+struct FilesystemStage;
+struct ComplianceStage;
+struct IntegrityStage;
+/// Registered by the recovery profile only.
+pub(crate) struct RecoveryShellStage;
+
+impl BootStage for FirmwareStage {
+    fn name(&self) -> &'static str { FIRMWARE }
+    fn run(&self, ctx: &BootContext) -> Result<(), BootError> {
+        match &ctx.config.firmware {
+            Some(firmware) => security::validate_firmware(
+                firmware, ctx.config.filesystem.encryption.signature_policy(), &ctx.measurements),
+            None => {
+                ctx.warn(FIRMWARE, "no [firmware] section configured; image signature not verified");
+                Ok(())
+            }
+        }
+    }
+    fn describe(&self, ctx: &BootContext) -> Vec<String> {
+        security::describe_firmware(ctx.config.firmware.as_ref(), ctx.config.filesystem.encryption.signature_policy())
+    }
+}
+
+impl BootStage for EnforcementStage {
+    fn name(&self) -> &'static str { ENFORCEMENT }
+    fn dependencies(&self) -> &[&'static str] { &[FIRMWARE] }
+    // Landlock, capabilities and no_new_privs only bind the calling thread.
+    fn exclusive(&self) -> bool { true }
+    fn run(&self, ctx: &BootContext) -> Result<(), BootError> {
+        let config = &ctx.config;
+        ctx.measurements.measure_json(measure::PCR_CONFIG, format!("profile {}", config.profile), &config.profile)?;
+        ctx.measurements.measure_json(measure::PCR_CONFIG, "enforcement", &config.enforcement)?;
+        if let Some(policy) = &config.policy {
+            ctx.measurements.measure_file(measure::PCR_POLICY, EventType::EvEventTag, "policy", Path::new(&policy.path))?;
+        }
+        for warning in security::enforce(&config.enforcement, &config.filesystem, config.profile, &ctx.policy)? {
+            ctx.warn(ENFORCEMENT, warning);
+        }
+        Ok(())
+    }
+    fn describe(&self, ctx: &BootContext) -> Vec<String> {
+        security::describe_enforce(&ctx.config.enforcement, &ctx.config.filesystem)
+    }
+    // No rollback: rlimits, seccomp filters, dropped capabilities and Landlock rulesets
+    // cannot be lifted by the process they confine.
+}
+
+impl BootStage for ModelStage {
+    fn name(&self) -> &'static str { MODELS }
+    fn dependencies(&self) -> &[&'static str] { &[ENFORCEMENT] }
+    fn run(&self, ctx: &BootContext) -> Result<(), BootError> {
+        for model in &ctx.config.ai_models {
+            let data = serde_json::to_vec(model).expect("model descriptor serializes to JSON");
+            ctx.measurements.measure(measure::PCR_MODELS, EventType::EvIpl,
+                                     format!("model {}@{}", model.name, model.version), &data)?;
+        }
+        ai::initialize(&ctx.config.ai_models, &ctx.policy)
+    }
+    fn describe(&self, ctx: &BootContext) -> Vec<String> {
+        ai::describe(&ctx.config.ai_models)
+    }
+    fn rollback(&self, ctx: &BootContext) -> Option<Result<(), BootError>> {
+        Some(ai::shutdown(&ctx.config.ai_models))
+    }
+}
+
+impl BootStage for FilesystemStage {
+    fn name(&self) -> &'static str { FILESYSTEM }
+    fn dependencies(&self) -> &[&'static str] { &[ENFORCEMENT] }
+    fn run(&self, ctx: &BootContext) -> Result<(), BootError> {
+        ctx.measurements.measure_json(measure::PCR_FILESYSTEM, "filesystem", &ctx.config.filesystem)?;
+        let tpm = ctx.config.measured_boot.as_ref().and_then(|m| m.tpm.as_ref());
+        let key = fs::unseal_key(tpm, &ctx.measurements)?;
+        fs::mount(&ctx.config.filesystem, key.as_deref())
+    }
+    fn describe(&self, ctx: &BootContext) -> Vec<String> {
+        fs::describe_mount(&ctx.config.filesystem, ctx.config.measured_boot.as_ref().and_then(|m| m.tpm.as_ref()))
+    }
+    fn rollback(&self, ctx: &BootContext) -> Option<Result<(), BootError>> {
+        Some(fs::unmount(&ctx.config.filesystem))
+    }
+}
+
+impl BootStage for ComplianceStage {
+    fn name(&self) -> &'static str { COMPLIANCE }
+    fn dependencies(&self) -> &[&'static str] { &[FILESYSTEM] }
+    fn run(&self, ctx: &BootContext) -> Result<(), BootError> {
+        ctx.measurements.measure_json(measure::PCR_COMPLIANCE, "compliance", &ctx.config.compliance)?;
+        compliance::apply(&ctx.config.compliance)
+    }
+    fn describe(&self, ctx: &BootContext) -> Vec<String> {
+        compliance::describe(&ctx.config.compliance)
+    }
+}
+
+impl BootStage for IntegrityStage {
+    fn name(&self) -> &'static str { INTEGRITY }
+    fn dependencies(&self) -> &[&'static str] { &[MODELS, FILESYSTEM, COMPLIANCE] }
+    fn run(&self, ctx: &BootContext) -> Result<(), BootError> {
+        ctx.integrity.transition(IntegrityState::Verifying,
+                                 format!("replaying {} measured boot event(s)", ctx.measurements.log().events.len()));
+        let warnings = integrity::verify(&ctx.measurements, ctx.config.measured_boot.as_ref(),
+                                         ctx.config.filesystem.encryption.signature_policy())?;
+        match warnings.first() {
+            None => ctx.integrity.transition(IntegrityState::Verified, "event log replayed and matched golden values"),
+            Some(first) => ctx.integrity.transition(IntegrityState::Degraded, first.clone()),
+        };
+        for warning in warnings {
+            ctx.warn(INTEGRITY, warning);
+        }
+        Ok(())
+    }
+    fn describe(&self, ctx: &BootContext) -> Vec<String> {
+        integrity::describe(ctx.config.measured_boot.as_ref())
+    }
+}
+
+impl BootStage for RecoveryShellStage {
+    fn name(&self) -> &'static str { RECOVERY_SHELL }
+    fn dependencies(&self) -> &[&'static str] { &[INTEGRITY] }
+    fn run(&self, ctx: &BootContext) -> Result<(), BootError> {
+        shell::open_restricted(&ctx.config.filesystem, &ctx.policy)
+    }
+    fn describe(&self, ctx: &BootContext) -> Vec<String> {
+        shell::describe_restricted(&ctx.config.filesystem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::BootProfile;
+    use std::thread::ThreadId;
+
+    /// A stage that records what it did into a shared journal.
+    struct Stub {
+        name: &'static str,
+        dependencies: &'static [&'static str],
+        fails: bool,
+        exclusive: bool,
+        /// What `rollback` returns: `None` for nothing to undo, `Some(false)` for a failure.
+        undo: Option<bool>,
+        journal: Arc<Mutex<Vec<(String, ThreadId)>>>,
+    }
+
+    impl Stub {
+        fn new(name: &'static str, dependencies: &'static [&'static str], journal: &Arc<Mutex<Vec<(String, ThreadId)>>>) -> Self {
+            Stub { name, dependencies, fails: false, exclusive: false, undo: Some(true), journal: journal.clone() }
+        }
+
+        fn record(&self, what: &str) {
+            self.journal.lock().unwrap().push((format!("{} {}", what, self.name), thread::current().id()));
+        }
+    }
+
+    impl BootStage for Stub {
+        fn name(&self) -> &'static str { self.name }
+        fn dependencies(&self) -> &[&'static str] { self.dependencies }
+        fn exclusive(&self) -> bool { self.exclusive }
+        fn run(&self, _: &BootContext) -> Result<(), BootError> {
+            self.record("run");
+            if self.fails {
+                return Err(BootError::Stage(self.name, ErrorDetail::rejected("stub failure")));
+            }
+            Ok(())
+        }
+        fn rollback(&self, _: &BootContext) -> Option<Result<(), BootError>> {
+            let undone = self.undo?;
+            self.record("rollback");
+            Some(if undone { Ok(()) } else { Err(BootError::Stage(self.name, ErrorDetail::internal("stub rollback failure"))) })
+        }
+    }
+
+    fn context(profile: BootProfile) -> BootContext {
+        BootContext::new(BootConfig { profile, ..BootConfig::default() }).with_integrity(Arc::new(IntegrityMonitor::new()))
+    }
+
+    fn registry(stages: Vec<Stub>) -> StageRegistry {
+        let mut registry = StageRegistry::new();
+        for stage in stages {
+            registry.register(stage);
+        }
+        registry
+    }
+
+    #[test]
+    fn plans_reject_duplicates_unknown_dependencies_and_cycles() {
+        let journal = Arc::new(Mutex::new(Vec::new()));
+        let plan = |stages| registry(stages).plan();
+        assert_eq!(plan(vec![Stub::new("a", &[], &journal), Stub::new("a", &[], &journal)]),
+                   Err(PlanError::DuplicateStage("a")));
+        assert_eq!(plan(vec![Stub::new("a", &[], &journal), Stub::new("b", &["a", "ghost"], &journal)]),
+                   Err(PlanError::MissingDependency { stage: "b", dependency: "ghost" }));
+        assert_eq!(plan(vec![Stub::new("self", &["self"], &journal)]), Err(PlanError::Cycle(vec!["self"])));
+        // Stages outside the cycle are ordered first; only the cycle is reported.
+        let cycle = vec![
+            Stub::new("root", &[], &journal),
+            Stub::new("a", &["root", "c"], &journal),
+            Stub::new("b", &["a"], &journal),
+            Stub::new("c", &["b"], &journal),
+            Stub::new("after", &["c"], &journal),
+        ];
+        assert_eq!(plan(cycle).unwrap_err().to_string(), "dependency cycle among stages: a, b, c, after");
+
+        let failure = registry(vec![Stub::new("a", &["b"], &journal), Stub::new("b", &["a"], &journal)])
+            .run(&context(BootProfile::Production)).unwrap_err();
+        assert_eq!(failure.stage, "plan");
+        assert!(matches!(failure.error, BootError::Plan(_)), "{:?}", failure.error);
+        assert!(journal.lock().unwrap().is_empty(), "nothing runs without a plan");
+    }
+
+    #[test]
+    fn independent_stages_share_a_wave_in_registration_order() {
+        let journal = Arc::new(Mutex::new(Vec::new()));
+        let registry = registry(vec![
+            Stub::new("join", &["right", "left"], &journal),
+            Stub::new("right", &["root"], &journal),
+            Stub::new("root", &[], &journal),
+            Stub::new("left", &["root"], &journal),
+            Stub::new("alone", &[], &journal),
+            Stub::new("last", &["join"], &journal),
+        ]);
+        assert_eq!(registry.plan().unwrap().waves,
+                   vec![vec!["root", "alone"], vec!["right", "left"], vec!["join"], vec!["last"]]);
+
+        let report = registry.run(&context(BootProfile::Production)).unwrap();
+        let order: Vec<_> = report.stages.iter().map(|s| s.name).collect();
+        assert_eq!(order, ["root", "alone", "right", "left", "join", "last"]);
+        let ran = |name: &str| journal.lock().unwrap().iter().position(|(what, _)| *what == format!("run {}", name)).unwrap();
+        assert!(ran("root") < ran("left") && ran("right") < ran("join") && ran("left") < ran("join") && ran("join") < ran("last"));
+    }
+
+    fn entries(journal: &Mutex<Vec<(String, ThreadId)>>) -> Vec<String> {
+        journal.lock().unwrap().iter().map(|(what, _)| what.clone()).collect()
+    }
+
+    #[test]
+    fn a_failure_skips_later_waves_and_unwinds_in_reverse() {
+        let journal = Arc::new(Mutex::new(Vec::new()));
+        let registry = registry(vec![
+            Stub { undo: None, ..Stub::new("firmware", &[], &journal) },
+            Stub::new("models", &["firmware"], &journal),
+            Stub { undo: Some(false), ..Stub::new("mount", &["models"], &journal) },
+            Stub::new("keys", &["mount"], &journal),
+            Stub { fails: true, ..Stub::new("compliance", &["keys"], &journal) },
+            Stub::new("integrity", &["compliance"], &journal),
+        ]);
+        let ctx = context(BootProfile::Production);
+        let failure = registry.run(&ctx).unwrap_err();
+
+        assert_eq!(failure.stage, "compliance");
+        assert_eq!(entries(&journal), ["run firmware", "run models", "run mount", "run keys", "run compliance",
+                                       "rollback keys", "rollback mount", "rollback models"]);
+        let outcomes: Vec<_> = failure.report.stages.iter().map(|s| (s.name, s.outcome)).collect();
+        assert_eq!(outcomes, [("firmware", StageOutcome::Succeeded), ("models", StageOutcome::Succeeded),
+                              ("mount", StageOutcome::Succeeded), ("keys", StageOutcome::Succeeded),
+                              ("compliance", StageOutcome::Failed), ("integrity", StageOutcome::Skipped)]);
+        let rollbacks: Vec<_> = failure.report.rollbacks.iter().map(|r| (r.stage, &r.result)).collect();
+        assert_eq!(rollbacks, [
+            ("keys", &RollbackResult::RolledBack),
+            ("mount", &RollbackResult::Failed("[stage.internal] mount: stub rollback failure".to_string())),
+            ("models", &RollbackResult::RolledBack),
+            ("firmware", &RollbackResult::Unsupported),
+        ]);
+        assert!(!failure.fully_rolled_back());
+        assert!(!failure.report.succeeded);
+        assert_eq!(ctx.integrity.state(), IntegrityState::Compromised);
+        assert!(failure.to_string().contains("✗ mount: rollback failed"), "{}", failure);
+    }
+
+    #[test]
+    fn a_failure_inside_a_wave_rolls_back_the_siblings_that_succeeded() {
+        let journal = Arc::new(Mutex::new(Vec::new()));
+        let registry = registry(vec![
+            Stub::new("firmware", &[], &journal),
+            Stub::new("models", &["firmware"], &journal),
+            Stub { fails: true, ..Stub::new("mount", &["firmware"], &journal) },
+            Stub::new("compliance", &["firmware"], &journal),
+            Stub::new("integrity", &["models", "mount", "compliance"], &journal),
+        ]);
+        assert_eq!(registry.plan().unwrap().waves[1], ["models", "mount", "compliance"]);
+        let failure = registry.run(&context(BootProfile::Production)).unwrap_err();
+
+        assert_eq!(failure.stage, "mount");
+        let rollbacks: Vec<_> = failure.report.rollbacks.iter().map(|r| r.stage).collect();
+        assert_eq!(rollbacks, ["compliance", "models", "firmware"]);
+        assert!(failure.fully_rolled_back());
+        let journal = entries(&journal);
+        assert!(journal.contains(&"run compliance".to_string()), "the wave finishes: {:?}", journal);
+        assert!(!journal.contains(&"run integrity".to_string()));
+        assert!(!journal.contains(&"rollback mount".to_string()), "a failed stage is not rolled back");
+    }
+
+    #[test]
+    fn the_warn_policy_tolerates_failures_without_rolling_back() {
+        let journal = Arc::new(Mutex::new(Vec::new()));
+        let registry = registry(vec![
+            Stub { fails: true, ..Stub::new("firmware", &[], &journal) },
+            Stub::new("integrity", &["firmware"], &journal),
+        ]);
+        let ctx = context(BootProfile::Developer);
+        let report = registry.run(&ctx).unwrap();
+        assert_eq!(entries(&journal), ["run firmware", "run integrity"]);
+        assert_eq!(report.stages[0].outcome, StageOutcome::Tolerated);
+        assert!(report.rollbacks.is_empty());
+        assert_eq!(ctx.integrity.state(), IntegrityState::Compromised);
+    }
+
+    #[test]
+    fn exclusive_stages_run_alone_on_the_calling_thread() {
+        let journal = Arc::new(Mutex::new(Vec::new()));
+        let mut registry = StageRegistry::new();
+        registry.register(Stub::new("a", &[], &journal));
+        registry.register(Stub { exclusive: true, ..Stub::new("barrier", &["a"], &journal) });
+        registry.register(Stub::new("sibling", &["a"], &journal));
+        registry.register(Stub::new("other", &["a"], &journal));
+        assert_eq!(registry.plan().unwrap().waves, vec![vec!["a"], vec!["barrier"], vec!["sibling", "other"]]);
+
+        registry.run(&context(BootProfile::Production)).unwrap();
+        let journal = journal.lock().unwrap();
+        let (_, barrier_thread) = journal.iter().find(|(what, _)| what == "run barrier").unwrap();
+        assert_eq!(*barrier_thread, thread::current().id());
+    }
+
+    /// Enforcement shares its wave with a site stage; the boot thread, not a worker,
+    /// must end up confined. Irreversible, so it runs in a child process.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn enforcement_confines_the_boot_thread_despite_siblings() {
+        const CHILD_ENV: &str = "MESH_STAGE_TEST_DIR";
+        let Ok(dir) = std::env::var(CHILD_ENV) else {
+            let dir = tempfile::tempdir().unwrap();
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "stage::tests::enforcement_confines_the_boot_thread_despite_siblings", "--nocapture", "--test-threads=1"])
+                .env(CHILD_ENV, dir.path())
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "child failed:\n{}{}",
+                    String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+            return;
+        };
+        let dir = Path::new(&dir);
+        let mut config = BootConfig { profile: BootProfile::Staging, ..BootConfig::default() };
+        config.enforcement.restrict_shell = false;
+        config.enforcement.lock_resources = false;
+        config.filesystem.mount_at = dir.join("mnt").to_string_lossy().into_owned();
+        config.filesystem.read_only = Vec::new();
+        config.filesystem.read_write = Vec::new();
+        let journal = Arc::new(Mutex::new(Vec::new()));
+        let mut registry = StageRegistry::new();
+        registry.register(FirmwareStage);
+        registry.register(EnforcementStage);
+        registry.register(Stub::new("site", &[FIRMWARE], &journal));
+        let ctx = BootContext::new(config).with_integrity(Arc::new(IntegrityMonitor::new()));
+
+        match registry.run(&ctx) {
+            Ok(_) => {
+                assert_eq!(unsafe { libc::prctl(libc::PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) }, 1);
+                std::fs::write(dir.join("mnt").join("inside"), b"ok").unwrap();
+                let denied = std::fs::write(dir.join("outside"), b"escaped").unwrap_err();
+                assert_eq!(denied.kind(), std::io::ErrorKind::PermissionDenied);
+                assert_eq!(journal.lock().unwrap().len(), 1, "the site stage still runs");
+            }
+            Err(failure) if failure.error.kind() == ErrorKind::Unsupported => println!("confinement unavailable, skipped: {}", failure),
+            Err(failure) => panic!("{}", failure),
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! TPM 2.0 backend behind the `tpm` feature, via tss-esapi. Every call opens its own
+//! ESYS context from the configured TCTI, so a `Tpm` can be shared between stage
+//! threads. Keys are primaries re-derived from the hierarchy seeds on each use, so the
+//! attestation key and the sealing parent are stable for the life of the TPM.
+//!
+//! Against the simulator: `swtpm socket --tpm2 --tpmstate dir=/tmp/swtpm
+//! --server type=tcp,port=2321 --ctrl type=tcp,port=2322 --flags not-need-init,startup-clear`
+//! with `tcti = "swtpm:host=localhost,port=2321"`.
+
+use crate::error::{ErrorDetail, ErrorKind};
+use crate::schema::{HashAlgorithm, TpmConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use tss_esapi::attributes::{ObjectAttributesBuilder, SessionAttributesBuilder};
+use tss_esapi::constants::SessionType;
+use tss_esapi::handles::{KeyHandle, PcrHandle};
+use tss_esapi::interface_types::algorithm::{HashingAlgorithm, PublicAlgorithm};
+use tss_esapi::interface_types::ecc::EccCurve;
+use tss_esapi::interface_types::resource_handles::Hierarchy;
+use tss_esapi::interface_types::session_handles::{AuthSession, PolicySession};
+use tss_esapi::structures::{
+    Data, Digest, DigestValues, EccPoint, EccScheme, HashScheme, KeyedHashScheme, PcrSelectionList,
+    PcrSelectionListBuilder, PcrSlot, Private, Public, PublicBuilder, PublicEccParametersBuilder,
+    PublicKeyedHashParameters, SensitiveData, SignatureScheme, SymmetricDefinition,
+};
+use tss_esapi::traits::{Marshall, UnMarshall};
+use tss_esapi::{Context, TctiNameConf};
+
+/// Verified without a TPM by `tpm_quote::verify`.
+pub use crate::tpm_quote::TpmQuote as Quote;
+
+/// A secret sealed to SHA-256 PCR values, as written by `tpm seal`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SealedBlob {
+    pub public: String,
+    pub private: String,
+    pub pcrs: Vec<u32>,
+}
+
+pub struct Tpm {
+    tcti: TctiNameConf,
+}
+
+fn tpm_error(what: impl Into<String>) -> impl FnOnce(tss_esapi::Error) -> ErrorDetail {
+    let what = what.into();
+    move |e| ErrorDetail::new(ErrorKind::Io, what).with_source(e)
+}
+
+fn algorithm(bank: HashAlgorithm) -> HashingAlgorithm {
+    match bank {
+        HashAlgorithm::Sha256 => HashingAlgorithm::Sha256,
+        HashAlgorithm::Sha512 => HashingAlgorithm::Sha512,
+    }
+}
+
+fn selection(bank: HashAlgorithm, pcrs: &[u32]) -> Result<PcrSelectionList, ErrorDetail> {
+    let slots = pcrs.iter()
+        .map(|pcr| PcrSlot::try_from(1u32 << pcr).map_err(tpm_error(format!("PCR {} cannot be selected", pcr))))
+        .collect::<Result<Vec<_>, _>>()?;
+    PcrSelectionListBuilder::new()
+        .with_selection(algorithm(bank), &slots)
+        .build()
+        .map_err(tpm_error("cannot build PCR selection"))
+}
+
+impl Tpm {
+    /// Connect once to make sure the TPM answers.
+    pub fn open(cfg: &TpmConfig) -> Result<Self, ErrorDetail> {
+        let tcti = TctiNameConf::from_str(&cfg.tcti)
+            .map_err(|e| ErrorDetail::invalid(format!("bad TCTI `{}`", cfg.tcti)).with_source(e))?;
+        let tpm = Tpm { tcti };
+        tpm.context().map_err(|e| ErrorDetail::unsupported(format!("no TPM at `{}`", cfg.tcti)).with_source(e))?;
+        println!("🔏 TPM 2.0 at {}", cfg.tcti);
+        Ok(tpm)
+    }
+
+    fn context(&self) -> Result<Context, ErrorDetail> {
+        Context::new(self.tcti.clone()).map_err(tpm_error("cannot open TPM context"))
+    }
+
+    /// TPM2_PCR_Extend with one digest per bank. Digests for banks the TPM has not
+    /// allocated are ignored by the TPM.
+    pub fn extend(&self, pcr: u32, digests: &[(HashAlgorithm, &[u8])]) -> Result<(), ErrorDetail> {
+        let handle = PcrHandle::try_from(pcr).map_err(tpm_error(format!("PCR {} cannot be extended", pcr)))?;
+        let mut values = DigestValues::new();
+        for (bank, digest) in digests {
+            values.set(algorithm(*bank), Digest::try_from(digest.to_vec()).map_err(tpm_error("digest too long"))?);
+        }
+        let mut context = self.context()?;
+        context.execute_with_session(Some(AuthSession::Password), |ctx| ctx.pcr_extend(handle, values))
+            .map_err(tpm_error(format!("cannot extend PCR {}", pcr)))
+    }
+
+    /// Current values of `pcrs` in `bank`; empty when the bank is not allocated.
+    pub fn read(&self, bank: HashAlgorithm, pcrs: &[u32]) -> Result<BTreeMap<u32, Vec<u8>>, ErrorDetail> {
+        let mut context = self.context()?;
+        let data = tss_esapi::abstraction::pcr::read_all(&mut context, selection(bank, pcrs)?)
+            .map_err(tpm_error(format!("cannot read {} PCRs", bank)))?;
+        let mut values = BTreeMap::new();
+        if let Some(pcr_bank) = data.pcr_bank(algorithm(bank)) {
+            for pcr in pcrs {
+                let slot = PcrSlot::try_from(1u32 << pcr).map_err(tpm_error("bad PCR slot"))?;
+                if let Some(digest) = pcr_bank.get_digest(slot) {
+                    values.insert(*pcr, digest.value().to_vec());
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Restricted ECDSA P-256 signing key in the endorsement hierarchy.
+    fn attestation_key(context: &mut Context) -> Result<(KeyHandle, Public), ErrorDetail> {
+        let attributes = ObjectAttributesBuilder::new()
+            .with_fixed_tpm(true)
+            .with_fixed_parent(true)
+            .with_sensitive_data_origin(true)
+            .with_user_with_auth(true)
+            .with_restricted(true)
+            .with_sign_encrypt(true)
+            .build()
+            .map_err(tpm_error("cannot build AK attributes"))?;
+        let parameters = PublicEccParametersBuilder::new_restricted_signing_key(
+            EccScheme::EcDsa(HashScheme::new(HashingAlgorithm::Sha256)), EccCurve::NistP256)
+            .build()
+            .map_err(tpm_error("cannot build AK parameters"))?;
+        let template = PublicBuilder::new()
+            .with_public_algorithm(PublicAlgorithm::Ecc)
+            .with_name_hashing_algorithm(HashingAlgorithm::Sha256)
+            .with_object_attributes(attributes)
+            .with_ecc_parameters(parameters)
+            .with_ecc_unique_identifier(EccPoint::default())
+            .build()
+            .map_err(tpm_error("cannot build AK template"))?;
+        let key = context.execute_with_nullauth_session(|ctx| {
+            ctx.create_primary(Hierarchy::Endorsement, template, None, None, None, None)
+        }).map_err(tpm_error("cannot create attestation key"))?;
+        Ok((key.key_handle, key.out_public))
+    }
+
+    /// Sign the current values of `pcrs` together with `nonce`.
+    pub fn quote(&self, nonce: &[u8], bank: HashAlgorithm, pcrs: &[u32]) -> Result<Quote, ErrorDetail> {
+        let mut context = self.context()?;
+        let (ak, ak_public) = Self::attestation_key(&mut context)?;
+        let nonce = Data::try_from(nonce.to_vec()).map_err(tpm_error("nonce too long"))?;
+        let selection = selection(bank, pcrs)?;
+        let (attest, signature) = context.execute_with_nullauth_session(|ctx| {
+            ctx.quote(ak, nonce, SignatureScheme::Null, selection)
+        }).map_err(tpm_error("cannot quote PCRs"))?;
+        let _ = context.flush_context(ak.into());
+        Ok(Quote {
+            attest: hex::encode(attest.marshall().map_err(tpm_error("cannot marshal quote"))?),
+            signature: hex::encode(signature.marshall().map_err(tpm_error("cannot marshal signature"))?),
+            ak_public: hex::encode(ak_public.marshall().map_err(tpm_error("cannot marshal AK"))?),
+            bank,
+            pcrs: pcrs.to_vec(),
+        })
+    }
+
+    /// RSA-2048 storage primary in the owner hierarchy, parent of sealed objects.
+    fn storage_key(context: &mut Context) -> Result<KeyHandle, ErrorDetail> {
+        use tss_esapi::interface_types::key_bits::RsaKeyBits;
+        use tss_esapi::structures::{RsaExponent, SymmetricDefinitionObject};
+        let template = tss_esapi::utils::create_restricted_decryption_rsa_public(
+            SymmetricDefinitionObject::AES_128_CFB, RsaKeyBits::Rsa2048, RsaExponent::default())
+            .map_err(tpm_error("cannot build storage key template"))?;
+        context.execute_with_nullauth_session(|ctx| ctx.create_primary(Hierarchy::Owner, template, None, None, None, None))
+            .map(|key| key.key_handle)
+            .map_err(tpm_error("cannot create storage key"))
+    }
+
+    /// Start a policy session (or a trial one, to compute the digest) satisfied by the
+    /// current SHA-256 values of `pcrs`.
+    fn pcr_policy(context: &mut Context, pcrs: &[u32], trial: bool) -> Result<PolicySession, ErrorDetail> {
+        let session = context.start_auth_session(
+            None, None, None, if trial { SessionType::Trial } else { SessionType::Policy },
+            SymmetricDefinition::AES_128_CFB, HashingAlgorithm::Sha256)
+            .map_err(tpm_error("cannot start policy session"))?
+            .ok_or_else(|| ErrorDetail::internal("TPM returned no session"))?;
+        let (attributes, mask) = SessionAttributesBuilder::new()
+            .with_decrypt(true).with_encrypt(true).build();
+        context.tr_sess_set_attributes(session, attributes, mask).map_err(tpm_error("cannot set session attributes"))?;
+        let policy = PolicySession::try_from(session).map_err(tpm_error("not a policy session"))?;
+        context.policy_pcr(policy, Digest::default(), selection(HashAlgorithm::Sha256, pcrs)?)
+            .map_err(tpm_error("cannot bind session to PCRs"))?;
+        Ok(policy)
+    }
+
+    /// Seal `secret` so it can only be unsealed while `pcrs` hold their current values.
+    pub fn seal(&self, secret: &[u8], pcrs: &[u32]) -> Result<SealedBlob, ErrorDetail> {
+        let mut context = self.context()?;
+        let trial = Self::pcr_policy(&mut context, pcrs, true)?;
+        let policy = context.policy_get_digest(trial).map_err(tpm_error("cannot compute PCR policy"))?;
+        let _ = context.flush_context(tss_esapi::handles::SessionHandle::from(trial).into());
+        let attributes = ObjectAttributesBuilder::new()
+            .with_fixed_tpm(true)
+            .with_fixed_parent(true)
+            .with_no_da(true)
+            .build()
+            .map_err(tpm_error("cannot build sealed object attributes"))?;
+        let template = PublicBuilder::new()
+            .with_public_algorithm(PublicAlgorithm::KeyedHash)
+            .with_name_hashing_algorithm(HashingAlgorithm::Sha256)
+            .with_object_attributes(attributes)
+            .with_auth_policy(policy)
+            .with_keyed_hash_parameters(PublicKeyedHashParameters::new(KeyedHashScheme::Null))
+            .with_keyed_hash_unique_identifier(Digest::default())
+            .build()
+            .map_err(tpm_error("cannot build sealed object template"))?;
+        let parent = Self::storage_key(&mut context)?;
+        let sensitive = SensitiveData::try_from(secret.to_vec())
+            .map_err(|e| ErrorDetail::invalid("secret is too large to seal").with_source(e))?;
+        let sealed = context.execute_with_nullauth_session(|ctx| {
+            ctx.create(parent, template, None, Some(sensitive), None, None)
+        }).map_err(tpm_error("cannot seal secret"))?;
+        let _ = context.flush_context(parent.into());
+        Ok(SealedBlob {
+            public: hex::encode(sealed.out_public.marshall().map_err(tpm_error("cannot marshal sealed object"))?),
+            private: hex::encode(sealed.out_private.value()),
+            pcrs: pcrs.to_vec(),
+        })
+    }
+
+    /// Unseal `blob`; fails with `ErrorKind::Rejected` when the PCRs have changed.
+    pub fn unseal(&self, blob: &SealedBlob) -> Result<Vec<u8>, ErrorDetail> {
+        let malformed = |what: &str| ErrorDetail::invalid(format!("sealed blob has a malformed {}", what));
+        let public = hex::decode(&blob.public).ok()
+            .and_then(|bytes| Public::unmarshall(&bytes).ok())
+            .ok_or_else(|| malformed("public area"))?;
+        let private = hex::decode(&blob.private).ok()
+            .and_then(|bytes| Private::try_from(bytes).ok())
+            .ok_or_else(|| malformed("private area"))?;
+        let mut context = self.context()?;
+        let parent = Self::storage_key(&mut context)?;
+        let object = context.execute_with_nullauth_session(|ctx| ctx.load(parent, private, public))
+            .map_err(tpm_error("cannot load sealed object"))?;
+        let policy = Self::pcr_policy(&mut context, &blob.pcrs, false)?;
+        let secret = context.execute_with_session(Some(AuthSession::PolicySession(policy)), |ctx| ctx.unseal(object.into()))
+            .map_err(|e| ErrorDetail::rejected(format!("PCRs {:?} no longer match the sealed policy", blob.pcrs)).with_source(e))?;
+        let _ = context.flush_context(object.into());
+        let _ = context.flush_context(parent.into());
+        Ok(secret.value().to_vec())
+    }
+}
+
+impl SealedBlob {
+    pub fn load(path: &Path) -> Result<Self, ErrorDetail> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ErrorDetail::io(format!("cannot read sealed key {}", path.display()), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| ErrorDetail::invalid(format!("malformed sealed key {}", path.display())).with_source(e))
+    }
+}
+
+#[cfg(all(test, feature = "tpm"))]
+mod tests {
+    //! Need swtpm running as in the module docs; `MESH_TPM_TCTI` overrides the TCTI.
+    //! Each test extends its own resettable PCR so they can share one TPM.
+    use super::*;
+    use crate::measure::PcrBank;
+    use sha2::{Digest as _, Sha256};
+
+    fn tpm() -> Tpm {
+        let tcti = std::env::var("MESH_TPM_TCTI").unwrap_or_else(|_| "swtpm:host=localhost,port=2321".to_string());
+        Tpm::open(&TpmConfig { tcti, sealed_key: None, seal_pcrs: Vec::new() }).expect("swtpm is not running")
+    }
+
+    fn extend(tpm: &Tpm, pcr: u32, data: &[u8]) -> [u8; 32] {
+        let digest: [u8; 32] = Sha256::digest(data).into();
+        tpm.extend(pcr, &[(HashAlgorithm::Sha256, &digest)]).unwrap();
+        digest
+    }
+
+    #[test]
+    fn extends_and_quotes_pcrs() {
+        let tpm = tpm();
+        let mut bank = PcrBank::new(HashAlgorithm::Sha256);
+        bank.values = tpm.read(HashAlgorithm::Sha256, &[16]).unwrap();
+        let digest = extend(&tpm, 16, b"stage");
+        bank.extend(16, &digest);
+        assert_eq!(tpm.read(HashAlgorithm::Sha256, &[16]).unwrap()[&16], bank.read(16));
+
+        let quote = tpm.quote(b"nonce-0123456789", HashAlgorithm::Sha256, &[16]).unwrap();
+        let trusted = std::slice::from_ref(&quote.ak_public);
+        assert_eq!(crate::tpm_quote::verify(&quote, trusted, b"nonce-0123456789", std::slice::from_ref(&bank)).unwrap(),
+                   vec![(HashAlgorithm::Sha256, vec![16])]);
+        assert!(crate::tpm_quote::verify(&quote, trusted, b"nonce-9876543210", std::slice::from_ref(&bank)).is_err());
+        bank.extend(16, &digest);
+        assert!(crate::tpm_quote::verify(&quote, trusted, b"nonce-0123456789", &[bank]).is_err());
+    }
+
+    #[test]
+    fn unseal_is_refused_once_a_pcr_changes() {
+        let tpm = tpm();
+        extend(&tpm, 23, b"before sealing");
+        let blob = tpm.seal(b"data key", &[23]).unwrap();
+        assert_eq!(tpm.unseal(&blob).unwrap(), b"data key");
+
+        extend(&tpm, 23, b"unexpected stage");
+        assert_eq!(tpm.unseal(&blob).unwrap_err().kind, ErrorKind::Rejected);
+    }
+}
@@ -0,0 +1,278 @@
+//! TPM2_Quote structures and their verification in plain Rust, so verifiers need
+//! neither a TPM nor the `tpm` feature. Only the ECDSA P-256 attestation key created
+//! by `tpm::Tpm` is understood. Layouts follow TPM 2.0 Part 2: TPMS_ATTEST,
+//! TPMT_SIGNATURE and TPMT_PUBLIC, big-endian with u16-length-prefixed buffers.
+
+use crate::error::ErrorDetail;
+use crate::measure::PcrBank;
+use crate::schema::HashAlgorithm;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// A TPM2_Quote over a PCR selection, marshalled and hex-encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TpmQuote {
+    /// TPMS_ATTEST, including the nonce as `extraData` and the PCR digest.
+    pub attest: String,
+    /// TPMT_SIGNATURE by the attestation key.
+    pub signature: String,
+    /// TPMT_PUBLIC of the attestation key; verifiers list it in `tpm_keys`.
+    pub ak_public: String,
+    pub bank: HashAlgorithm,
+    pub pcrs: Vec<u32>,
+}
+
+const TPM_GENERATED_VALUE: u32 = 0xff54_4347;
+const TPM_ST_ATTEST_QUOTE: u16 = 0x8018;
+const TPM_ALG_SHA256: u16 = 0x000b;
+const TPM_ALG_SHA512: u16 = 0x000d;
+const TPM_ALG_NULL: u16 = 0x0010;
+const TPM_ALG_ECDSA: u16 = 0x0018;
+const TPM_ALG_ECC: u16 = 0x0023;
+const TPM_ECC_NIST_P256: u16 = 0x0003;
+
+/// Big-endian cursor over a marshalled TPM structure.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    what: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], what: &'static str) -> Self {
+        Reader { bytes, what }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ErrorDetail> {
+        if self.bytes.len() < n {
+            return Err(ErrorDetail::rejected(format!("truncated {}", self.what)));
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, ErrorDetail> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ErrorDetail> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().expect("2 bytes")))
+    }
+
+    fn u32(&mut self) -> Result<u32, ErrorDetail> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().expect("4 bytes")))
+    }
+
+    /// A TPM2B: u16 size, then that many bytes.
+    fn sized(&mut self) -> Result<&'a [u8], ErrorDetail> {
+        let n = self.u16()? as usize;
+        self.take(n)
+    }
+}
+
+fn bank_of(alg: u16) -> Option<HashAlgorithm> {
+    match alg {
+        TPM_ALG_SHA256 => Some(HashAlgorithm::Sha256),
+        TPM_ALG_SHA512 => Some(HashAlgorithm::Sha512),
+        _ => None,
+    }
+}
+
+/// The fields of a quote's TPMS_ATTEST that verification needs.
+struct Attested {
+    extra_data: Vec<u8>,
+    /// Selected PCRs per bank, in selection order.
+    selection: Vec<(HashAlgorithm, Vec<u32>)>,
+    pcr_digest: Vec<u8>,
+}
+
+fn parse_attest(bytes: &[u8]) -> Result<Attested, ErrorDetail> {
+    let mut r = Reader::new(bytes, "TPM attestation");
+    if r.u32()? != TPM_GENERATED_VALUE {
+        return Err(ErrorDetail::rejected("TPM attestation was not generated by a TPM"));
+    }
+    if r.u16()? != TPM_ST_ATTEST_QUOTE {
+        return Err(ErrorDetail::rejected("TPM attestation is not a quote"));
+    }
+    r.sized()?; // qualifiedSigner
+    let extra_data = r.sized()?.to_vec();
+    r.take(17 + 8)?; // clockInfo, firmwareVersion
+    let mut selection = Vec::new();
+    for _ in 0..r.u32()? {
+        let alg = r.u16()?;
+        let bank = bank_of(alg)
+            .ok_or_else(|| ErrorDetail::rejected(format!("TPM quote selects unsupported bank 0x{:04x}", alg)))?;
+        let size = r.u8()? as usize;
+        let bitmap = r.take(size)?;
+        let pcrs = (0..size * 8).filter(|i| bitmap[i / 8] & (1 << (i % 8)) != 0).map(|i| i as u32).collect();
+        selection.push((bank, pcrs));
+    }
+    let pcr_digest = r.sized()?.to_vec();
+    Ok(Attested { extra_data, selection, pcr_digest })
+}
+
+/// The ECDSA P-256 key in a TPMT_PUBLIC (a TPM2B_PUBLIC wrapper is also accepted).
+fn parse_ak(bytes: &[u8]) -> Result<p256::ecdsa::VerifyingKey, ErrorDetail> {
+    let mut r = Reader::new(bytes, "TPM attestation key");
+    if bytes.len() > 2 && u16::from_be_bytes([bytes[0], bytes[1]]) as usize == bytes.len() - 2 {
+        r.take(2)?;
+    }
+    if r.u16()? != TPM_ALG_ECC {
+        return Err(ErrorDetail::rejected("TPM attestation key is not an ECC key"));
+    }
+    r.u16()?; // nameAlg
+    r.u32()?; // objectAttributes
+    r.sized()?; // authPolicy
+    if r.u16()? != TPM_ALG_NULL {
+        r.take(4)?; // symmetric keyBits, mode
+    }
+    if r.u16()? != TPM_ALG_NULL {
+        r.u16()?; // scheme hashAlg
+    }
+    if r.u16()? != TPM_ECC_NIST_P256 {
+        return Err(ErrorDetail::rejected("TPM attestation key is not on P-256"));
+    }
+    if r.u16()? != TPM_ALG_NULL {
+        r.u16()?; // kdf hashAlg
+    }
+    let (x, y) = (r.sized()?, r.sized()?);
+    let point = [&[0x04], pad32(x)?.as_slice(), pad32(y)?.as_slice()].concat();
+    p256::ecdsa::VerifyingKey::from_sec1_bytes(&point)
+        .map_err(|_| ErrorDetail::rejected("TPM attestation key is not a valid P-256 point"))
+}
+
+fn parse_signature(bytes: &[u8]) -> Result<p256::ecdsa::Signature, ErrorDetail> {
+    let mut r = Reader::new(bytes, "TPM signature");
+    if r.u16()? != TPM_ALG_ECDSA || r.u16()? != TPM_ALG_SHA256 {
+        return Err(ErrorDetail::rejected("TPM quote is not signed with ECDSA over SHA-256"));
+    }
+    let (s_r, s_s) = (r.sized()?, r.sized()?);
+    p256::ecdsa::Signature::from_scalars(pad32(s_r)?, pad32(s_s)?)
+        .map_err(|_| ErrorDetail::rejected("TPM signature scalars are out of range"))
+}
+
+/// Left-pad an ECC parameter to 32 bytes.
+fn pad32(bytes: &[u8]) -> Result<[u8; 32], ErrorDetail> {
+    let bytes = &bytes[bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len())..];
+    if bytes.len() > 32 {
+        return Err(ErrorDetail::rejected("TPM ECC parameter is longer than 32 bytes"));
+    }
+    let mut out = [0u8; 32];
+    out[32 - bytes.len()..].copy_from_slice(bytes);
+    Ok(out)
+}
+
+/// Accept `quote` only if its attestation key is one of `trusted` (hex TPMT_PUBLIC), the
+/// key signed it, it answers `nonce`, and the PCR digest it signs is that of `replayed`
+/// for the selected PCRs. Returns the selected PCRs per bank. Every refusal is
+/// `ErrorKind::Rejected`.
+pub fn verify(quote: &TpmQuote, trusted: &[String], nonce: &[u8], replayed: &[PcrBank])
+    -> Result<Vec<(HashAlgorithm, Vec<u32>)>, ErrorDetail>
+{
+    use p256::ecdsa::signature::Verifier;
+    let decode = |hex_value: &str, what: &str| hex::decode(hex_value.trim())
+        .map_err(|_| ErrorDetail::rejected(format!("TPM {} is not valid hex", what)));
+    if !trusted.iter().any(|k| k.trim().eq_ignore_ascii_case(quote.ak_public.trim())) {
+        return Err(ErrorDetail::rejected("TPM attestation key is not trusted"));
+    }
+    let ak = parse_ak(&decode(&quote.ak_public, "attestation key")?)?;
+    let attest = decode(&quote.attest, "attestation")?;
+    ak.verify(&attest, &parse_signature(&decode(&quote.signature, "signature")?)?)
+        .map_err(|_| ErrorDetail::rejected("TPM quote signature does not match the attestation key"))?;
+
+    let attested = parse_attest(&attest)?;
+    if attested.extra_data != nonce {
+        return Err(ErrorDetail::rejected("TPM quote does not answer our nonce (replayed?)"));
+    }
+    let mut hasher = Sha256::new();
+    for (bank, pcrs) in &attested.selection {
+        let values = replayed.iter().find(|b| b.algorithm == *bank)
+            .ok_or_else(|| ErrorDetail::rejected(format!("TPM quote covers {} PCRs the event log does not measure", bank)))?;
+        for pcr in pcrs {
+            hasher.update(values.read(*pcr));
+        }
+    }
+    if hasher.finalize()[..] != attested.pcr_digest[..] {
+        return Err(ErrorDetail::rejected("TPM-quoted PCRs differ from the event log; it was rewritten or extended outside this boot"));
+    }
+    Ok(attested.selection)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::measure::{EventType, MeasuredBoot, PCR_FIRMWARE, PCR_POLICY};
+    use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+
+    fn sized(bytes: &[u8]) -> Vec<u8> {
+        [&(bytes.len() as u16).to_be_bytes()[..], bytes].concat()
+    }
+
+    /// A quote as a TPM with attestation key `secret` would produce for `nonce` while
+    /// the SHA-256 `pcrs` hold the values in `bank`.
+    pub(crate) fn synthetic_quote(secret: u8, nonce: &[u8], bank: &PcrBank, pcrs: &[u32]) -> TpmQuote {
+        let key = SigningKey::from_slice(&[secret; 32]).unwrap();
+        let point = key.verifying_key().to_encoded_point(false);
+        let ak_public = [
+            &TPM_ALG_ECC.to_be_bytes()[..], &TPM_ALG_SHA256.to_be_bytes(), &0x0005_0072u32.to_be_bytes(), &sized(&[]),
+            &TPM_ALG_NULL.to_be_bytes(), &TPM_ALG_ECDSA.to_be_bytes(), &TPM_ALG_SHA256.to_be_bytes(),
+            &TPM_ECC_NIST_P256.to_be_bytes(), &TPM_ALG_NULL.to_be_bytes(),
+            &sized(point.x().unwrap()), &sized(point.y().unwrap()),
+        ].concat();
+        let mut bitmap = [0u8; 3];
+        for pcr in pcrs {
+            bitmap[*pcr as usize / 8] |= 1 << (pcr % 8);
+        }
+        let digest = Sha256::digest(pcrs.iter().flat_map(|pcr| bank.read(*pcr)).collect::<Vec<u8>>());
+        let attest = [
+            &TPM_GENERATED_VALUE.to_be_bytes()[..], &TPM_ST_ATTEST_QUOTE.to_be_bytes(), &sized(b"ak-name"), &sized(nonce),
+            &[0u8; 25], &1u32.to_be_bytes(), &TPM_ALG_SHA256.to_be_bytes(), &[3], &bitmap, &sized(&digest),
+        ].concat();
+        let signature: Signature = key.sign(&attest);
+        let (r, s) = signature.split_bytes();
+        let signature = [&TPM_ALG_ECDSA.to_be_bytes()[..], &TPM_ALG_SHA256.to_be_bytes(), &sized(&r), &sized(&s)].concat();
+        TpmQuote {
+            attest: hex::encode(attest),
+            signature: hex::encode(signature),
+            ak_public: hex::encode(ak_public),
+            bank: HashAlgorithm::Sha256,
+            pcrs: pcrs.to_vec(),
+        }
+    }
+
+    fn replayed(firmware: &[u8]) -> Vec<PcrBank> {
+        let measured = MeasuredBoot::new(&[HashAlgorithm::Sha256]);
+        measured.measure(PCR_FIRMWARE, EventType::EvPostCode, "firmware", firmware).unwrap();
+        measured.measure(PCR_POLICY, EventType::EvEventTag, "policy", b"policy").unwrap();
+        measured.log().replay().unwrap()
+    }
+
+    fn rejected(result: Result<Vec<(HashAlgorithm, Vec<u32>)>, ErrorDetail>, needle: &str) {
+        let err = result.unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Rejected);
+        assert!(err.message.contains(needle), "`{}` does not mention `{}`", err.message, needle);
+    }
+
+    #[test]
+    fn accepts_a_quote_over_the_replayed_pcrs() {
+        let banks = replayed(b"firmware");
+        let quote = synthetic_quote(3, b"nonce-0123456789", &banks[0], &[PCR_FIRMWARE, PCR_POLICY]);
+        let selection = verify(&quote, std::slice::from_ref(&quote.ak_public), b"nonce-0123456789", &banks).unwrap();
+        assert_eq!(selection, vec![(HashAlgorithm::Sha256, vec![PCR_FIRMWARE, PCR_POLICY])]);
+    }
+
+    #[test]
+    fn rejects_untrusted_keys_stale_nonces_and_rewritten_logs() {
+        let banks = replayed(b"firmware");
+        let quote = synthetic_quote(3, b"nonce-0123456789", &banks[0], &[PCR_FIRMWARE, PCR_POLICY]);
+        let trusted = std::slice::from_ref(&quote.ak_public);
+        let other = synthetic_quote(4, b"nonce-0123456789", &banks[0], &[PCR_FIRMWARE]);
+        rejected(verify(&quote, std::slice::from_ref(&other.ak_public), b"nonce-0123456789", &banks), "not trusted");
+        rejected(verify(&quote, trusted, b"nonce-9876543210", &banks), "nonce");
+        rejected(verify(&quote, trusted, b"nonce-0123456789", &replayed(b"forged firmware")), "differ from the event log");
+
+        let mut forged = quote.clone();
+        forged.signature = other.signature.clone();
+        rejected(verify(&forged, trusted, b"nonce-0123456789", &banks), "signature does not match");
+    }
+}
@@ -0,0 +1,550 @@
+use crate::config::Format;
+use crate::schema::{
+    AIModelDescriptor, AttestationConfig, BootConfig, ComplianceConfig, EnforcementLayer, FileSystemConfig, FirmwareConfig, KeyAlgorithm,
+    MeasuredBootConfig, SignaturePolicy, TrustedKey,
+};
+use crate::measure::PCR_COUNT;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// Security levels an `AIModelDescriptor` may declare.
+pub const SECURITY_LEVELS: std::ops::RangeInclusive<u8> = 1..=5;
+
+/// Data ciphers `fs::mount` knows how to set up.
+pub const SUPPORTED_CIPHERS: &[&str] = &["AES-256-GCM", "CHACHA20-POLY1305"];
+
+/// Key-encapsulation mechanisms `fs::mount` knows how to set up, and whether each is post-quantum.
+pub const SUPPORTED_KEMS: &[(&str, bool)] = &[
+    ("X25519", false),
+    ("ML-KEM-768", true),
+    ("ML-KEM-1024", true),
+    ("X25519-ML-KEM-768", true),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Dotted field path, e.g. `ai_models[0].security_level`.
+    pub path: String,
+    pub severity: Severity,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+/// Every violation found in a config, in field order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub violations: Vec<Violation>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        !self.has_errors()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Violation> {
+        self.violations.iter().filter(|v| v.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Violation> {
+        self.violations.iter().filter(|v| v.severity == Severity::Warning)
+    }
+
+    fn push(&mut self, severity: Severity, path: impl Into<String>, message: impl Into<String>, suggestion: Option<String>) {
+        self.violations.push(Violation { path: path.into(), severity, message: message.into(), suggestion });
+    }
+
+    fn error(&mut self, path: impl Into<String>, message: impl Into<String>, suggestion: Option<String>) {
+        self.push(Severity::Error, path, message, suggestion);
+    }
+
+    fn warn(&mut self, path: impl Into<String>, message: impl Into<String>, suggestion: Option<String>) {
+        self.push(Severity::Warning, path, message, suggestion);
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.path, self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (hint: {})", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for v in &self.violations {
+            writeln!(f, "{}", v)?;
+        }
+        let errors = self.errors().count();
+        write!(f, "{} error(s), {} warning(s)", errors, self.violations.len() - errors)
+    }
+}
+
+impl BootConfig {
+    /// Check semantic constraints the deserializer cannot express, collecting every violation.
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        check_enforcement(&self.enforcement, &mut report);
+        check_models(&self.ai_models, &mut report);
+        check_filesystem(&self.filesystem, &mut report);
+        check_compliance(&self.compliance, &mut report);
+        if let Some(policy) = &self.policy {
+            if Format::from_path(Path::new(&policy.path)).is_none() {
+                report.error("policy.path", format!("`{}` is neither .toml nor .json", policy.path), None);
+            }
+            if policy.reload_interval_secs == Some(0) {
+                report.error("policy.reload_interval_secs", "interval must be at least 1 second", None);
+            }
+            let reachable = self.filesystem.read_only.iter().chain(&self.filesystem.writable_paths())
+                .any(|dir| within(&policy.path, dir));
+            if policy.reload_interval_secs.is_some() && self.enforcement.sandbox_filesystem && !reachable {
+                report.warn("policy.path", format!("`{}` is outside the filesystem sandbox; reloads will fail", policy.path),
+                            Some("reloading starts after enforcement; list its directory in `filesystem.read_only`".to_string()));
+            }
+        }
+        match &self.firmware {
+            Some(firmware) => check_firmware(firmware, self.filesystem.encryption.signature_policy(), &mut report),
+            None => report.warn("firmware", "no firmware image is configured; signatures will not be checked",
+                                Some("add a [firmware] section with `image`, `signature` and `trusted_keys`".to_string())),
+        }
+        if let Some(measured) = &self.measured_boot {
+            check_measured_boot(measured, &self.filesystem, &mut report);
+        }
+        if let Some(attestation) = &self.attestation {
+            check_attestation(attestation, self.measured_boot.as_ref(), &mut report);
+        }
+        report
+    }
+}
+
+fn check_measured_boot(cfg: &MeasuredBootConfig, fs: &FileSystemConfig, report: &mut ValidationReport) {
+    if cfg.banks.is_empty() {
+        report.error("measured_boot.banks", "at least one PCR bank is required", Some("use `[\"sha256\"]`".to_string()));
+    }
+    let signed = cfg.golden_file.is_some() || cfg.kernel_fingerprint.as_ref().is_some_and(|k| k.manifest.is_some());
+    if signed || !cfg.trusted_keys.is_empty() {
+        check_trusted_keys("measured_boot", &cfg.trusted_keys, &cfg.revoked_keys, fs.encryption.signature_policy(), report);
+    }
+    if signed && cfg.trusted_keys.is_empty() {
+        report.error("measured_boot.trusted_keys", "golden files and kernel manifests must be signed; no keys to check them with",
+                     Some("sign them with `measure sign KEY FILE` and list the key's [[trusted_keys]] entry here".to_string()));
+    }
+    if !cfg.golden.is_empty() {
+        report.warn("measured_boot.golden", "inline golden values are only as trustworthy as this config file",
+                    Some("move them to a signed `golden_file`".to_string()));
+    }
+    let mut seen = HashSet::new();
+    for (i, golden) in cfg.golden.iter().enumerate() {
+        let path = format!("measured_boot.golden[{}]", i);
+        if golden.pcr >= PCR_COUNT {
+            report.error(format!("{}.pcr", path), format!("PCR {} does not exist; there are {}", golden.pcr, PCR_COUNT), None);
+        }
+        if !cfg.banks.contains(&golden.bank) {
+            report.error(format!("{}.bank", path), format!("{} is not one of the measured banks", golden.bank), None);
+        }
+        if hex::decode(golden.value.trim()).map_or(true, |v| v.len() != golden.bank.digest_len()) {
+            report.error(format!("{}.value", path),
+                         format!("expected {} hex characters for {}", golden.bank.digest_len() * 2, golden.bank), None);
+        }
+        if !seen.insert((golden.pcr, golden.bank)) {
+            report.error(path, format!("duplicate golden value for {} PCR[{}]", golden.bank, golden.pcr), None);
+        }
+    }
+    if let Some(kernel) = &cfg.kernel_fingerprint {
+        if !Path::new(&kernel.root).is_absolute() {
+            report.error("measured_boot.kernel_fingerprint.root", format!("`{}` is not an absolute path", kernel.root), None);
+        }
+        if let Some(output) = kernel.output.as_ref().filter(|o| !fs.writable_paths().iter().any(|dir| within(o, dir))) {
+            report.warn("measured_boot.kernel_fingerprint.output", format!("`{}` is outside the writable paths", output),
+                        Some("the filesystem sandbox will refuse to write it; place it under `mount_at`".to_string()));
+        }
+        if kernel.expected.is_none() && kernel.manifest.is_none() {
+            report.warn("measured_boot.kernel_fingerprint", "neither `expected` nor `manifest` is set; the fingerprint is only recorded",
+                        Some("record a baseline with `kernel_fingerprint --manifest PATH`".to_string()));
+        }
+        if let Some(expected) = &kernel.expected {
+            if hex::decode(expected.trim()).map_or(true, |v| v.len() != kernel.algorithm.digest_len()) {
+                report.error("measured_boot.kernel_fingerprint.expected",
+                             format!("expected {} hex characters for {}", kernel.algorithm.digest_len() * 2, kernel.algorithm), None);
+            }
+        }
+    }
+    if cfg.watch_interval_secs == Some(0) {
+        report.error("measured_boot.watch_interval_secs", "interval must be at least 1 second", None);
+    }
+    if let Some(tpm) = &cfg.tpm {
+        if !cfg!(feature = "tpm") {
+            report.warn("measured_boot.tpm", "built without the `tpm` feature; only software PCRs are extended",
+                        Some("rebuild with `--features tpm`".to_string()));
+        }
+        for (i, pcr) in tpm.seal_pcrs.iter().enumerate() {
+            if *pcr >= PCR_COUNT {
+                report.error(format!("measured_boot.tpm.seal_pcrs[{}]", i), format!("PCR {} does not exist", pcr), None);
+            }
+        }
+    }
+    if let Some(log) = &cfg.event_log {
+        if !fs.writable_paths().iter().any(|dir| within(log, dir)) {
+            report.warn("measured_boot.event_log", format!("`{}` is outside the writable paths", log),
+                        Some("the filesystem sandbox will refuse to write it; place it under `mount_at`".to_string()));
+        }
+    }
+}
+
+fn check_attestation(cfg: &AttestationConfig, measured: Option<&MeasuredBootConfig>, report: &mut ValidationReport) {
+    if measured.and_then(|m| m.event_log.as_ref()).is_none() {
+        report.error("attestation", "quotes are built from the measured boot event log",
+                     Some("set `measured_boot.event_log`".to_string()));
+    }
+    if cfg.signing_keys.is_empty() {
+        report.error("attestation.signing_keys", "at least one node signing key is required",
+                     Some("create one with `attest keygen ed25519 <node-id> <path>`".to_string()));
+    }
+    if cfg.listen.parse::<std::net::SocketAddr>().is_err() {
+        report.error("attestation.listen", format!("`{}` is not an IP:port address", cfg.listen), None);
+    }
+    if !cfg!(feature = "tpm") || measured.and_then(|m| m.tpm.as_ref()).is_none() {
+        report.warn("attestation", "quotes are software-only: the node key vouches for its own, rewritable event log",
+                    Some("build with `--features tpm` and configure `measured_boot.tpm` to add a TPM quote".to_string()));
+    }
+}
+
+fn check_enforcement(layer: &EnforcementLayer, report: &mut ValidationReport) {
+    if !layer.restrict_shell && !layer.lock_resources && !layer.harden_kernel && !layer.sandbox_filesystem {
+        report.warn("enforcement", "every enforcement control is disabled",
+                    Some("enable at least `lock_resources` outside developer machines".to_string()));
+    }
+    let limits = [
+        ("max_memory_mb", layer.limits.max_memory_mb),
+        ("max_open_files", layer.limits.max_open_files),
+        ("max_processes", layer.limits.max_processes),
+    ];
+    for (name, value) in limits {
+        if value == Some(0) {
+            report.error(format!("enforcement.limits.{}", name), "a limit of 0 would stop the boot itself",
+                         Some("remove the field to keep the inherited limit".to_string()));
+        }
+    }
+    if layer.exec_allowlist.is_empty() {
+        report.warn("enforcement.exec_allowlist", "no binary may be executed once the shell is restricted",
+                    Some("list at least the recovery shell, e.g. `/bin`".to_string()));
+    }
+    for (i, path) in layer.exec_allowlist.iter().enumerate() {
+        if !Path::new(path).is_absolute() {
+            report.error(format!("enforcement.exec_allowlist[{}]", i), format!("`{}` is not an absolute path", path), None);
+        }
+    }
+    for (i, name) in layer.retain_capabilities.iter().enumerate() {
+        if !name.starts_with("CAP_") || !name[4..].chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') {
+            report.error(format!("enforcement.retain_capabilities[{}]", i), format!("`{}` is not a capability name", name),
+                         Some("use the kernel spelling, e.g. `CAP_NET_BIND_SERVICE`".to_string()));
+        }
+    }
+    if layer.kernel_audit.min_score.is_some_and(|min| min > 100) {
+        report.error("enforcement.kernel_audit.min_score", "audit scores range from 0 to 100", None);
+    }
+    if layer.kernel_audit.apply_irreversible && !layer.kernel_audit.apply {
+        report.warn("enforcement.kernel_audit.apply_irreversible", "has no effect without `apply`", None);
+    }
+    if !layer.harden_kernel && (layer.kernel_audit.apply || layer.kernel_audit.min_score.is_some()) {
+        report.warn("enforcement.kernel_audit", "the kernel audit only runs with `harden_kernel`", None);
+    }
+}
+
+fn check_models(models: &[AIModelDescriptor], report: &mut ValidationReport) {
+    if models.is_empty() {
+        report.warn("ai_models", "no AI models are configured", None);
+    }
+    let mut seen = HashSet::new();
+    for (i, model) in models.iter().enumerate() {
+        let path = format!("ai_models[{}]", i);
+        if model.name.trim().is_empty() {
+            report.error(format!("{}.name", path), "model name is empty", None);
+        } else if !seen.insert(model.name.as_str()) {
+            report.error(format!("{}.name", path), format!("duplicate model name `{}`", model.name),
+                         Some("give each model a unique name".to_string()));
+        }
+        if model.version.trim().is_empty() {
+            report.error(format!("{}.version", path), "model version is empty",
+                         Some("pin an explicit version such as `v1.0.0`".to_string()));
+        }
+        if !SECURITY_LEVELS.contains(&model.security_level) {
+            report.error(format!("{}.security_level", path),
+                         format!("security level {} is outside {}..={}", model.security_level,
+                                 SECURITY_LEVELS.start(), SECURITY_LEVELS.end()),
+                         Some(format!("use a value between {} and {}", SECURITY_LEVELS.start(), SECURITY_LEVELS.end())));
+        } else if model.security_level >= 4 && !model.isolated {
+            report.warn(format!("{}.isolated", path),
+                        format!("security level {} model runs shared", model.security_level),
+                        Some("set `isolated = true` for level 4 and 5 models".to_string()));
+        }
+    }
+}
+
+fn check_filesystem(fs: &FileSystemConfig, report: &mut ValidationReport) {
+    let mount = Path::new(&fs.mount_at);
+    if !mount.is_absolute() {
+        report.error("filesystem.mount_at", format!("`{}` is not an absolute path", fs.mount_at),
+                     Some(format!("use `/{}`", fs.mount_at.trim_start_matches("./"))));
+    } else if mount.parent().is_none() {
+        report.error("filesystem.mount_at", "refusing to mount over `/`", None);
+    }
+    if mount.components().any(|c| c == Component::ParentDir) {
+        report.error("filesystem.mount_at", format!("`{}` contains `..`", fs.mount_at),
+                     Some("spell out the canonical path".to_string()));
+    }
+
+    for (field, paths) in [("read_only", &fs.read_only), ("read_write", &fs.read_write)] {
+        for (i, path) in paths.iter().enumerate() {
+            if !Path::new(path).is_absolute() {
+                report.error(format!("filesystem.{}[{}]", field, i), format!("`{}` is not an absolute path", path), None);
+            } else if Path::new(path).components().any(|c| c == Component::ParentDir) {
+                report.error(format!("filesystem.{}[{}]", field, i), format!("`{}` contains `..`", path),
+                             Some("spell out the canonical path".to_string()));
+            } else if Path::new(path).parent().is_none() {
+                report.warn(format!("filesystem.{}[{}]", field, i), "`/` makes the filesystem sandbox moot",
+                            Some("list the directories the node actually needs".to_string()));
+            }
+        }
+    }
+
+    let encryption = &fs.encryption;
+    if !SUPPORTED_CIPHERS.contains(&encryption.cipher.as_str()) {
+        report.error("filesystem.encryption.cipher", format!("unsupported cipher `{}`", encryption.cipher),
+                     Some(suggest(&encryption.cipher, SUPPORTED_CIPHERS.iter().copied())));
+    }
+    match SUPPORTED_KEMS.iter().find(|(name, _)| *name == encryption.kem) {
+        Some((_, post_quantum)) => {
+            if encryption.quantum_resistant && !post_quantum {
+                report.error("filesystem.encryption.kem",
+                             format!("`{}` is not quantum resistant but `quantum_resistant` is set", encryption.kem),
+                             Some("use `ML-KEM-768` or the hybrid `X25519-ML-KEM-768`".to_string()));
+            } else if !encryption.quantum_resistant && *post_quantum {
+                report.warn("filesystem.encryption.quantum_resistant",
+                            format!("`{}` is post-quantum but `quantum_resistant` is false", encryption.kem),
+                            Some("set `quantum_resistant = true`".to_string()));
+            }
+        }
+        None => report.error("filesystem.encryption.kem", format!("unsupported KEM `{}`", encryption.kem),
+                             Some(suggest(&encryption.kem, SUPPORTED_KEMS.iter().map(|(name, _)| *name)))),
+    }
+    if encryption.quantum_resistant && encryption.signature_policy() == SignaturePolicy::Classical {
+        report.warn("filesystem.encryption.signature_policy",
+                    "`quantum_resistant` is set but signed artifacts only need a classical signature",
+                    Some("use `signature_policy = \"both\"`".to_string()));
+    }
+}
+
+/// Whether `path` lies under the absolute directory `dir` once `.` and `..` are
+/// resolved in both; a relative path never does. Lexical, like the sandbox rules.
+fn within(path: &str, dir: &str) -> bool {
+    fn normalize(path: &str) -> Option<PathBuf> {
+        let path = Path::new(path);
+        if !path.is_absolute() {
+            return None;
+        }
+        let mut normal = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::ParentDir => {
+                    normal.pop();
+                }
+                Component::CurDir => {}
+                other => normal.push(other),
+            }
+        }
+        Some(normal)
+    }
+    matches!((normalize(path), normalize(dir)), (Some(path), Some(dir)) if path.starts_with(&dir))
+}
+
+/// A case-insensitive match from `known`, or the full list when there is none.
+fn suggest<'a>(given: &str, known: impl Iterator<Item = &'a str> + Clone) -> String {
+    known.clone()
+        .find(|name| name.eq_ignore_ascii_case(given))
+        .map(|name| format!("did you mean `{}`?", name))
+        .unwrap_or_else(|| format!("supported: {}", known.collect::<Vec<_>>().join(", ")))
+}
+
+fn check_compliance(cfg: &ComplianceConfig, report: &mut ValidationReport) {
+    if (cfg.gdpr || cfg.ccpa) && !cfg.audit_log {
+        report.warn("compliance.audit_log", "GDPR/CCPA compliance is enabled without an audit log",
+                    Some("set `audit_log = true`".to_string()));
+    }
+}
+
+fn check_firmware(cfg: &FirmwareConfig, policy: SignaturePolicy, report: &mut ValidationReport) {
+    if cfg.trusted_keys.is_empty() {
+        report.error("firmware.trusted_keys", "no trusted keys; every image would be rejected", None);
+    }
+    check_trusted_keys("firmware", &cfg.trusted_keys, &cfg.revoked_keys, policy, report);
+    for (i, digest) in cfg.revoked_images.iter().enumerate() {
+        if digest.len() != 64 || hex::decode(digest).is_err() {
+            report.error(format!("firmware.revoked_images[{}]", i), "not a hex SHA-256 digest", None);
+        }
+    }
+}
+
+/// Key encodings, validity windows and signature families of `<section>.trusted_keys`.
+fn check_trusted_keys(section: &str, keys: &[TrustedKey], revoked: &[String], policy: SignaturePolicy,
+                      report: &mut ValidationReport) {
+    let mut seen = HashSet::new();
+    for (i, key) in keys.iter().enumerate() {
+        let path = format!("{}.trusted_keys[{}]", section, i);
+        if !seen.insert(key.id.as_str()) {
+            report.error(format!("{}.id", path), format!("duplicate key id `{}`", key.id), None);
+        }
+        let expected = match key.algorithm {
+            KeyAlgorithm::Ed25519 => &[32][..],
+            KeyAlgorithm::EcdsaP256 => &[33, 65][..],
+            KeyAlgorithm::MlDsa65 => &[1952][..],
+        };
+        match hex::decode(key.public_key.trim()) {
+            Ok(bytes) if expected.contains(&bytes.len()) => {}
+            Ok(bytes) => report.error(format!("{}.public_key", path),
+                                      format!("{} key is {} bytes", key.algorithm, bytes.len()),
+                                      Some(format!("expected {:?} bytes", expected))),
+            Err(_) => report.error(format!("{}.public_key", path), "public key is not valid hex", None),
+        }
+        if let (Some(from), Some(until)) = (key.not_before, key.not_after) {
+            if from >= until {
+                report.error(format!("{}.not_after", path), "validity window is empty", None);
+            }
+        }
+        if revoked.contains(&key.id) {
+            report.warn(format!("{}.id", path), format!("key `{}` is both trusted and revoked", key.id),
+                        Some("drop it from `trusted_keys`".to_string()));
+        }
+    }
+    for post_quantum in [false, true] {
+        let family = if post_quantum { "post-quantum" } else { "classical" };
+        if policy.requires(post_quantum) && !keys.iter().any(|k| k.algorithm.is_post_quantum() == post_quantum) {
+            report.error(format!("{}.trusted_keys", section), format!("{} signature policy needs a trusted {} key", policy, family),
+                         Some(if post_quantum { "add an `ml-dsa-65` key" } else { "add an `ed25519` or `ecdsa-p256` key" }.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::BootProfile;
+
+    fn warned(report: &ValidationReport, path: &str) -> bool {
+        report.warnings().any(|v| v.path == path)
+    }
+
+    #[test]
+    fn containment_resolves_dot_and_dot_dot() {
+        assert!(within("/secure_data/logs/events.json", "/secure_data"));
+        assert!(within("/secure_data/./logs/../events.json", "/secure_data/"));
+        assert!(within("/etc/../secure_data/x", "/secure_data/logs/.."));
+        assert!(!within("/secure_data/../etc/events.json", "/secure_data"));
+        assert!(!within("/secure_data/../../../etc/shadow", "/secure_data"));
+        assert!(!within("/secure_database/x", "/secure_data"));
+        assert!(!within("secure_data/x", "/"));
+    }
+
+    #[test]
+    fn outputs_escaping_the_writable_paths_are_flagged() {
+        let measured = |event_log: &str| {
+            let measured_boot = Some(MeasuredBootConfig {
+                banks: vec![crate::schema::HashAlgorithm::Sha256],
+                event_log: Some(event_log.to_string()),
+                golden: Vec::new(),
+                golden_file: None,
+                trusted_keys: Vec::new(),
+                revoked_keys: Vec::new(),
+                watch_interval_secs: None,
+                tpm: None,
+                kernel_fingerprint: None,
+            });
+            BootConfig { measured_boot, ..BootConfig::default() }.validate()
+        };
+        assert!(!warned(&measured("/secure_data/boot-events.json"), "measured_boot.event_log"));
+        assert!(!warned(&measured("/secure_data/logs/../boot-events.json"), "measured_boot.event_log"));
+        assert!(warned(&measured("/secure_data/../etc/boot-events.json"), "measured_boot.event_log"));
+
+        let policy = Some(crate::schema::PolicyConfig {
+            path: "/etc/mesh/../../root/policy.toml".to_string(),
+            reload_interval_secs: Some(30),
+        });
+        let mut config = BootConfig { policy, ..BootConfig::default() };
+        assert!(warned(&config.validate(), "policy.path"));
+        config.policy.as_mut().unwrap().path = "/root/../etc/mesh/policy.toml".to_string();
+        assert!(!warned(&config.validate(), "policy.path"));
+    }
+
+    #[test]
+    fn sandbox_entries_must_be_absolute_and_canonical() {
+        let mut config = BootConfig::default();
+        config.filesystem.read_only = vec!["/".to_string(), "usr/lib".to_string()];
+        config.filesystem.read_write = vec!["/secure_data/../etc".to_string()];
+        let report = config.validate();
+        let flagged: Vec<_> = report.violations.iter()
+            .filter(|v| v.path.starts_with("filesystem.read_"))
+            .map(|v| (v.path.as_str(), v.severity))
+            .collect();
+        assert_eq!(flagged, [("filesystem.read_only[0]", Severity::Warning), ("filesystem.read_only[1]", Severity::Error),
+                             ("filesystem.read_write[0]", Severity::Error)]);
+    }
+
+    #[test]
+    fn the_filesystem_sandbox_counts_as_an_enforcement_control() {
+        let mut config = BootConfig::default();
+        config.enforcement.restrict_shell = false;
+        config.enforcement.lock_resources = false;
+        config.enforcement.harden_kernel = false;
+        assert!(!warned(&config.validate(), "enforcement"));
+        config.enforcement.sandbox_filesystem = false;
+        assert!(warned(&config.validate(), "enforcement"));
+    }
+
+    #[test]
+    fn boots_validate_the_config_their_profile_produces() {
+        let warnings = |profile, lock_resources| {
+            let mut config = BootConfig { profile, ..BootConfig::default() };
+            config.enforcement.lock_resources = lock_resources;
+            crate::boot::dry_run(config).unwrap().config_warnings
+        };
+        let disabled = |warnings: &[String]| warnings.iter().any(|w| w.contains("every enforcement control is disabled"));
+        // Developer turns the shell, kernel and sandbox controls off, leaving nothing.
+        assert!(disabled(&warnings(BootProfile::Developer, false)), "{:?}", warnings(BootProfile::Developer, false));
+        assert!(!disabled(&warnings(BootProfile::Developer, true)));
+        // Production turns them all back on, whatever the file says.
+        let mut config = BootConfig::default();
+        config.enforcement = EnforcementLayer {
+            restrict_shell: false,
+            lock_resources: false,
+            harden_kernel: false,
+            sandbox_filesystem: false,
+            ..config.enforcement
+        };
+        assert!(warned(&config.validate(), "enforcement"));
+        let warnings = crate::boot::dry_run(config).unwrap().config_warnings;
+        assert!(!disabled(&warnings), "{:?}", warnings);
+        assert!(warnings.iter().any(|w| w == "production profile sets enforcement.sandbox_filesystem = true"), "{:?}", warnings);
+    }
+}